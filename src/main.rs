@@ -1,8 +1,22 @@
 use crate::structures::settings::Settings;
 use crate::utils::process_backup::start_backup_process;
+use crate::utils::process_cat::cat_process;
+use crate::utils::process_daemon::daemon_process;
+use crate::utils::process_gc::gc_process;
+use crate::utils::process_import::import_process;
+use crate::utils::process_list::list_process;
+use crate::utils::process_migrate::migrate_process;
+use crate::utils::process_prune::prune_process;
 use crate::utils::process_restore::{restore_all_process, restore_selected_process};
+use crate::utils::process_share::share_process;
+use crate::utils::process_diff::diff_process;
+use crate::utils::process_inspect::inspect_process;
+use crate::utils::process_update::{check_for_update, self_update_process};
+use crate::utils::process_verify::verify_process;
+use crate::utils::time_utils::set_timezone;
 use log::{error, LevelFilter};
 use std::env;
+use std::path::Path;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
 use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
@@ -25,11 +39,31 @@ mod utils;
 /// - `"backup"`: Starts the backup process using the provided settings and S3 bucket configuration.
 /// - `"restore"`: Initiates the restore process. If no additional arguments are provided, it restores all backups.
 ///   If a backup file is specified, it restores the selected backup.
+///   With `--paths`, only the listed paths are restored from folder backups. `--key`, `--before` or `--at` restore
+///   an older backup instead of the latest one.
+/// - `"list"`: Prints the available backups of all or the specified elements.
+/// - `"share"`: Prints a presigned download URL for a backup of the specified element.
+/// - `"cat"`: Streams a backup of the specified element to the standard output.
+/// - `"import"`: Imports pre-existing backups from an S3 prefix or a local directory into an element.
+/// - `"migrate"`: Copies the backups of an element to another bucket, prefix or endpoint.
+/// - `"prune"`: Deletes the backups that are not kept by the retention policies (`--dry-run` only prints them).
+/// - `"gc"`: Lists (and with `--prefix` or `--all`, optionally deletes) S3 objects that do not belong to any
+///   configured element.
+/// - `"verify"`: Verifies the checksums of the backups of the specified element.
+/// - `"diff"`: Compares a folder element with its latest backup.
+/// - `"inspect"`: Lists the files or tables inside a backup of the specified element.
+/// - `"daemon"`: Keeps running and backs up each element according to its `schedule` (cron expression).
+/// - `"self-update"`: Replaces the executable with the latest release from GitHub (`--check` only prints versions).
+/// - `"version"`: Prints the version of reback.
+///
+/// The `self-update` and `version` commands do not need the settings file. Before a backup, reback checks whether a
+/// newer release is available (at most once a day) and logs a notice, unless `disable_version_check` is set.
 ///
 /// # Behavior
 /// - Initializes logging with `env_logger::init()`.
 /// - Reads and validates command-line arguments.
 /// - Loads settings from a configuration file using `Settings::from_file()`.
+/// - Applies the configured timezone using `set_timezone()`.
 /// - Creates an S3 bucket instance using `Settings::get_bucket()`.
 /// - Based on the command-line argument, either initiates the backup process or restores the data from the S3 bucket.
 ///
@@ -104,6 +138,18 @@ async fn main() {
         return;
     }
 
+    match args[1].as_str() {
+        "self-update" => {
+            self_update_process(&args).await;
+            return;
+        }
+        "version" => {
+            println!("reback {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        _ => {}
+    }
+
     let settings = match Settings::from_file() {
         Ok(s) => s,
         Err(err) => {
@@ -112,6 +158,13 @@ async fn main() {
         }
     };
 
+    if let Some(timezone) = &settings.timezone {
+        if let Err(err) = set_timezone(timezone) {
+            error!("Failed to initialize settings: {}", err);
+            return;
+        }
+    }
+
     let bucket = match settings.get_bucket() {
         Some(bucket) => bucket,
         None => {
@@ -122,6 +175,9 @@ async fn main() {
 
     match args[1].as_str() {
         "backup" => {
+            if !settings.disable_version_check {
+                check_for_update(Path::new(&settings.backup_dir)).await;
+            }
             start_backup_process(&settings, &bucket).await;
         }
         "restore" => {
@@ -131,6 +187,42 @@ async fn main() {
                 restore_all_process(&settings, &bucket).await;
             }
         }
+        "list" => {
+            list_process(&settings, &bucket, &args).await;
+        }
+        "share" => {
+            share_process(&settings, &bucket, &args).await;
+        }
+        "cat" => {
+            cat_process(&settings, &bucket, &args).await;
+        }
+        "import" => {
+            import_process(&settings, &bucket, &args).await;
+        }
+        "migrate" => {
+            migrate_process(&settings, &bucket, &args).await;
+        }
+        "prune" => {
+            prune_process(&settings, &bucket, &args).await;
+        }
+        "gc" => {
+            gc_process(&settings, &bucket, &args).await;
+        }
+        "verify" => {
+            verify_process(&settings, &bucket, &args).await;
+        }
+        "diff" => {
+            diff_process(&settings, &bucket, &args).await;
+        }
+        "inspect" => {
+            inspect_process(&settings, &bucket, &args).await;
+        }
+        "daemon" => {
+            if !settings.disable_version_check {
+                check_for_update(Path::new(&settings.backup_dir)).await;
+            }
+            daemon_process(settings, bucket).await;
+        }
         _ => {
             error!("Unknown argument provided. Exiting.");
             return;
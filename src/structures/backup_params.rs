@@ -1,20 +1,40 @@
+use crate::structures::settings::S3PathStyle;
 use serde::Deserialize;
 
 /// Enum representing the different types of backup parameters.
 ///
 /// This enum defines the configuration for various backup types, including:
-/// PostgreSQL (both normal and Docker-based), MongoDB (normal and Docker-based), and Folder backups.
+/// PostgreSQL (both normal and Docker-based), MongoDB (normal and Docker-based), MySQL, Redis, SQLite and Folder
+/// backups.
 /// Each variant contains the relevant configuration for connecting to the database or specifying the folder
 /// to be backed up.
 ///
 /// # Variants
 /// - `Postgresql` - Represents a PostgreSQL backup, with details about the database host, port, name,
-///   user, and password.
+///   user, and password. With `client_container`, the client tools run from the official `postgres` image
+///   matching the server version.
 /// - `PostgresqlDocker` - Represents a PostgreSQL backup from a Docker container, with details about the
 ///   Docker container, database name, user, and password.
 /// - `Mongodb` - Represents a MongoDB backup, with details about the host, port, and optional user/password.
 /// - `MongodbDocker` - Represents a MongoDB backup from a Docker container, with optional user/password.
-/// - `Folder` - Represents a folder backup, with a path to the folder to back up.
+/// - `Folder` - Represents a folder backup, with a path to the folder to back up and the archive format.
+///   How ownership, permissions and timestamps are restored is configured with `FolderRestoreOptions`.
+///   With `local_snapshots`, hardlinked rsync snapshots of the folder are kept locally, and the archive is created
+///   from the newest snapshot. With `incremental`, tar archives only contain the changes since the previous backup
+///   (see `incremental_utils`), and a new full backup is started every `full_backup_interval_days`.
+/// - `MySQL` - Represents a MySQL backup, with details about the database host, port, name, user, and password.
+///   With `client_container`, the client tools run from the official `mysql` or `mariadb` image matching the
+///   server version. The `mysqldump` flags are configured with `MySQLDumpOptions`.
+/// - `MySQLDocker` - Represents a MySQL backup from a Docker container, with the same `MySQLDumpOptions`.
+/// - `Postgresql`, `PostgresqlDocker`, `MySQL` and `MySQLDocker` support `all_databases: auto`, which backs up every
+///   database of the server as a separate artifact instead of `db_name` (see `Elements::expand_databases`).
+/// - `Redis` - Represents a Redis backup, with details about the host, port and optional password. The RDB snapshot
+///   is fetched with `redis-cli --rdb`. Restores copy the snapshot to `rdb_path`.
+/// - `RedisDocker` - Represents a Redis backup from a Docker container. Restores stop the container, copy the
+///   snapshot to `rdb_path` inside the container (default `/data/dump.rdb`) and start it again.
+/// - `Sqlite` - Represents a backup of an SQLite database file, created and restored with the online backup API of
+///   the `sqlite3` shell (`.backup` and `.restore`), so the database stays consistent while it is in use.
+/// - `S3Sync` - Represents a mirror of a prefix from another S3-compatible bucket into the backup bucket.
 ///
 /// # Example
 /// ```rust
@@ -24,23 +44,32 @@ use serde::Deserialize;
 ///     db_name: "my_db".to_string(),
 ///     db_user: "user".to_string(),
 ///     db_password: "password".to_string(),
+///     client_container: false,
 /// };
 /// ```
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum BackupParams {
     Postgresql {
         db_host: Option<String>,
         db_port: u16,
+        #[serde(default)]
         db_name: String,
         db_user: String,
         db_password: String,
+        #[serde(default)]
+        client_container: bool,
+        #[serde(default)]
+        all_databases: Option<DatabaseDiscovery>,
     },
     PostgresqlDocker {
         docker_container: String,
+        #[serde(default)]
         db_name: String,
         db_user: String,
         db_password: String,
+        #[serde(default)]
+        all_databases: Option<DatabaseDiscovery>,
     },
     Mongodb {
         db_host: Option<String>,
@@ -55,18 +84,240 @@ pub enum BackupParams {
     },
     Folder {
         target_path: String,
+        #[serde(default)]
+        archive_format: ArchiveFormat,
+        #[serde(default)]
+        local_snapshots: bool,
+        #[serde(default)]
+        incremental: bool,
+        full_backup_interval_days: Option<u64>,
+        #[serde(flatten)]
+        restore_options: FolderRestoreOptions,
     },
+    #[serde(rename = "mysql", alias = "my_s_q_l")]
     MySQL {
         db_host: Option<String>,
         db_port: u16,
+        #[serde(default)]
         db_name: String,
         db_user: String,
         db_password: String,
+        #[serde(default)]
+        client_container: bool,
+        #[serde(default)]
+        all_databases: Option<DatabaseDiscovery>,
+        #[serde(flatten)]
+        dump_options: MySQLDumpOptions,
     },
+    #[serde(rename = "mysql_docker", alias = "my_s_q_l_docker")]
     MySQLDocker {
         docker_container: String,
+        #[serde(default)]
         db_name: String,
         db_user: String,
         db_password: String,
+        #[serde(default)]
+        all_databases: Option<DatabaseDiscovery>,
+        #[serde(flatten)]
+        dump_options: MySQLDumpOptions,
     },
+    Redis {
+        db_host: Option<String>,
+        db_port: u16,
+        db_password: Option<String>,
+        rdb_path: Option<String>,
+    },
+    RedisDocker {
+        docker_container: String,
+        db_password: Option<String>,
+        rdb_path: Option<String>,
+    },
+    Sqlite {
+        db_path: String,
+    },
+    S3Sync {
+        source_endpoint: String,
+        source_region: String,
+        source_bucket: String,
+        source_access: String,
+        source_secret: String,
+        source_path_style: S3PathStyle,
+        #[serde(default)]
+        source_prefix: String,
+    },
+}
+
+/// Defines how the databases of an element are discovered.
+///
+/// # Variants
+/// - `Auto` - The list of databases is queried from the server at run time.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseDiscovery {
+    Auto,
+}
+
+/// Defines the archive format of folder backups.
+///
+/// # Variants
+/// - `TarGz` - A gzip-compressed tar archive created with the `tar` binary (default).
+/// - `Zip` - A zip archive created without external binaries, which can be opened directly on Windows.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    #[default]
+    TarGz,
+    Zip,
+}
+
+/// Defines the value of the `--set-gtid-purged` flag of `mysqldump`.
+///
+/// The values are written like the flag values (e.g., `"OFF"`), lowercase values are accepted as well.
+#[derive(Debug, Clone, Deserialize)]
+pub enum GtidPurged {
+    #[serde(rename = "OFF", alias = "off")]
+    Off,
+    #[serde(rename = "ON", alias = "on")]
+    On,
+    #[serde(rename = "AUTO", alias = "auto")]
+    Auto,
+    #[serde(rename = "COMMENTED", alias = "commented")]
+    Commented,
+}
+
+impl GtidPurged {
+    /// Returns the value of the flag.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GtidPurged::Off => "OFF",
+            GtidPurged::On => "ON",
+            GtidPurged::Auto => "AUTO",
+            GtidPurged::Commented => "COMMENTED",
+        }
+    }
+}
+
+/// Defines how file attributes are applied when a folder backup is restored.
+///
+/// # Fields
+/// - `preserve_ownership` - Restores the owner and group stored in the archive (requires root). Otherwise, the
+///   files belong to the user running the restore. Default: `true`. Zip archives do not store ownership.
+/// - `preserve_permissions` - Restores the stored permissions instead of applying the umask. Default: `true`.
+/// - `preserve_timestamps` - Restores the stored modification times instead of the current time. Default: `true`.
+/// - `restore_owner` - Optional `user[:group]` that the restored files are assigned to with `chown -R`.
+///   Overrides `preserve_ownership`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FolderRestoreOptions {
+    pub preserve_ownership: bool,
+    pub preserve_permissions: bool,
+    pub preserve_timestamps: bool,
+    pub restore_owner: Option<String>,
+}
+
+impl Default for FolderRestoreOptions {
+    fn default() -> Self {
+        FolderRestoreOptions {
+            preserve_ownership: true,
+            preserve_permissions: true,
+            preserve_timestamps: true,
+            restore_owner: None,
+        }
+    }
+}
+
+impl FolderRestoreOptions {
+    /// Returns the `tar` extraction flags for the options, separated by spaces.
+    ///
+    /// # Example
+    /// ```rust
+    /// let flags = FolderRestoreOptions::default().to_tar_args();
+    /// assert_eq!(flags, "--same-owner --same-permissions");
+    /// ```
+    pub fn to_tar_args(&self) -> String {
+        let mut args = Vec::new();
+
+        args.push(if self.preserve_ownership && self.restore_owner.is_none() {
+            "--same-owner"
+        } else {
+            "--no-same-owner"
+        });
+        args.push(if self.preserve_permissions {
+            "--same-permissions"
+        } else {
+            "--no-same-permissions"
+        });
+        if !self.preserve_timestamps {
+            args.push("--touch");
+        }
+
+        args.join(" ")
+    }
+}
+
+/// Defines the flags passed to `mysqldump` for MySQL elements.
+///
+/// The defaults produce a consistent dump of InnoDB tables without locking them and include stored routines,
+/// events and triggers.
+///
+/// # Fields
+/// - `single_transaction` - Dumps the data in a single transaction instead of locking the tables. Default: `true`.
+/// - `routines` - Includes stored procedures and functions. Default: `true`.
+/// - `events` - Includes scheduled events. Default: `true`.
+/// - `triggers` - Includes triggers. Default: `true`.
+/// - `set_gtid_purged` - Optional value of `--set-gtid-purged` (`OFF`, `ON`, `AUTO`, `COMMENTED`). Not passed by default,
+///   because MariaDB does not support this flag.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MySQLDumpOptions {
+    pub single_transaction: bool,
+    pub routines: bool,
+    pub events: bool,
+    pub triggers: bool,
+    pub set_gtid_purged: Option<GtidPurged>,
+}
+
+impl Default for MySQLDumpOptions {
+    fn default() -> Self {
+        MySQLDumpOptions {
+            single_transaction: true,
+            routines: true,
+            events: true,
+            triggers: true,
+            set_gtid_purged: None,
+        }
+    }
+}
+
+impl MySQLDumpOptions {
+    /// Returns the `mysqldump` flags for the options, separated by spaces.
+    ///
+    /// # Example
+    /// ```rust
+    /// let flags = MySQLDumpOptions::default().to_args();
+    /// assert_eq!(flags, "--single-transaction --routines --events --triggers");
+    /// ```
+    pub fn to_args(&self) -> String {
+        let mut args = Vec::new();
+
+        if self.single_transaction {
+            args.push(String::from("--single-transaction"));
+        }
+        if self.routines {
+            args.push(String::from("--routines"));
+        }
+        if self.events {
+            args.push(String::from("--events"));
+        }
+        args.push(String::from(if self.triggers {
+            "--triggers"
+        } else {
+            "--skip-triggers"
+        }));
+        if let Some(value) = &self.set_gtid_purged {
+            args.push(format!("--set-gtid-purged={}", value.as_str()));
+        }
+
+        args.join(" ")
+    }
 }
@@ -0,0 +1,253 @@
+use crate::structures::elements::Elements;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// The name of the variable used when the template values are plain strings or numbers.
+const DEFAULT_VARIABLE: &str = "value";
+
+/// Represents an element template that is instantiated once for every entry in `values`.
+///
+/// The `element` is written like a regular element, but its strings can contain `{variable}` placeholders.
+/// If the values are plain strings or numbers, the placeholder is `{value}`. If the values are objects, every key
+/// of the object is available as a variable (e.g., `{tenant}`, `{port}`).
+///
+/// A string that consists only of a placeholder is replaced with the value itself, so numbers and booleans keep
+/// their type (e.g., `"db_port": "{port}"` becomes `"db_port": 5433`).
+///
+/// # Fields
+/// - `values` - The values the template is expanded over.
+/// - `element` - The element definition with placeholders.
+///
+/// # Example
+/// ```json
+/// {
+///   "values": ["tenant_a", "tenant_b"],
+///   "element": {
+///     "element_title": "pg_{value}",
+///     "s3_folder": "tenants/{value}",
+///     "backup_retention_days": 7,
+///     "s3_backup_retention_days": 30,
+///     "params": { "type": "postgresql", "db_port": 5432, "db_name": "{value}", "db_user": "postgres", "db_password": "password" }
+///   }
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct ElementTemplate {
+    pub values: Vec<Value>,
+    pub element: Value,
+}
+
+impl ElementTemplate {
+    /// Instantiates the template for every entry in `values`.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<Elements>)` - The generated elements in the order of the values.
+    /// - `Err(String)` - An error message if a value is not a string, number or object, a placeholder has no
+    ///   variable, or a generated element is invalid (e.g., a required field is missing).
+    ///
+    /// # Example
+    /// ```rust
+    /// let elements = template.expand()?;
+    /// ```
+    pub fn expand(&self) -> Result<Vec<Elements>, String> {
+        self.values
+            .iter()
+            .map(|value| {
+                let variables = get_variables(value)?;
+                let element = substitute(&self.element, &variables)?;
+
+                serde_json::from_value(element)
+                    .map_err(|e| format!("Invalid element template for value {}: {}", value, e))
+            })
+            .collect()
+    }
+}
+
+/// Returns the variables defined by a single template value.
+fn get_variables(value: &Value) -> Result<Map<String, Value>, String> {
+    match value {
+        Value::Object(variables) => Ok(variables.clone()),
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => {
+            let mut variables = Map::new();
+            variables.insert(DEFAULT_VARIABLE.to_string(), value.clone());
+            Ok(variables)
+        }
+        _ => Err(format!("Unsupported element template value: {}", value)),
+    }
+}
+
+/// Replaces the `{variable}` placeholders in all strings of a JSON value.
+///
+/// A placeholder is a name of letters, digits and `_` in braces. Every placeholder must have a variable, so a typo
+/// fails the configuration instead of ending up in a path or command. Other braces (e.g., `{}` or `{ "a": 1 }`) are
+/// kept as they are.
+fn substitute(value: &Value, variables: &Map<String, Value>) -> Result<Value, String> {
+    match value {
+        Value::String(text) => {
+            let whole = text
+                .strip_prefix('{')
+                .and_then(|name| name.strip_suffix('}'))
+                .and_then(|name| variables.get(name));
+
+            if let Some(variable) = whole {
+                return Ok(variable.clone());
+            }
+
+            substitute_text(text, variables).map(Value::String)
+        }
+        Value::Array(items) => items
+            .iter()
+            .map(|item| substitute(item, variables))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, field)| Ok((key.clone(), substitute(field, variables)?)))
+            .collect::<Result<_, String>>()
+            .map(Value::Object),
+        other => Ok(other.clone()),
+    }
+}
+
+/// Replaces the placeholders in a string in a single pass, so values that contain `{other}` are inserted as they
+/// are and not expanded again.
+fn substitute_text(text: &str, variables: &Map<String, Value>) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let name = rest[1..]
+            .find('}')
+            .map(|end| &rest[1..=end])
+            .filter(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+
+        match name {
+            Some(name) => {
+                match variables.get(name) {
+                    Some(Value::String(value)) => result.push_str(value),
+                    Some(value) => result.push_str(&value.to_string()),
+                    None => return Err(format!("Unknown placeholder {{{}}} in element template: {}", name, text)),
+                }
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::backup_params::BackupParams;
+    use serde_json::json;
+
+    fn template(values: Value) -> ElementTemplate {
+        serde_json::from_value(json!({
+            "values": values,
+            "element": {
+                "element_title": "pg_{tenant}",
+                "s3_folder": "tenants/{tenant}/{tenant}",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": "{days}",
+                "params": {
+                    "type": "postgresql",
+                    "db_port": "{port}",
+                    "db_name": "db_{tenant}_{port}",
+                    "db_user": "postgres",
+                    "db_password": "password"
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn expands_object_values_with_typed_placeholders() {
+        let elements = template(json!([
+            { "tenant": "a", "port": 5432, "days": 30 },
+            { "tenant": "b", "port": 5433, "days": 14 }
+        ]))
+        .expand()
+        .unwrap();
+
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[1].element_title, "pg_b");
+        assert_eq!(elements[1].s3_folder, "tenants/b/b");
+        assert_eq!(elements[1].s3_backup_retention_days, 14);
+
+        match &elements[1].params {
+            Some(BackupParams::Postgresql { db_port, db_name, .. }) => {
+                assert_eq!(*db_port, 5433);
+                assert_eq!(db_name, "db_b_5433");
+            }
+            other => panic!("unexpected params: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn expands_plain_values_as_default_variable() {
+        let template: ElementTemplate = serde_json::from_value(json!({
+            "values": ["shop", 42],
+            "element": {
+                "element_title": "folder_{value}",
+                "s3_folder": "folders/{value}",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30,
+                "params": { "type": "folder", "target_path": "/srv/{value}/{ not a placeholder }" }
+            }
+        }))
+        .unwrap();
+
+        let elements = template.expand().unwrap();
+
+        assert_eq!(elements[0].element_title, "folder_shop");
+        assert_eq!(elements[1].s3_folder, "folders/42");
+
+        match &elements[0].params {
+            Some(BackupParams::Folder { target_path, .. }) => assert_eq!(target_path, "/srv/shop/{ not a placeholder }"),
+            other => panic!("unexpected params: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_values_and_invalid_elements() {
+        let error = template(json!([["nested"]])).expand().unwrap_err();
+        assert!(error.contains("Unsupported element template value"), "{}", error);
+
+        let error = template(json!([{ "tenant": "a", "port": 5432, "days": "thirty" }])).expand().unwrap_err();
+        assert!(error.contains("Invalid element template"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let error = template(json!([{ "tenant": "a", "port": 5432 }])).expand().unwrap_err();
+        assert!(error.contains("Unknown placeholder {days}"), "{}", error);
+
+        let mut typo = template(json!([{ "tenant": "a", "port": 5432, "days": 30 }]));
+        typo.element["params"]["db_name"] = json!("db_{tenat}");
+        let error = typo.expand().unwrap_err();
+        assert!(error.contains("Unknown placeholder {tenat}"), "{}", error);
+    }
+
+    #[test]
+    fn does_not_expand_placeholders_in_values() {
+        let elements = template(json!([{ "tenant": "{port}", "port": 5432, "days": 30 }]))
+            .expand()
+            .unwrap();
+
+        assert_eq!(elements[0].element_title, "pg_{port}");
+        assert_eq!(elements[0].s3_folder, "tenants/{port}/{port}");
+    }
+}
@@ -1,9 +1,52 @@
-use crate::structures::backup_params::BackupParams;
-use chrono::Local;
-use log::{error, info};
+use crate::structures::backup_params::{ArchiveFormat, BackupParams, DatabaseDiscovery};
+use crate::structures::settings::{create_bucket, Compression, StorageTarget};
+use crate::utils::archive_utils::{
+    create_zip_archive, extract_zip_archive, is_zip_archive, normalize_archive_path,
+};
+use crate::utils::container_utils::{
+    get_docker_run_command, get_mysql_client, get_postgres_client, quote_shell_argument, run_query,
+};
+use crate::utils::fs_utils::get_local_snapshots;
+use crate::utils::incremental_utils::{
+    prepare_incremental_backup, BackupLevel, DEFAULT_FULL_BACKUP_INTERVAL_DAYS,
+};
+use crate::utils::time_utils;
+use log::{info, warn};
+use s3::Bucket;
 use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output};
+
+/// The format of the timestamp in backup file names (e.g., `element-title-YYYY-MM-DD_HH-MM-SS.sql`).
+pub const BACKUP_TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// The subdirectory of the local backup directory where hardlinked folder snapshots are stored.
+pub const LOCAL_SNAPSHOTS_DIR: &str = "snapshots";
+
+/// The suffix of a local snapshot while it is being created. The directory is renamed once `rsync` succeeds.
+pub const PARTIAL_SNAPSHOT_SUFFIX: &str = ".partial";
+
+/// The temporary path of the RDB snapshot inside Redis containers.
+const REDIS_CONTAINER_DUMP_PATH: &str = "/tmp/reback-dump.rdb";
+
+/// The default path of the RDB snapshot in the official Redis image.
+const REDIS_DEFAULT_RDB_PATH: &str = "/data/dump.rdb";
+
+/// The environment variable with the MongoDB password. The MongoDB tools cannot read the password from the
+/// environment, so it is expanded by the shell that runs `mongodump` or `mongorestore`.
+const MONGO_PASSWORD_ENV: &str = "REBACK_MONGO_PASSWORD";
+
+/// The query that lists the databases of a PostgreSQL server.
+const PG_LIST_DATABASES_QUERY: &str =
+    "SELECT datname FROM pg_database WHERE NOT datistemplate AND datallowconn ORDER BY datname";
+
+/// The PostgreSQL maintenance and template databases that are skipped by the discovery.
+const PG_SYSTEM_DATABASES: [&str; 3] = ["postgres", "template0", "template1"];
+
+/// The MySQL system databases that are skipped by the discovery.
+const MYSQL_SYSTEM_DATABASES: [&str; 4] = ["information_schema", "performance_schema", "mysql", "sys"];
 
 /// Represents an element to be backed up, along with its backup configuration.
 ///
@@ -16,16 +59,55 @@ use std::process::Command;
 /// - `s3_folder` - The folder in the S3 bucket where the backup should be stored.
 /// - `backup_retention_days` - The number of days to retain the backup locally.
 /// - `s3_backup_retention_days` - The number of days to retain the backup in the S3 bucket.
+/// - `keep_daily` - Optional number of days for which the newest backup is kept after the retention days.
+/// - `keep_weekly` - Optional number of weeks for which the newest backup is kept after the retention days.
+/// - `keep_monthly` - Optional number of months for which the newest backup is kept after the retention days.
+/// - `keep_yearly` - Optional number of years for which the newest backup is kept after the retention days.
+/// - `s3_credentials` - Optional name of the S3 keys (from `Settings::s3_credentials`) used to upload and download.
+/// - `s3_prune_credentials` - Optional name of the S3 keys used only to list and delete outdated backups.
+/// - `s3_lock_credentials` - Optional name of the S3 keys used to read, write and delete the lock of the element
+///   (see `lock_bucket_credentials`).
+/// - `lock_period_minutes` - Optional period of an S3 lock that allows only one host to back up the element per period.
+/// - `schedule` - Optional cron expression used by `reback daemon` to back up the element (e.g., `0 3 * * *`).
+/// - `storage` - Optional storage targets of the element. If not set, `Settings::storage` is used.
+/// - `compression` - Optional compression of the backup files. If not set, `Settings::compression` is used.
+/// - `stream_backups` - Optional override of `Settings::stream_backups` for the element.
 /// - `params` - Optional parameters describing the type of backup (e.g., database or folder).
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Elements {
     pub element_title: String,
     pub s3_folder: String,
     pub backup_retention_days: u64,
     pub s3_backup_retention_days: u64,
+    pub keep_daily: Option<u32>,
+    pub keep_weekly: Option<u32>,
+    pub keep_monthly: Option<u32>,
+    pub keep_yearly: Option<u32>,
+    pub s3_credentials: Option<String>,
+    pub s3_prune_credentials: Option<String>,
+    pub s3_lock_credentials: Option<String>,
+    pub lock_period_minutes: Option<u64>,
+    pub schedule: Option<String>,
+    pub storage: Option<Vec<StorageTarget>>,
+    pub compression: Option<Compression>,
+    pub stream_backups: Option<bool>,
     pub params: Option<BackupParams>,
 }
 
+/// A shell command that writes a database dump to the standard output.
+///
+/// # Fields
+/// - `command` - The shell command.
+/// - `env` - Environment variables of the command (e.g., the password in `PGPASSWORD`), so secrets do not appear
+///   in the command line.
+/// - `extension` - The extension of the dump file (e.g., `sql`).
+#[derive(Clone)]
+pub struct DumpCommand {
+    pub command: String,
+    pub env: Vec<(&'static str, String)>,
+    pub extension: &'static str,
+}
+
 impl Elements {
     /// Performs a backup based on the specified parameters for the element.
     ///
@@ -33,6 +115,7 @@ impl Elements {
     /// It constructs the required backup command, executes it, and returns the path to the backup file.
     ///
     /// The filename is formatted with a timestamp (e.g., `element-title-YYYY-MM-DD_HH-MM-SS.sql`) to avoid overwriting files.
+    /// If a file with the same name already exists (e.g., two backups within the same second), a sequence suffix is added.
     /// The backup command is executed for each type of backup, depending on the provided parameters.
     ///
     /// # Arguments
@@ -46,17 +129,210 @@ impl Elements {
     /// - Executes a backup command based on the backup type specified in `self.params`.
     /// - If no backup parameters are provided (`None`), it returns an error with the element's title.
     /// - The method handles PostgreSQL, MongoDB, Docker-based backups, and folder backups.
+    ///   Database dumps are created with the command from `get_dump_command`, redirected into the backup file.
     /// - For Docker-based backups, the appropriate `docker exec` commands are used to run the backups inside containers.
-    /// - For folder backups, a `tar` command is used to create compressed archive files.
+    ///   The dump is streamed over the standard output of `docker exec`, so no path inside the container is required.
+    /// - For folder backups, a `tar` command is used to create compressed archive files, or a zip archive is
+    ///   written in-process if the `zip` archive format is selected. Incremental folder backups are created with
+    ///   `tar --listed-incremental` (see `create_incremental_archive`).
+    /// - For Redis backups, the RDB snapshot is fetched with `redis-cli --rdb`. For SQLite backups, the `.backup`
+    ///   command of the `sqlite3` shell is used.
     ///
     /// # Example
     /// ```rust
     /// let backup_path = element.perform_backup(&backup_dir).await?;
     /// ```
     pub async fn perform_backup(&self, path: &Path) -> Result<PathBuf, String> {
-        let now = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+        let now = time_utils::now().format(BACKUP_TIMESTAMP_FORMAT).to_string();
         let file_path: PathBuf;
 
+        match &self.params {
+            Some(BackupParams::Postgresql { .. })
+            | Some(BackupParams::PostgresqlDocker { .. })
+            | Some(BackupParams::Mongodb { .. })
+            | Some(BackupParams::MongodbDocker { .. })
+            | Some(BackupParams::MySQL { .. })
+            | Some(BackupParams::MySQLDocker { .. }) => {
+                let dump = self.get_dump_command()?;
+                file_path = self.get_backup_path(path, &now, dump.extension)?;
+
+                let command = format!("{} > {}", dump.command, quote_shell_argument(&file_path.to_string_lossy()));
+
+                // A failed dump leaves an empty or partial file, which must not be uploaded as a backup.
+                if let Err(e) = self.execute_command_with_env(&command, &dump.env).await {
+                    let _ = fs::remove_file(&file_path);
+                    return Err(e);
+                }
+            }
+
+            Some(BackupParams::Folder {
+                target_path,
+                incremental: true,
+                full_backup_interval_days,
+                ..
+            }) => {
+                info!("Backing up folder incrementally: path={}", target_path);
+
+                file_path = self
+                    .create_incremental_archive(
+                        path,
+                        &now,
+                        target_path,
+                        full_backup_interval_days.unwrap_or(DEFAULT_FULL_BACKUP_INTERVAL_DAYS),
+                    )
+                    .await?;
+            }
+
+            Some(BackupParams::Folder {
+                target_path,
+                archive_format,
+                local_snapshots,
+                ..
+            }) => {
+                info!("Backing up folder: path={}", target_path);
+
+                let source = if *local_snapshots {
+                    self.create_local_snapshot(path, &now, target_path).await?
+                } else {
+                    PathBuf::from(target_path)
+                };
+
+                match archive_format {
+                    ArchiveFormat::TarGz => {
+                        file_path = self.get_backup_path(path, &now, "tar.gz")?;
+
+                        let mut command = Command::new("tar");
+                        command.arg("-czf").arg(&file_path).arg("-C").arg(&source).arg(".");
+
+                        let result = self.execute_program(command).await;
+
+                        if let Err(e) = result {
+                            let _ = fs::remove_file(&file_path);
+                            return Err(e);
+                        }
+                    }
+                    ArchiveFormat::Zip => {
+                        file_path = self.get_backup_path(path, &now, "zip")?;
+
+                        let (zip_source, zip_path) = (source.clone(), file_path.clone());
+                        let created = tokio::task::spawn_blocking(move || {
+                            create_zip_archive(&zip_source, &zip_path).map_err(|e| e.to_string())
+                        })
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()));
+
+                        created.map_err(|e| format!("Failed to create zip archive of {}: {}", source.display(), e))?;
+                    }
+                }
+            }
+
+            Some(BackupParams::Redis {
+                db_host,
+                db_port,
+                db_password,
+                ..
+            }) => {
+                let db_host = db_host.clone().unwrap_or(String::from("localhost"));
+
+                info!("Backing up Redis: host={}, port={}", db_host, db_port);
+
+                file_path = self.get_backup_path(path, &now, "rdb")?;
+
+                let mut command = Command::new("redis-cli");
+                command
+                    .args(["-h", &db_host, "-p", &db_port.to_string(), "--rdb"])
+                    .arg(&file_path)
+                    .envs(get_redis_env(db_password));
+
+                self.execute_program(command).await?;
+            }
+
+            Some(BackupParams::RedisDocker {
+                docker_container,
+                db_password,
+                ..
+            }) => {
+                info!("Backing up Redis Docker: docker_container={}", docker_container);
+
+                file_path = self.get_backup_path(path, &now, "rdb")?;
+
+                let file = File::create(&file_path)
+                    .map_err(|e| format!("Failed to create backup file {}: {}", file_path.display(), e))?;
+                let env = get_redis_env(db_password);
+
+                // The snapshot is written to a temporary file inside the container and streamed to the host,
+                // because older versions of redis-cli cannot write it to stdout. The password is passed from the
+                // environment of `docker exec` (`-e REDISCLI_AUTH` without a value).
+                let mut command = Command::new("docker");
+                command.arg("exec");
+                for (name, _) in &env {
+                    command.args(["-e", name]);
+                }
+                command
+                    .args([docker_container.as_str(), "sh", "-c"])
+                    .arg(format!(
+                        "redis-cli --rdb {0} > /dev/null && cat {0} && rm -f {0}",
+                        REDIS_CONTAINER_DUMP_PATH
+                    ))
+                    .envs(env)
+                    .stdout(file);
+
+                if let Err(e) = self.execute_program(command).await {
+                    let _ = fs::remove_file(&file_path);
+                    return Err(e);
+                }
+            }
+
+            Some(BackupParams::Sqlite { db_path }) => {
+                info!("Backing up SQLite: path={}", db_path);
+
+                file_path = self.get_backup_path(path, &now, "sqlite")?;
+
+                let mut command = Command::new("sqlite3");
+                command
+                    .arg(db_path)
+                    .arg(format!(".backup {}", quote_sqlite_argument(&file_path.to_string_lossy())));
+
+                self.execute_program(command).await?;
+            }
+
+            Some(BackupParams::S3Sync { .. }) => {
+                return Err(format!(
+                    "Element '{}' is an S3 sync element and does not produce a backup file",
+                    self.element_title
+                ));
+            }
+
+            None => {
+                return Err(format!(
+                    "No backup parameters provided for element '{}'",
+                    self.element_title
+                ));
+            }
+        }
+
+        info!("Backup created successfully!");
+
+        Ok(file_path)
+    }
+
+    /// Returns the shell command that writes the database dump of the element to the standard output.
+    ///
+    /// `perform_backup` redirects the output into the backup file, and streaming backups (see `stream_utils`)
+    /// upload it to S3 directly. Dumps are only available for PostgreSQL, MySQL and MongoDB elements, both local
+    /// and Docker-based.
+    ///
+    /// # Returns
+    /// - `Ok(DumpCommand)` - The dump command and the extension of the dump file.
+    /// - `Err(String)` - An error message if the element is not a database with a dump command, or if the client
+    ///   image for `client_container` cannot be detected.
+    ///
+    /// # Example
+    /// ```rust
+    /// let dump = element.get_dump_command()?;
+    /// let command = format!("{} > {}", dump.command, quote_shell_argument(&file_path.to_string_lossy()));
+    /// ```
+    pub fn get_dump_command(&self) -> Result<DumpCommand, String> {
         match &self.params {
             Some(BackupParams::Postgresql {
                 db_host,
@@ -64,6 +340,8 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                client_container,
+                ..
             }) => {
                 let db_host = db_host.clone().unwrap_or(String::from("localhost"));
 
@@ -72,20 +350,34 @@ impl Elements {
                     db_host, db_port, db_name, db_user
                 );
 
-                let file_name = format!("{}-{}.sql", self.element_title, now);
-                file_path = path.join(&file_name);
-
-                let command = format!(
-                    "PGPASSWORD=\"{}\" pg_dump -U {} -h {} -p {} {} > {}",
-                    db_password,
-                    db_user,
-                    db_host,
+                let dump_args = format!(
+                    "-U {} -h {} -p {} {}",
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(&db_host),
                     db_port,
-                    db_name,
-                    file_path.display(),
+                    quote_shell_argument(db_name)
                 );
 
-                self.execute_command(&command).await;
+                let command = if *client_container {
+                    let client =
+                        get_postgres_client(&db_host, *db_port, db_name, db_user, db_password)?;
+                    info!("Using client image {}", client.image);
+
+                    get_docker_run_command(
+                        &client.image,
+                        "PGPASSWORD",
+                        &format!("{} {}", client.dump, dump_args),
+                        false,
+                    )
+                } else {
+                    format!("pg_dump {}", dump_args)
+                };
+
+                Ok(DumpCommand {
+                    command,
+                    env: vec![("PGPASSWORD", db_password.clone())],
+                    extension: "sql",
+                })
             }
 
             Some(BackupParams::PostgresqlDocker {
@@ -93,25 +385,23 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                ..
             }) => {
                 info!(
                     "Backing up PostgreSQL Docker: docker_container={}, db={}, user={}",
                     docker_container, db_name, db_user
                 );
 
-                let file_name = format!("{}-{}.sql", self.element_title, now);
-                file_path = path.join(&file_name);
-
-                let command = format!(
-                    "docker exec {} bash -c \"PGPASSWORD='{}' pg_dump -U {} {}\" > {}",
-                    docker_container,
-                    db_password,
-                    db_user,
-                    db_name,
-                    file_path.display(),
-                );
-
-                self.execute_command(&command).await;
+                Ok(DumpCommand {
+                    command: format!(
+                        "docker exec -e PGPASSWORD {} pg_dump -U {} {}",
+                        quote_shell_argument(docker_container),
+                        quote_shell_argument(db_user),
+                        quote_shell_argument(db_name),
+                    ),
+                    env: vec![("PGPASSWORD", db_password.clone())],
+                    extension: "sql",
+                })
             }
 
             Some(BackupParams::Mongodb {
@@ -123,31 +413,18 @@ impl Elements {
                 info!("Backing up MongoDB");
                 let db_host = db_host.clone().unwrap_or(String::from("localhost"));
 
-                let file_name = format!("{}-{}.gz", self.element_title, now);
-                file_path = path.join(&file_name);
-
-                let command = match db_user {
-                    Some(user) => {
-                        format!(
-                            "mongodump --host {} --port {} --username {} --password {:?} --authenticationDatabase admin --archive={} --gzip",
-                            db_host,
-                            db_port,
-                            user,
-                            db_password,
-                            file_path.display(),
-                        )
-                    }
-                    None => {
-                        format!(
-                            "mongodump --host {} --port {} --archive={} --gzip",
-                            db_host,
-                            db_port,
-                            file_path.display(),
-                        )
-                    }
-                };
+                let (auth_args, env) = get_mongo_auth(db_user, db_password);
 
-                self.execute_command(&command).await;
+                Ok(DumpCommand {
+                    command: format!(
+                        "mongodump --host {} --port {} {}--archive --gzip",
+                        quote_shell_argument(&db_host),
+                        db_port,
+                        auth_args,
+                    ),
+                    env,
+                    extension: "gz",
+                })
             }
 
             Some(BackupParams::MongodbDocker {
@@ -157,46 +434,18 @@ impl Elements {
             }) => {
                 info!("Backing up MongoDB: docker_container={}", docker_container);
 
-                let file_name = format!("{}-{}.gz", self.element_title, now);
-                file_path = path.join(&file_name);
-
-                let command = match db_user {
-                    Some(user) => {
-                        format!(
-                            "docker exec {} mongodump --username {} --password {:?} --authenticationDatabase admin --archive=/backup/backup.gz --gzip",
-                            docker_container,
-                            user,
-                            db_password,
-                        )
-                    }
-                    None => {
-                        format!(
-                            "docker exec {} mongodump --archive=/backup/backup.gz --gzip",
-                            docker_container,
-                        )
-                    }
-                };
-
-                self.execute_command(&command).await;
-
-                let copy_backup_command = format!(
-                    "docker cp {}:/backup/backup.gz {}",
-                    docker_container,
-                    file_path.display()
-                );
-
-                self.execute_command(&copy_backup_command).await;
-            }
-
-            Some(BackupParams::Folder { target_path }) => {
-                info!("Backing up folder: path={}", target_path);
-
-                let file_name = format!("{}-{}.tar.gz", self.element_title, now);
-                file_path = path.join(&file_name);
+                let (auth_args, env) = get_mongo_auth(db_user, db_password);
 
-                let command = format!("tar -czvf {} -C {} .", file_path.display(), target_path);
-
-                self.execute_command(&command).await;
+                Ok(DumpCommand {
+                    command: get_mongo_docker_command(
+                        docker_container,
+                        &format!("mongodump {}--archive --gzip", auth_args),
+                        &env,
+                        false,
+                    ),
+                    env,
+                    extension: "gz",
+                })
             }
 
             Some(BackupParams::MySQL {
@@ -205,6 +454,9 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                client_container,
+                dump_options,
+                ..
             }) => {
                 let db_host = db_host.clone().unwrap_or(String::from("localhost"));
 
@@ -213,20 +465,34 @@ impl Elements {
                     db_host, db_port, db_name, db_user
                 );
 
-                let file_name = format!("{}-{}.sql", self.element_title, now);
-                file_path = path.join(&file_name);
-
-                let command = format!(
-                    "MYSQL_PWD={} mysqldump -u {} -h {} -P {} {} > {}",
-                    db_password,
-                    db_user,
-                    db_host,
+                let dump_args = format!(
+                    "{} -u {} -h {} -P {} {}",
+                    dump_options.to_args(),
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(&db_host),
                     db_port,
-                    db_name,
-                    file_path.display(),
+                    quote_shell_argument(db_name)
                 );
 
-                self.execute_command(&command).await;
+                let command = if *client_container {
+                    let client = get_mysql_client(&db_host, *db_port, db_user, db_password)?;
+                    info!("Using client image {}", client.image);
+
+                    get_docker_run_command(
+                        &client.image,
+                        "MYSQL_PWD",
+                        &format!("{} {}", client.dump, dump_args),
+                        false,
+                    )
+                } else {
+                    format!("mysqldump {}", dump_args)
+                };
+
+                Ok(DumpCommand {
+                    command,
+                    env: vec![("MYSQL_PWD", db_password.clone())],
+                    extension: "sql",
+                })
             }
 
             Some(BackupParams::MySQLDocker {
@@ -234,38 +500,45 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                dump_options,
+                ..
             }) => {
                 info!(
                     "Backing up MySQL Docker: docker_container={}, db={}, user={}",
                     docker_container, db_name, db_user
                 );
 
-                let file_name = format!("{}-{}.sql", self.element_title, now);
-                file_path = path.join(&file_name);
-
-                let command = format!(
-                    "docker exec {} bash -c \"MYSQL_PWD='{}' mysqldump -u {} {}\" > {}",
-                    docker_container,
-                    db_password,
-                    db_user,
-                    db_name,
-                    file_path.display(),
-                );
-
-                self.execute_command(&command).await;
+                Ok(DumpCommand {
+                    command: format!(
+                        "docker exec -e MYSQL_PWD {} mysqldump {} -u {} {}",
+                        quote_shell_argument(docker_container),
+                        dump_options.to_args(),
+                        quote_shell_argument(db_user),
+                        quote_shell_argument(db_name),
+                    ),
+                    env: vec![("MYSQL_PWD", db_password.clone())],
+                    extension: "sql",
+                })
             }
 
-            None => {
-                return Err(format!(
-                    "No backup parameters provided for element '{}'",
-                    self.element_title
-                ));
-            }
+            _ => Err(format!(
+                "Element '{}' is not a database with a dump command",
+                self.element_title
+            )),
         }
+    }
 
-        info!("Backup created successfully!");
-
-        Ok(file_path)
+    /// Checks whether the element is a database whose dump can be streamed (see `get_dump_command`).
+    pub fn has_dump_command(&self) -> bool {
+        matches!(
+            self.params,
+            Some(BackupParams::Postgresql { .. })
+                | Some(BackupParams::PostgresqlDocker { .. })
+                | Some(BackupParams::Mongodb { .. })
+                | Some(BackupParams::MongodbDocker { .. })
+                | Some(BackupParams::MySQL { .. })
+                | Some(BackupParams::MySQLDocker { .. })
+        )
     }
 
     /// Restores a backup based on the specified parameters for the element.
@@ -275,23 +548,48 @@ impl Elements {
     ///
     /// # Arguments
     /// - `path` - The path to the backup file that needs to be restored.
+    /// - `paths` - The paths inside a folder archive to restore (e.g., `etc/nginx/nginx.conf`). Empty to restore
+    ///   the whole backup. Only supported for folder elements.
     ///
     /// # Returns
     /// - `Ok(())` - Indicates successful restoration of the backup.
-    /// - `Err(String)` - An error message if restore parameters are not provided or an error occurs during restoration.
+    /// - `Err(String)` - An error message if restore parameters are not provided, paths are given for an element
+    ///   that is not a folder, or an error occurs during restoration.
     ///
     /// # Behavior
     /// - Executes a restore command based on the type specified in `self.params`.
     /// - If no restore parameters are provided (`None`), it returns an error with the element's title.
     /// - Handles PostgreSQL, MongoDB, Docker-based restores, and folder restores.
     /// - For Docker-based restores, the appropriate `docker exec` commands are used to execute restoration inside containers.
-    /// - For folder restores, the `tar` command is used to extract the archived files.
+    /// - For folder restores, the `tar` command is used to extract the archived files. Zip archives (detected by
+    ///   the `.zip` extension) are extracted in-process. Ownership, permissions and timestamps are applied according
+    ///   to the element's restore options. If `paths` are given, only these entries are extracted.
+    /// - Archives of incremental chains are extracted with `--listed-incremental=/dev/null`, so files deleted between
+    ///   two backups are also deleted when the increments are replayed in order. Selective restore of `paths` is
+    ///   not supported for incremental elements.
+    /// - For Redis restores, the RDB snapshot is copied to `rdb_path`. The Docker container is stopped while the
+    ///   snapshot is copied, so Redis does not overwrite it on shutdown. For SQLite restores, the `.restore` command
+    ///   of the `sqlite3` shell is used.
     ///
     /// # Example
     /// ```rust
-    /// element.perform_restore(&backup_file_path).await?;
+    /// element.perform_restore(&backup_file_path, &[]).await?;
     /// ```
-    pub async fn perform_restore(&self, path: &Path) -> Result<(), String> {
+    pub async fn perform_restore(&self, path: &Path, paths: &[String]) -> Result<(), String> {
+        if !paths.is_empty() && !matches!(self.params, Some(BackupParams::Folder { .. })) {
+            return Err(format!(
+                "Element '{}' is not a folder element, selective restore of paths is not supported",
+                self.element_title
+            ));
+        }
+
+        if !paths.is_empty() && self.is_incremental() {
+            return Err(format!(
+                "Element '{}' is an incremental folder element, selective restore of paths is not supported",
+                self.element_title
+            ));
+        }
+
         match &self.params {
             Some(BackupParams::Postgresql {
                 db_host,
@@ -299,6 +597,8 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                client_container,
+                ..
             }) => {
                 let db_host = db_host.clone().unwrap_or(String::from("localhost"));
 
@@ -307,17 +607,41 @@ impl Elements {
                     db_host, db_port, db_name, db_user
                 );
 
-                let command = format!(
-                    "PGPASSWORD=\"{}\" psql -U {} -h {} -p {} -d {} -f {}",
-                    db_password,
-                    db_user,
-                    db_host,
-                    db_port,
-                    db_name,
-                    path.display(),
-                );
+                let command = if *client_container {
+                    let client =
+                        get_postgres_client(&db_host, *db_port, db_name, db_user, db_password)?;
+                    info!("Using client image {}", client.image);
+
+                    format!(
+                        "{} < {}",
+                        get_docker_run_command(
+                            &client.image,
+                            "PGPASSWORD",
+                            &format!(
+                                "{} -U {} -h {} -p {} -d {}",
+                                client.client,
+                                quote_shell_argument(db_user),
+                                quote_shell_argument(&db_host),
+                                db_port,
+                                quote_shell_argument(db_name)
+                            ),
+                            true,
+                        ),
+                        quote_shell_argument(&path.to_string_lossy()),
+                    )
+                } else {
+                    format!(
+                        "psql -U {} -h {} -p {} -d {} -f {}",
+                        quote_shell_argument(db_user),
+                        quote_shell_argument(&db_host),
+                        db_port,
+                        quote_shell_argument(db_name),
+                        quote_shell_argument(&path.to_string_lossy()),
+                    )
+                };
 
-                self.execute_command(&command).await;
+                self.execute_command_with_env(&command, &[("PGPASSWORD", db_password.clone())])
+                    .await?;
             }
 
             Some(BackupParams::PostgresqlDocker {
@@ -325,6 +649,7 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                ..
             }) => {
                 info!(
                     "Restoring PostgreSQL Docker: docker_container={}, db={}, user={}",
@@ -332,15 +657,15 @@ impl Elements {
                 );
 
                 let command = format!(
-                    "docker exec -i {} bash -c \"PGPASSWORD='{}' psql -U {} -d {}\" < {}",
-                    docker_container,
-                    db_password,
-                    db_user,
-                    db_name,
-                    path.display(),
+                    "docker exec -i -e PGPASSWORD {} psql -U {} -d {} < {}",
+                    quote_shell_argument(docker_container),
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(db_name),
+                    quote_shell_argument(&path.to_string_lossy()),
                 );
 
-                self.execute_command(&command).await;
+                self.execute_command_with_env(&command, &[("PGPASSWORD", db_password.clone())])
+                    .await?;
             }
 
             Some(BackupParams::Mongodb {
@@ -352,24 +677,17 @@ impl Elements {
                 info!("Restoring MongoDB");
                 let db_host = db_host.clone().unwrap_or(String::from("localhost"));
 
-                let command = match db_user {
-                    Some(user) => format!(
-                        "mongorestore --host {} --port {} --username {} --password {:?} --authenticationDatabase admin --archive={} --gzip",
-                        db_host,
-                        db_port,
-                        user,
-                        db_password,
-                        path.display(),
-                    ),
-                    None => format!(
-                        "mongorestore --host {} --port {} --archive={} --gzip",
-                        db_host,
-                        db_port,
-                        path.display(),
-                    ),
-                };
+                let (auth_args, env) = get_mongo_auth(db_user, db_password);
+
+                let command = format!(
+                    "mongorestore --host {} --port {} {}--archive={} --gzip",
+                    quote_shell_argument(&db_host),
+                    db_port,
+                    auth_args,
+                    quote_shell_argument(&path.to_string_lossy()),
+                );
 
-                self.execute_command(&command).await;
+                self.execute_command_with_env(&command, &env).await?;
             }
 
             Some(BackupParams::MongodbDocker {
@@ -382,35 +700,86 @@ impl Elements {
                     docker_container
                 );
 
-                let command = match db_user {
-                    Some(user) => format!(
-                        "docker exec -i {} mongorestore --username {} --password {:?} --authenticationDatabase admin --archive --gzip",
-                        docker_container,
-                        user,
-                        db_password,
-                    ),
-                    None => format!(
-                        "docker exec -i {} mongorestore --archive --gzip",
+                let (auth_args, env) = get_mongo_auth(db_user, db_password);
+
+                let command = format!(
+                    "{} < {}",
+                    get_mongo_docker_command(
                         docker_container,
+                        &format!("mongorestore {}--archive --gzip", auth_args),
+                        &env,
+                        true,
                     ),
-                };
-
-                let copy_command = format!(
-                    "docker cp {} {}:/backup/backup.gz",
-                    path.display(),
-                    docker_container,
+                    quote_shell_argument(&path.to_string_lossy()),
                 );
 
-                self.execute_command(&copy_command).await;
-                self.execute_command(&command).await;
+                self.execute_command_with_env(&command, &env).await?;
             }
 
-            Some(BackupParams::Folder { target_path }) => {
+            Some(BackupParams::Folder {
+                target_path,
+                restore_options,
+                ..
+            }) => {
                 info!("Restoring folder: path={}", target_path);
 
-                let command = format!("tar -xzvf {} -C {}", path.display(), target_path);
+                let paths: Vec<String> = paths
+                    .iter()
+                    .map(|path| normalize_archive_path(path))
+                    .collect();
+
+                if !paths.is_empty() {
+                    info!("Restoring only selected paths: {:?}", paths);
+                }
+
+                if is_zip_archive(path) {
+                    extract_zip_archive(
+                        path,
+                        Path::new(target_path),
+                        &paths,
+                        restore_options.preserve_permissions,
+                        restore_options.preserve_timestamps,
+                    )
+                    .map_err(|e| {
+                        format!("Failed to extract zip archive {}: {}", path.display(), e)
+                    })?;
+                } else {
+                    let mut args = vec![String::from("-xzvf"), path.display().to_string()];
+                    args.extend(restore_options.to_tar_args().split_whitespace().map(String::from));
+
+                    if BackupLevel::from_file_name(&path.to_string_lossy()).is_some() {
+                        args.push(String::from("--listed-incremental=/dev/null"));
+                    }
+
+                    args.extend([String::from("-C"), target_path.clone()]);
+                    args.extend(paths.iter().map(|path| format!("./{}", path)));
+
+                    let mut command = Command::new("tar");
+                    command.args(&args);
+
+                    self.execute_program(command).await?;
+                }
+
+                if let Some(owner) = &restore_options.restore_owner {
+                    info!("Changing owner of {} to {}", target_path, owner);
+
+                    let mut args = vec![String::from("-R"), String::from("--"), owner.clone()];
+
+                    if paths.is_empty() {
+                        args.push(target_path.clone());
+                    } else {
+                        args.extend(
+                            paths
+                                .iter()
+                                .map(|path| Path::new(target_path).join(path).display().to_string()),
+                        );
+                    }
+
+                    let mut command = Command::new("chown");
+                    command.args(&args);
 
-                self.execute_command(&command).await;
+                    self.execute_program(command).await?;
+                }
             }
 
             Some(BackupParams::MySQL {
@@ -419,6 +788,8 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                client_container,
+                ..
             }) => {
                 let db_host = db_host.clone().unwrap_or(String::from("localhost"));
 
@@ -427,17 +798,34 @@ impl Elements {
                     db_host, db_port, db_name, db_user
                 );
 
-                let command = format!(
-                    "MYSQL_PWD={} mysql -u {} -h {} -P {} {} < {}",
-                    db_password,
-                    db_user,
-                    db_host,
+                let client_args = format!(
+                    "-u {} -h {} -P {} {}",
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(&db_host),
                     db_port,
-                    db_name,
-                    path.display(),
+                    quote_shell_argument(db_name)
                 );
 
-                self.execute_command(&command).await;
+                let command = if *client_container {
+                    let client = get_mysql_client(&db_host, *db_port, db_user, db_password)?;
+                    info!("Using client image {}", client.image);
+
+                    format!(
+                        "{} < {}",
+                        get_docker_run_command(
+                            &client.image,
+                            "MYSQL_PWD",
+                            &format!("{} {}", client.client, client_args),
+                            true,
+                        ),
+                        quote_shell_argument(&path.to_string_lossy()),
+                    )
+                } else {
+                    format!("mysql {} < {}", client_args, quote_shell_argument(&path.to_string_lossy()))
+                };
+
+                self.execute_command_with_env(&command, &[("MYSQL_PWD", db_password.clone())])
+                    .await?;
             }
 
             Some(BackupParams::MySQLDocker {
@@ -445,6 +833,7 @@ impl Elements {
                 db_name,
                 db_user,
                 db_password,
+                ..
             }) => {
                 info!(
                     "Restoring MySQL Docker: docker_container={}, db={}, user={}",
@@ -452,15 +841,75 @@ impl Elements {
                 );
 
                 let command = format!(
-                    "docker exec -i {} bash -c \"MYSQL_PWD='{}' mysql -u {} {}\" < {}",
-                    docker_container,
-                    db_password,
-                    db_user,
-                    db_name,
-                    path.display(),
+                    "docker exec -i -e MYSQL_PWD {} mysql -u {} {} < {}",
+                    quote_shell_argument(docker_container),
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(db_name),
+                    quote_shell_argument(&path.to_string_lossy()),
+                );
+
+                self.execute_command_with_env(&command, &[("MYSQL_PWD", db_password.clone())])
+                    .await?;
+            }
+
+            Some(BackupParams::Redis { rdb_path, .. }) => {
+                let rdb_path = rdb_path.as_ref().ok_or(format!(
+                    "Element '{}': rdb_path is required to restore a Redis backup",
+                    self.element_title
+                ))?;
+
+                info!("Restoring Redis: rdb_path={}", rdb_path);
+                warn!("Redis must be stopped during the restore and started afterwards to load {}", rdb_path);
+
+                fs::copy(path, rdb_path).map_err(|e| {
+                    format!("Failed to copy {} to {}: {}", path.display(), rdb_path, e)
+                })?;
+            }
+
+            Some(BackupParams::RedisDocker {
+                docker_container,
+                rdb_path,
+                ..
+            }) => {
+                let rdb_path = rdb_path.as_deref().unwrap_or(REDIS_DEFAULT_RDB_PATH);
+
+                info!(
+                    "Restoring Redis Docker: docker_container={}, rdb_path={}",
+                    docker_container, rdb_path
                 );
 
-                self.execute_command(&command).await;
+                let mut stop = Command::new("docker");
+                stop.args(["stop", docker_container]);
+                self.execute_program(stop).await?;
+
+                let mut copy = Command::new("docker");
+                copy.arg("cp").arg(path).arg(format!("{}:{}", docker_container, rdb_path));
+                let copied = self.execute_program(copy).await;
+
+                // The container is started again even if the copy failed, so Redis is not left stopped.
+                let mut start = Command::new("docker");
+                start.args(["start", docker_container]);
+                let started = self.execute_program(start).await;
+
+                copied.and(started)?;
+            }
+
+            Some(BackupParams::Sqlite { db_path }) => {
+                info!("Restoring SQLite: path={}", db_path);
+
+                let mut command = Command::new("sqlite3");
+                command
+                    .arg(db_path)
+                    .arg(format!(".restore {}", quote_sqlite_argument(&path.to_string_lossy())));
+
+                self.execute_program(command).await?;
+            }
+
+            Some(BackupParams::S3Sync { .. }) => {
+                return Err(format!(
+                    "Element '{}' is an S3 sync element and cannot be restored from a file",
+                    self.element_title
+                ));
             }
 
             None => {
@@ -475,41 +924,936 @@ impl Elements {
         Ok(())
     }
 
-    /// Executes a shell command asynchronously to perform a backup.
+    /// Reserves a unique path for a new backup file.
     ///
-    /// This function runs a shell command (using `sh -c`) to execute the backup operation.
-    /// It captures the output and checks whether the command succeeded or failed, printing
-    /// appropriate messages based on the result.
+    /// The file is named `element-title-YYYY-MM-DD_HH-MM-SS.extension`. If a file with this name already exists,
+    /// a sequence suffix is added (`element-title-YYYY-MM-DD_HH-MM-SS-1.extension`, and so on). The file is created
+    /// atomically, so concurrent backups of the same element never receive the same path.
     ///
     /// # Arguments
-    /// - `command` - The shell command to execute. This should be a valid shell command string
-    ///   that performs the backup operation.
+    /// - `path` - The directory where the backup file will be stored.
+    /// - `now` - The formatted timestamp of the backup.
+    /// - `extension` - The extension of the backup file.
     ///
     /// # Returns
-    /// - `()` - This function does not return any value. It logs success or failure messages
-    ///   based on the command's execution status.
+    /// - `Ok(PathBuf)` - The path of the reserved (empty) backup file.
+    /// - `Err(String)` - An error message if the file cannot be created.
+    fn get_backup_path(&self, path: &Path, now: &str, extension: &str) -> Result<PathBuf, String> {
+        let mut sequence = 0;
+
+        loop {
+            let file_name = if sequence == 0 {
+                format!("{}-{}.{}", self.element_title, now, extension)
+            } else {
+                format!("{}-{}-{}.{}", self.element_title, now, sequence, extension)
+            };
+            let file_path = path.join(&file_name);
+
+            match OpenOptions::new().write(true).create_new(true).open(&file_path) {
+                Ok(_) => return Ok(file_path),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => sequence += 1,
+                Err(e) => {
+                    return Err(format!(
+                        "Failed to create backup file {}: {}",
+                        file_path.display(),
+                        e
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Creates the next archive of the incremental chain of a folder.
     ///
-    /// # Behavior
-    /// - If the command executes successfully (i.e., the exit status is `0`), it logs a success message.
-    /// - If the command fails, it logs an error message along with the `stderr` output to provide error details.
+    /// The archive is created with `tar --listed-incremental`, which records the state of the folder in a snapshot
+    /// file in `<path>/incremental`. The first archive of a chain is a full backup (`.full.tar.gz`), the following
+    /// ones only contain the changes since the previous archive (`.inc1.tar.gz`, `.inc2.tar.gz`, ...). A new chain is
+    /// started once the full backup is older than `interval_days`.
+    ///
+    /// # Arguments
+    /// - `path` - The local backup directory of the element.
+    /// - `now` - The formatted timestamp of the backup.
+    /// - `target_path` - The folder to back up.
+    /// - `interval_days` - The number of days after which a new full backup is started.
+    ///
+    /// # Returns
+    /// - `Ok(PathBuf)` - The path of the new archive.
+    /// - `Err(String)` - An error message if `tar` fails or the state of the chain cannot be saved.
+    async fn create_incremental_archive(
+        &self,
+        path: &Path,
+        now: &str,
+        target_path: &str,
+        interval_days: u64,
+    ) -> Result<PathBuf, String> {
+        let backup = prepare_incremental_backup(path, &self.element_title, now, interval_days)?;
+        let file_path = self.get_backup_path(path, now, &backup.level.extension())?;
+
+        let mut command = Command::new("tar");
+        command
+            .arg("-czf")
+            .arg(&file_path)
+            .arg(format!("--listed-incremental={}", backup.snapshot_file.display()))
+            .arg("-C")
+            .arg(target_path)
+            .arg(".");
+
+        let result = self.execute_program(command).await;
+
+        if let Err(e) = result {
+            backup.abort();
+            let _ = fs::remove_file(&file_path);
+            return Err(e);
+        }
+
+        backup.commit()?;
+
+        Ok(file_path)
+    }
+
+    /// Creates a hardlinked snapshot of a folder in the local snapshots directory.
+    ///
+    /// The snapshot is created with `rsync -a --delete` in `<path>/snapshots/element-title-YYYY-MM-DD_HH-MM-SS`.
+    /// Unchanged files are hardlinked to the previous snapshot (`--link-dest`), so every additional snapshot only
+    /// consumes disk space for the changed files.
+    ///
+    /// The snapshot is first written to a directory with the `.partial` suffix, which is renamed once `rsync`
+    /// succeeds and removed if it fails. Partial snapshots are ignored by `get_local_snapshots`, so an incomplete
+    /// snapshot is never used as the base of the next one or counted by retention.
+    ///
+    /// # Arguments
+    /// - `path` - The local backup directory of the element.
+    /// - `now` - The formatted timestamp of the backup.
+    /// - `target_path` - The folder to snapshot.
+    ///
+    /// # Returns
+    /// - `Ok(PathBuf)` - The path of the new snapshot.
+    /// - `Err(String)` - An error message if the snapshot directory cannot be created or `rsync` fails.
+    async fn create_local_snapshot(
+        &self,
+        path: &Path,
+        now: &str,
+        target_path: &str,
+    ) -> Result<PathBuf, String> {
+        let snapshots_dir = path.join(LOCAL_SNAPSHOTS_DIR);
+        fs::create_dir_all(&snapshots_dir).map_err(|e| {
+            format!(
+                "Failed to create snapshots dir {}: {}",
+                snapshots_dir.display(),
+                e
+            )
+        })?;
+
+        let latest = get_local_snapshots(&snapshots_dir)
+            .map_err(|e| format!("Failed to read snapshots dir {}: {}", snapshots_dir.display(), e))?
+            .pop()
+            .and_then(|(snapshot, _)| fs::canonicalize(snapshot).ok());
+
+        let mut sequence = 0;
+        let (snapshot, partial) = loop {
+            let name = if sequence == 0 {
+                format!("{}-{}", self.element_title, now)
+            } else {
+                format!("{}-{}-{}", self.element_title, now, sequence)
+            };
+            let snapshot = snapshots_dir.join(&name);
+            let partial = snapshots_dir.join(format!("{}{}", name, PARTIAL_SNAPSHOT_SUFFIX));
+
+            if snapshot.exists() {
+                sequence += 1;
+                continue;
+            }
+
+            match fs::create_dir(&partial) {
+                Ok(_) => break (snapshot, partial),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => sequence += 1,
+                Err(e) => {
+                    return Err(format!(
+                        "Failed to create snapshot dir {}: {}",
+                        partial.display(),
+                        e
+                    ))
+                }
+            }
+        };
+
+        let mut args = vec![String::from("-a"), String::from("--delete")];
+        if let Some(latest) = &latest {
+            info!("Linking unchanged files to snapshot {}", latest.display());
+            args.push(format!("--link-dest={}", latest.display()));
+        }
+        args.push(format!("{}/", target_path.trim_end_matches('/')));
+        args.push(format!("{}/", partial.display()));
+
+        let mut command = Command::new("rsync");
+        command.args(&args);
+
+        let result = self
+            .execute_program(command)
+            .await
+            .and_then(|_| {
+                fs::rename(&partial, &snapshot).map_err(|e| {
+                    format!("Failed to rename snapshot {}: {}", partial.display(), e)
+                })
+            });
+
+        if let Err(e) = result {
+            if let Err(remove_error) = fs::remove_dir_all(&partial) {
+                warn!(
+                    "Failed to remove partial snapshot {}: {}",
+                    partial.display(),
+                    remove_error
+                );
+            }
+            return Err(format!("Failed to create snapshot {}: {}", snapshot.display(), e));
+        }
+
+        info!("Created local snapshot {}", snapshot.display());
+
+        Ok(snapshot)
+    }
+
+    /// Returns the name of the S3 keys used for the lock of the element (`lock_period_minutes`).
+    ///
+    /// The lock is read and deleted, which a write-only upload key (`s3_credentials`) cannot do. So
+    /// `s3_lock_credentials` is used if set, then `s3_prune_credentials`, and `s3_credentials` only as the last
+    /// resort.
+    pub fn lock_bucket_credentials(&self) -> Option<&String> {
+        self.s3_lock_credentials
+            .as_ref()
+            .or(self.s3_prune_credentials.as_ref())
+            .or(self.s3_credentials.as_ref())
+    }
+
+    /// Checks whether the element is a folder with incremental backups (`incremental: true`).
+    pub fn is_incremental(&self) -> bool {
+        matches!(&self.params, Some(BackupParams::Folder { incremental: true, .. }))
+    }
+
+    /// Checks whether the element discovers its databases at run time (`all_databases: auto`).
+    pub fn discovers_databases(&self) -> bool {
+        matches!(
+            &self.params,
+            Some(BackupParams::Postgresql { all_databases: Some(DatabaseDiscovery::Auto), .. })
+                | Some(BackupParams::PostgresqlDocker { all_databases: Some(DatabaseDiscovery::Auto), .. })
+                | Some(BackupParams::MySQL { all_databases: Some(DatabaseDiscovery::Auto), .. })
+                | Some(BackupParams::MySQLDocker { all_databases: Some(DatabaseDiscovery::Auto), .. })
+        )
+    }
+
+    /// Checks the parameters of the element that cannot be checked while the configuration is deserialized.
+    ///
+    /// `db_name` of `postgresql`, `postgresql_docker`, `mysql` and `mysql_docker` elements may only be omitted
+    /// with `all_databases: auto`. Incremental folder elements require the `tar_gz` archive format and cannot be
+    /// combined with `local_snapshots`.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the element is valid.
+    /// - `Err(String)` - An error message describing the invalid parameter.
+    ///
+    /// # Example
+    /// ```rust
+    /// for element in &settings.elements {
+    ///     element.validate()?;
+    /// }
+    /// ```
+    pub fn validate(&self) -> Result<(), String> {
+        // The folder is also a path below the root of local and SFTP storages, so it must not leave the root.
+        if self.s3_folder.split('/').any(|component| component == "..") {
+            return Err(format!(
+                "Element '{}': s3_folder must not contain '..'",
+                self.element_title
+            ));
+        }
+
+        match &self.params {
+            Some(BackupParams::Postgresql { db_name, all_databases: None, .. })
+            | Some(BackupParams::PostgresqlDocker { db_name, all_databases: None, .. })
+            | Some(BackupParams::MySQL { db_name, all_databases: None, .. })
+            | Some(BackupParams::MySQLDocker { db_name, all_databases: None, .. })
+                if db_name.trim().is_empty() =>
+            {
+                Err(format!(
+                    "Element '{}' requires db_name unless all_databases is set to auto",
+                    self.element_title
+                ))
+            }
+            Some(BackupParams::Folder {
+                archive_format,
+                local_snapshots,
+                incremental: true,
+                ..
+            }) if *local_snapshots || matches!(archive_format, ArchiveFormat::Zip) => Err(format!(
+                "Element '{}': incremental backups require the tar_gz archive format and cannot be combined with local_snapshots",
+                self.element_title
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that an S3 sync element does not mirror a prefix of the backup bucket into itself.
+    ///
+    /// If the source is the backup bucket, the `s3_folder` must not overlap the `source_prefix` (one containing
+    /// the other), since every run would copy the previous copies again and deleting outdated objects would remove
+    /// the source objects. Other elements are always valid.
+    ///
+    /// # Arguments
+    /// - `s3_endpoint` - The endpoint of the backup bucket.
+    /// - `s3_bucket` - The name of the backup bucket.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the element is valid.
+    /// - `Err(String)` - An error message describing the overlap.
+    pub fn validate_sync_target(&self, s3_endpoint: &str, s3_bucket: &str) -> Result<(), String> {
+        let Some(BackupParams::S3Sync {
+            source_endpoint,
+            source_bucket,
+            source_prefix,
+            ..
+        }) = &self.params
+        else {
+            return Ok(());
+        };
+
+        if source_bucket != s3_bucket || source_endpoint.trim_end_matches('/') != s3_endpoint.trim_end_matches('/') {
+            return Ok(());
+        }
+
+        let prefix = |folder: &str| match folder.trim_matches('/') {
+            "" => String::new(),
+            folder => format!("{}/", folder),
+        };
+        let (source, target) = (prefix(source_prefix), prefix(&self.s3_folder));
+
+        if source.starts_with(&target) || target.starts_with(&source) {
+            return Err(format!(
+                "Element '{}': s3_folder '{}' overlaps source_prefix '{}' of the same bucket",
+                self.element_title, self.s3_folder, source_prefix
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Expands an element with `all_databases: auto` into one element per database of the server.
+    ///
+    /// The databases are queried from the server (the maintenance and template databases of PostgreSQL and the
+    /// system databases of MySQL are skipped). Elements without discovery are returned as they are.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<Elements>)` - The elements to back up.
+    /// - `Err(String)` - An error message if the databases cannot be listed.
+    ///
+    /// # Example
+    /// ```rust
+    /// for element in element.expand_databases()? {
+    ///     element.perform_backup(&path).await?;
+    /// }
+    /// ```
+    pub fn expand_databases(&self) -> Result<Vec<Elements>, String> {
+        if !self.discovers_databases() {
+            return Ok(vec![self.clone()]);
+        }
+
+        let databases = self.list_databases()?;
+        info!(
+            "Discovered databases for {}: {:?}",
+            self.element_title, databases
+        );
+
+        Ok(self.expand_for_databases(&databases))
+    }
+
+    /// Creates one element per database from an element with `all_databases: auto`.
+    ///
+    /// Each element is named `element-title-database`, stores its backups in `s3_folder/database` and backs up
+    /// only its database. The discovery is disabled for the created elements.
+    ///
+    /// The names come from the server or from S3 and end up in commands, paths and S3 keys, so names that are not
+    /// accepted by `is_safe_database_name` are skipped with a warning.
+    ///
+    /// # Arguments
+    /// - `databases` - The names of the databases.
     ///
     /// # Example
     /// ```rust
-    /// element.execute_command(&command).await;
+    /// let elements = element.expand_for_databases(&[String::from("shop")]);
     /// ```
-    async fn execute_command(&self, command: &str) {
-        let output = match Command::new("sh").arg("-c").arg(command).output() {
-            Ok(o) => o,
-            Err(e) => {
-                error!("Failed to execute backup command '{}': {}", command, e);
-                return;
+    pub fn expand_for_databases(&self, databases: &[String]) -> Vec<Elements> {
+        databases
+            .iter()
+            .filter(|database| {
+                let safe = is_safe_database_name(database);
+                if !safe {
+                    warn!(
+                        "Skipping database {:?} of {}: only letters, digits, '_', '.' and '-' are allowed",
+                        database, self.element_title
+                    );
+                }
+                safe
+            })
+            .map(|database| {
+                let mut element = self.clone();
+                element.element_title = format!("{}-{}", self.element_title, database);
+                element.s3_folder = format!("{}/{}", self.s3_folder.trim_end_matches('/'), database);
+
+                match &mut element.params {
+                    Some(BackupParams::Postgresql { db_name, all_databases, .. })
+                    | Some(BackupParams::PostgresqlDocker { db_name, all_databases, .. })
+                    | Some(BackupParams::MySQL { db_name, all_databases, .. })
+                    | Some(BackupParams::MySQLDocker { db_name, all_databases, .. }) => {
+                        *db_name = database.clone();
+                        *all_databases = None;
+                    }
+                    _ => {}
+                }
+
+                element
+            })
+            .collect()
+    }
+
+    /// Queries the list of databases from the server of the element.
+    fn list_databases(&self) -> Result<Vec<String>, String> {
+        let (command, env, system_databases) = match &self.params {
+            Some(BackupParams::Postgresql {
+                db_host,
+                db_port,
+                db_name,
+                db_user,
+                db_password,
+                client_container,
+                ..
+            }) => {
+                let db_host = db_host.clone().unwrap_or(String::from("localhost"));
+                let db_name = if db_name.is_empty() { "postgres" } else { db_name };
+                let query = format!(
+                    "psql -U {} -h {} -p {} -d {} -tAc '{}'",
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(&db_host),
+                    db_port,
+                    quote_shell_argument(db_name),
+                    PG_LIST_DATABASES_QUERY
+                );
+
+                let command = if *client_container {
+                    let client =
+                        get_postgres_client(&db_host, *db_port, db_name, db_user, db_password)?;
+                    get_docker_run_command(&client.image, "PGPASSWORD", &query, false)
+                } else {
+                    query
+                };
+
+                (command, ("PGPASSWORD", db_password.clone()), &PG_SYSTEM_DATABASES[..])
+            }
+            Some(BackupParams::PostgresqlDocker {
+                docker_container,
+                db_name,
+                db_user,
+                db_password,
+                ..
+            }) => {
+                let db_name = if db_name.is_empty() { "postgres" } else { db_name };
+
+                let command = format!(
+                    "docker exec -e PGPASSWORD {} psql -U {} -d {} -tAc '{}'",
+                    quote_shell_argument(docker_container),
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(db_name),
+                    PG_LIST_DATABASES_QUERY
+                );
+
+                (command, ("PGPASSWORD", db_password.clone()), &PG_SYSTEM_DATABASES[..])
+            }
+            Some(BackupParams::MySQL {
+                db_host,
+                db_port,
+                db_user,
+                db_password,
+                client_container,
+                ..
+            }) => {
+                let db_host = db_host.clone().unwrap_or(String::from("localhost"));
+                let query = format!(
+                    "mysql -u {} -h {} -P {} -N -e 'SHOW DATABASES'",
+                    quote_shell_argument(db_user),
+                    quote_shell_argument(&db_host),
+                    db_port
+                );
+
+                let command = if *client_container {
+                    let client = get_mysql_client(&db_host, *db_port, db_user, db_password)?;
+                    get_docker_run_command(
+                        &client.image,
+                        "MYSQL_PWD",
+                        &query.replacen("mysql", client.client, 1),
+                        false,
+                    )
+                } else {
+                    query
+                };
+
+                (command, ("MYSQL_PWD", db_password.clone()), &MYSQL_SYSTEM_DATABASES[..])
+            }
+            Some(BackupParams::MySQLDocker {
+                docker_container,
+                db_user,
+                db_password,
+                ..
+            }) => {
+                let command = format!(
+                    "docker exec -e MYSQL_PWD {} mysql -u {} -N -e 'SHOW DATABASES'",
+                    quote_shell_argument(docker_container),
+                    quote_shell_argument(db_user)
+                );
+
+                (command, ("MYSQL_PWD", db_password.clone()), &MYSQL_SYSTEM_DATABASES[..])
+            }
+            _ => {
+                return Err(format!(
+                    "Element '{}' does not support database discovery",
+                    self.element_title
+                ))
             }
         };
 
+        let output = run_query(&command, &[env])
+            .map_err(|e| format!("Failed to list databases for {}: {}", self.element_title, e))?;
+
+        Ok(output
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !system_databases.contains(line))
+            .map(String::from)
+            .collect())
+    }
+
+    /// Creates the S3 bucket instance for the source of an S3 sync element.
+    ///
+    /// # Returns
+    /// - `Some(Ok(Bucket))` if the element is an S3 sync element and the source bucket is created.
+    /// - `Some(Err(String))` if the element is an S3 sync element, but the source bucket cannot be created.
+    /// - `None` if the element is not an S3 sync element.
+    ///
+    /// # Example
+    /// ```rust
+    /// if let Some(source) = element.get_sync_source() {
+    ///     let (source_bucket, source_prefix) = source?;
+    /// }
+    /// ```
+    pub fn get_sync_source(&self) -> Option<Result<(Bucket, &String), String>> {
+        match &self.params {
+            Some(BackupParams::S3Sync {
+                source_endpoint,
+                source_region,
+                source_bucket,
+                source_access,
+                source_secret,
+                source_path_style,
+                source_prefix,
+            }) => {
+                info!(
+                    "Syncing S3: endpoint={}, bucket={}, prefix={}",
+                    source_endpoint, source_bucket, source_prefix
+                );
+
+                Some(
+                    create_bucket(
+                        source_endpoint,
+                        source_region,
+                        source_bucket,
+                        source_access,
+                        source_secret,
+                        source_path_style,
+                    )
+                    .map(|bucket| (bucket, source_prefix))
+                    .ok_or_else(|| {
+                        format!(
+                            "Failed to create source bucket for element '{}'",
+                            self.element_title
+                        )
+                    }),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Executes a shell command (using `sh -c`) with additional environment variables and waits for it to finish.
+    ///
+    /// The command runs on a blocking thread (see `get_command_output`). The shell is only used for database commands that redirect a dump into or out of a file. Other programs are
+    /// run without a shell by `execute_program`.
+    ///
+    /// Secrets such as database passwords are passed this way instead of being embedded in the command, so they
+    /// appear neither in the process list nor in the logged command.
+    ///
+    /// # Arguments
+    /// - `command` - The shell command to execute.
+    /// - `env` - The environment variables of the command (e.g., `("PGPASSWORD", password)`).
+    ///
+    /// # Returns
+    /// - `Ok(())` - If the command exits successfully.
+    /// - `Err(String)` - An error message with the `stderr` output of the command if it cannot be started or fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// element.execute_command_with_env("pg_dump my_db > backup.sql", &[("PGPASSWORD", password)]).await?;
+    /// ```
+    async fn execute_command_with_env(&self, command: &str, env: &[(&str, String)]) -> Result<(), String> {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command).envs(env.iter().cloned());
+
+        let output = get_command_output(shell)
+            .await
+            .map_err(|e| format!("Failed to execute backup command '{}': {}", command, e))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Command failed! Error: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    /// Executes a program directly, without a shell, and waits for it to finish on a blocking thread.
+    ///
+    /// Unlike `execute_command_with_env`, every argument is passed to the program as is, so paths and names from the
+    /// configuration or the archive (e.g., `it's a folder`) are never interpreted by a shell. Unless the standard
+    /// output of the command is redirected (e.g., into the backup file), it is discarded.
+    ///
+    /// # Arguments
+    /// - `command` - The program with its arguments and environment variables (e.g., `Command::new("tar")`).
+    ///
+    /// # Returns
+    /// - `Ok(())` - If the program exits successfully.
+    /// - `Err(String)` - An error message with the error output of the program if it cannot be started or fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut command = Command::new("chown");
+    /// command.args(["-R", "--", "www-data", target_path]);
+    /// element.execute_program(command).await?;
+    /// ```
+    async fn execute_program(&self, command: Command) -> Result<(), String> {
+        let program = command.get_program().to_string_lossy().to_string();
+        let output = get_command_output(command)
+            .await
+            .map_err(|e| format!("Failed to execute {}: {}", program, e))?;
+
         if output.status.success() {
+            Ok(())
         } else {
-            error!("Backup failed!");
-            error!("Error: {}", String::from_utf8_lossy(&output.stderr));
+            Err(format!(
+                "{} failed! Error: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+/// Runs a command on a blocking thread and collects its output.
+///
+/// Dumps, archives and restores can take a long time, so they must not occupy the runtime while other elements are
+/// backed up at the same time (see `run_parallel_backups`).
+async fn get_command_output(mut command: Command) -> io::Result<Output> {
+    tokio::task::spawn_blocking(move || command.output())
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Returns the environment of `redis-cli` with the Redis password, if any.
+///
+/// `REDISCLI_AUTH` is used instead of `-a`, so the password is not part of the command line. It is set on the
+/// spawned process (or passed through `docker exec -e REDISCLI_AUTH`), never embedded in a command.
+fn get_redis_env(db_password: &Option<String>) -> Vec<(&'static str, String)> {
+    match db_password {
+        Some(password) => vec![("REDISCLI_AUTH", password.clone())],
+        None => Vec::new(),
+    }
+}
+
+/// Returns the authentication arguments of `mongodump` and `mongorestore` and their environment.
+///
+/// The password is passed in `REBACK_MONGO_PASSWORD` and referenced as `"$REBACK_MONGO_PASSWORD"`, so it is not
+/// part of the logged command or the command line of the shell. The shell expands it into the arguments of the
+/// MongoDB tool, so it is still visible in the process list of the host (or the container) while the tool runs.
+/// Without a user, no authentication is used.
+fn get_mongo_auth(
+    db_user: &Option<String>,
+    db_password: &Option<String>,
+) -> (String, Vec<(&'static str, String)>) {
+    match db_user {
+        Some(user) => (
+            format!(
+                "--username {} --password \"${}\" --authenticationDatabase admin ",
+                quote_shell_argument(user),
+                MONGO_PASSWORD_ENV
+            ),
+            vec![(MONGO_PASSWORD_ENV, db_password.clone().unwrap_or_default())],
+        ),
+        None => (String::new(), Vec::new()),
+    }
+}
+
+/// Returns the `docker exec` command that runs a MongoDB tool inside a container.
+///
+/// With authentication, the environment variables are passed to the container (`-e REBACK_MONGO_PASSWORD` without
+/// a value) and the tool is run through `sh -c` inside the container, so the password variable is expanded there.
+/// With `interactive`, the standard input is attached (`-i`) to read the archive of a restore.
+fn get_mongo_docker_command(
+    docker_container: &str,
+    command: &str,
+    env: &[(&'static str, String)],
+    interactive: bool,
+) -> String {
+    let mut args = vec![String::from("docker exec")];
+
+    if interactive {
+        args.push(String::from("-i"));
+    }
+
+    for (name, _) in env {
+        args.push(format!("-e {}", name));
+    }
+
+    args.push(quote_shell_argument(docker_container));
+
+    if env.is_empty() {
+        args.push(command.to_string());
+    } else {
+        args.push(format!("sh -c {}", quote_shell_argument(command)));
+    }
+
+    args.join(" ")
+}
+
+/// Checks whether a discovered database name only contains letters, digits, `_`, `.` and `-` and no `..`, so it
+/// can be used in a command, a file name and an S3 key without escaping its folder.
+fn is_safe_database_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && !name.contains("..")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Quotes an argument of a dot-command of the `sqlite3` shell (e.g., the file of `.backup`).
+///
+/// Double-quoted arguments are unescaped by the shell, so backslashes and double quotes are escaped.
+fn quote_sqlite_argument(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn postgres_element() -> Elements {
+        serde_json::from_str(
+            r#"{
+                "element_title": "pg",
+                "s3_folder": "db/pg/",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30,
+                "params": {
+                    "type": "postgresql",
+                    "db_port": 5432,
+                    "db_user": "postgres",
+                    "db_password": "secret",
+                    "all_databases": "auto"
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn folder_element(params: &str) -> Elements {
+        serde_json::from_str(&format!(
+            r#"{{
+                "element_title": "configs",
+                "s3_folder": "configs",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30,
+                "params": {{"type": "folder", "target_path": "/etc", {}}}
+            }}"#,
+            params
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_rejects_incremental_folders_with_zip_or_local_snapshots() {
+        assert!(folder_element(r#""incremental": true"#).validate().is_ok());
+        assert!(folder_element(r#""incremental": true, "archive_format": "zip""#)
+            .validate()
+            .is_err());
+        assert!(folder_element(r#""incremental": true, "local_snapshots": true"#)
+            .validate()
+            .is_err());
+        assert!(folder_element(r#""archive_format": "zip", "local_snapshots": true"#)
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_folders_leaving_the_storage_root() {
+        let mut element = folder_element(r#""incremental": false"#);
+        assert!(element.validate().is_ok());
+
+        element.s3_folder = String::from("configs/../../etc");
+        assert!(element.validate().is_err());
+
+        element.s3_folder = String::from("..");
+        assert!(element.validate().is_err());
+
+        element.s3_folder = String::from("configs/..backup");
+        assert!(element.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_sync_target_rejects_overlapping_folders_of_the_same_bucket() {
+        let element = |s3_folder: &str, source_prefix: &str| -> Elements {
+            serde_json::from_value(serde_json::json!({
+                "element_title": "mirror",
+                "s3_folder": s3_folder,
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30,
+                "params": {
+                    "type": "s3_sync",
+                    "source_endpoint": "https://s3.example.com/",
+                    "source_region": "us-east-1",
+                    "source_bucket": "backups",
+                    "source_access": "access",
+                    "source_secret": "secret",
+                    "source_path_style": "path",
+                    "source_prefix": source_prefix
+                }
+            }))
+            .unwrap()
+        };
+        let validate = |element: Elements| element.validate_sync_target("https://s3.example.com", "backups");
+
+        assert!(validate(element("mirror", "uploads")).is_ok());
+        assert!(validate(element("uploads-mirror", "uploads")).is_ok());
+        assert!(validate(element("uploads/mirror", "uploads")).is_err());
+        assert!(validate(element("/uploads/", "uploads")).is_err());
+        assert!(validate(element("mirror", "")).is_err());
+        assert!(validate(element("mirror", "mirror/uploads")).is_err());
+        assert!(element("uploads/mirror", "uploads")
+            .validate_sync_target("https://s3.example.com", "other")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn perform_backup_fails_and_removes_the_file_when_the_dump_fails() {
+        let dir = std::env::temp_dir().join(format!("reback-failed-dump-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Nothing listens on port 1, so `pg_dump` fails (as does the shell if `pg_dump` is not installed).
+        let mut element = postgres_element().expand_for_databases(&[String::from("shop")]).remove(0);
+        if let Some(BackupParams::Postgresql { db_host, db_port, .. }) = &mut element.params {
+            *db_host = Some(String::from("127.0.0.1"));
+            *db_port = 1;
+        }
+
+        let result = element.perform_backup(&dir).await;
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(files, 0);
+    }
+
+    #[test]
+    fn expand_for_databases_creates_one_element_per_database() {
+        let elements = postgres_element().expand_for_databases(&[String::from("shop"), String::from("blog_v2.1")]);
+
+        let titles: Vec<_> = elements.iter().map(|element| element.element_title.as_str()).collect();
+        let folders: Vec<_> = elements.iter().map(|element| element.s3_folder.as_str()).collect();
+
+        assert_eq!(titles, ["pg-shop", "pg-blog_v2.1"]);
+        assert_eq!(folders, ["db/pg/shop", "db/pg/blog_v2.1"]);
+        assert!(!elements[0].discovers_databases());
+        assert!(matches!(
+            &elements[0].params,
+            Some(BackupParams::Postgresql { db_name, .. }) if db_name == "shop"
+        ));
+    }
+
+    #[test]
+    fn expand_for_databases_skips_unsafe_names() {
+        let databases = [
+            "x;curl evil|sh",
+            "../x",
+            "..",
+            ".",
+            "a/b",
+            "it's",
+            "$(id)",
+            "",
+            "shop",
+        ]
+        .map(String::from);
+
+        let elements = postgres_element().expand_for_databases(&databases);
+
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].element_title, "pg-shop");
+    }
+
+    #[test]
+    fn dump_command_quotes_the_connection_arguments() {
+        let mut element = postgres_element().expand_for_databases(&[String::from("shop")]).remove(0);
+        if let Some(BackupParams::Postgresql { db_name, db_user, db_host, .. }) = &mut element.params {
+            *db_name = String::from("shop'; rm -rf /");
+            *db_user = String::from("admin$(id)");
+            *db_host = Some(String::from("db;reboot"));
         }
+
+        let dump = element.get_dump_command().unwrap();
+
+        assert_eq!(
+            dump.command,
+            "pg_dump -U 'admin$(id)' -h 'db;reboot' -p 5432 'shop'\\''; rm -rf /'"
+        );
+    }
+
+    #[test]
+    fn docker_dump_command_quotes_the_container() {
+        let element: Elements = serde_json::from_str(
+            r#"{
+                "element_title": "pg",
+                "s3_folder": "pg",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30,
+                "params": {
+                    "type": "postgresql_docker",
+                    "docker_container": "db;reboot",
+                    "db_name": "shop",
+                    "db_user": "postgres",
+                    "db_password": "secret"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let dump = element.get_dump_command().unwrap();
+
+        assert_eq!(dump.command, "docker exec -e PGPASSWORD 'db;reboot' pg_dump -U 'postgres' 'shop'");
+    }
+
+    #[test]
+    fn mongo_docker_command_quotes_the_command_for_the_container_shell() {
+        let (auth_args, env) = get_mongo_auth(&Some(String::from("backup user")), &Some(String::from("secret")));
+        let command = get_mongo_docker_command("mongo", &format!("mongodump {}--archive", auth_args), &env, false);
+
+        assert_eq!(
+            command,
+            r#"docker exec -e REBACK_MONGO_PASSWORD 'mongo' sh -c 'mongodump --username '\''backup user'\'' --password "$REBACK_MONGO_PASSWORD" --authenticationDatabase admin --archive'"#
+        );
     }
 }
@@ -1,3 +1,4 @@
 pub mod settings;
 pub mod elements;
-mod backup_params;
\ No newline at end of file
+pub mod backup_params;
+pub mod element_template;
\ No newline at end of file
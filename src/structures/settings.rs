@@ -1,7 +1,9 @@
+use crate::structures::element_template::ElementTemplate;
 use crate::structures::elements::Elements;
 use s3::creds::Credentials;
 use s3::{Bucket, Region};
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::{env, fs, io};
 use log::error;
 
@@ -17,8 +19,22 @@ use log::error;
 /// - `s3_access` - The access key for the S3 bucket.
 /// - `s3_secret` - The secret key for the S3 bucket.
 /// - `s3_path_style` - Defines the addressing style for the S3 bucket. Can be either `Path` or `VirtualHost`.
+/// - `s3_credentials` - Optional named S3 key pairs that elements can reference instead of the default keys.
+/// - `timezone` - Optional IANA timezone (e.g., `Europe/Moscow`) for backup names, schedules and retention.
+/// - `glacier_restore` - Parameters for retrieving backups stored in the Glacier or Deep Archive storage classes.
 /// - `backup_dir` - The directory path where backups are temporarily stored before uploading.
 /// - `elements` - A collection of elements to be processed for backup.
+/// - `templates` - Element templates that are expanded into `elements` when the settings are loaded.
+/// - `disable_version_check` - Disables the check for a newer release of reback before backups.
+/// - `notifications` - Channels that receive the results of backups and restores.
+/// - `storage` - The default storage targets of the elements. If empty, backups are stored in the S3 bucket.
+/// - `compression` - Optional compression of backup files before they are uploaded.
+/// - `encryption` - Optional encryption of backup files before they are uploaded.
+/// - `max_parallel_backups` - The maximum number of elements backed up at the same time by `reback backup`
+///   (default `1`).
+/// - `stream_backups` - Whether database dumps are uploaded to S3 while they are created, without a local file.
+/// - `lock_settle_seconds` - The number of seconds to wait before reading an element lock back after writing it
+///   (default `5`, see `acquire_s3_lock`).
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub s3_endpoint: String,
@@ -27,8 +43,27 @@ pub struct Settings {
     pub s3_access: String,
     pub s3_secret: String,
     pub s3_path_style: S3PathStyle,
+    #[serde(default)]
+    pub s3_credentials: HashMap<String, S3Credentials>,
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub glacier_restore: GlacierRestore,
     pub backup_dir: String,
     pub elements: Vec<Elements>,
+    #[serde(default)]
+    pub templates: Vec<ElementTemplate>,
+    #[serde(default)]
+    pub disable_version_check: bool,
+    #[serde(default)]
+    pub notifications: Notifications,
+    #[serde(default)]
+    pub storage: Vec<StorageTarget>,
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+    pub max_parallel_backups: Option<usize>,
+    #[serde(default)]
+    pub stream_backups: bool,
+    pub lock_settle_seconds: Option<u64>,
 }
 
 /// Defines the addressing style for S3 bucket operations.
@@ -39,19 +74,190 @@ pub struct Settings {
 ///
 /// This enum is serialized and deserialized with `kebab-case` naming conventions
 /// (e.g., `"path"` or `"virtual-host"`).
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum S3PathStyle {
     Path,
     VirtualHost,
 }
 
+/// Represents a named pair of S3 keys.
+///
+/// Elements reference these pairs by name, which allows using a restricted (e.g., write-only) key for
+/// uploads and a separate key with delete permissions only for pruning outdated backups.
+///
+/// # Fields
+/// - `s3_access` - The access key for the S3 bucket.
+/// - `s3_secret` - The secret key for the S3 bucket.
+#[derive(Debug, Deserialize)]
+pub struct S3Credentials {
+    pub s3_access: String,
+    pub s3_secret: String,
+}
+
+/// Defines how backups in the Glacier or Deep Archive storage classes are retrieved before a restore.
+///
+/// # Fields
+/// - `tier` - The retrieval tier used for the restore request.
+/// - `days` - The number of days the restored copy stays available.
+/// - `poll_interval_secs` - The interval in seconds between checks of the restore status.
+/// - `max_wait_hours` - The maximum number of hours to wait for the object to become retrievable.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GlacierRestore {
+    pub tier: GlacierTier,
+    pub days: u32,
+    pub poll_interval_secs: u64,
+    pub max_wait_hours: u64,
+}
+
+impl Default for GlacierRestore {
+    fn default() -> Self {
+        GlacierRestore {
+            tier: GlacierTier::Standard,
+            days: 1,
+            poll_interval_secs: 300,
+            max_wait_hours: 48,
+        }
+    }
+}
+
+/// Defines a storage target where backups are uploaded, pruned and restored from.
+///
+/// Backups are stored as `<s3_folder>/<file name>` in every target. The `s3_backup_retention_days` of the element
+/// applies to all targets.
+///
+/// # Variants
+/// - `S3` - The S3 bucket from the settings (with the `s3_credentials` of the element).
+/// - `Local` - A local or mounted directory (e.g., a NAS share).
+/// - `Sftp` - A directory on an SFTP server, accessed with the `sftp` client and key-based authentication.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StorageTarget {
+    S3,
+    Local {
+        path: String,
+    },
+    Sftp {
+        host: String,
+        port: Option<u16>,
+        user: String,
+        identity_file: Option<String>,
+        path: String,
+    },
+}
+
+/// Defines how backup files are compressed before they are uploaded.
+///
+/// The compression extension is appended to the file name (e.g., `.sql.zst`). Files that are already compressed
+/// (`.gz`, `.zip`, `.zst`) are left as they are.
+///
+/// # Variants
+/// - `None` - No compression (e.g., to disable the global compression for an element).
+/// - `Gzip` - gzip with an optional `level` from `0` to `9` (default `6`).
+/// - `Zstd` - zstd with an optional `level` from `1` to `22` (default `3`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    Gzip { level: Option<u32> },
+    Zstd { level: Option<i32> },
+}
+
+/// Defines how backup files are encrypted with [age](https://age-encryption.org) before they are uploaded.
+///
+/// Encrypted files get the `.age` extension and can also be decrypted with the `age` command-line tool.
+/// Either a passphrase or public keys (recipients) are used. With recipients, only the public keys are needed on
+/// the host that creates backups, and the private key (`identity_file`) is only needed for restores.
+///
+/// # Fields
+/// - `passphrase` - The passphrase used to encrypt and decrypt backups.
+/// - `passphrase_env` - The name of an environment variable containing the passphrase (instead of `passphrase`).
+/// - `recipients` - age public keys (`age1...`) the backups are encrypted to.
+/// - `identity_file` - The path to an age identity file with the private keys used to decrypt backups.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Encryption {
+    pub passphrase: Option<String>,
+    pub passphrase_env: Option<String>,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    pub identity_file: Option<String>,
+}
+
+/// Defines where and when notifications about backup and restore results are sent.
+///
+/// Every configured channel receives the same messages. Notifications are best-effort: a failed delivery is logged
+/// and never interrupts a backup or restore.
+///
+/// # Fields
+/// - `telegram_bot_token` - The token of the Telegram bot.
+/// - `telegram_chat_id` - The ID of the Telegram chat (e.g., `-1001234567890`).
+/// - `slack_webhook_url` - The URL of a Slack incoming webhook.
+/// - `discord_webhook_url` - The URL of a Discord webhook.
+/// - `webhook_url` - A URL that receives the results as JSON in a POST request.
+/// - `on_success` - Whether a notification is sent for every successful element.
+/// - `on_failure` - Whether a notification is sent for every failed element.
+/// - `summary` - Whether a summary is sent at the end of `backup` and `restore` runs.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    pub slack_webhook_url: Option<String>,
+    pub discord_webhook_url: Option<String>,
+    pub webhook_url: Option<String>,
+    pub on_success: bool,
+    pub on_failure: bool,
+    pub summary: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Notifications {
+            telegram_bot_token: None,
+            telegram_chat_id: None,
+            slack_webhook_url: None,
+            discord_webhook_url: None,
+            webhook_url: None,
+            on_success: false,
+            on_failure: true,
+            summary: true,
+        }
+    }
+}
+
+/// Defines the retrieval tier for objects in the Glacier or Deep Archive storage classes.
+///
+/// # Variants
+/// - `Expedited` - The fastest and most expensive tier (not available for Deep Archive).
+/// - `Standard` - The default tier.
+/// - `Bulk` - The slowest and cheapest tier.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlacierTier {
+    Expedited,
+    Standard,
+    Bulk,
+}
+
+impl GlacierTier {
+    /// Returns the tier name as expected by the S3 `RestoreObject` API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GlacierTier::Expedited => "Expedited",
+            GlacierTier::Standard => "Standard",
+            GlacierTier::Bulk => "Bulk",
+        }
+    }
+}
+
 impl Settings {
     /// Reads the application's configuration from a JSON file.
     ///
     /// This function attempts to read the `settings.json` file located in the same directory
     /// as the executable, deserialize its content into a `Settings` instance, and return it.
     /// If the file is not found or cannot be parsed, an appropriate error is returned.
+    /// Element templates are expanded and appended to `elements` (see `expand_templates`).
     ///
     /// # Returns
     /// - `Ok(Settings)` if the file is successfully read and parsed into a `Settings` instance.
@@ -61,6 +267,9 @@ impl Settings {
     /// - If the file cannot be found or read, an error of kind `io::ErrorKind::NotFound` is returned.
     /// - If the JSON cannot be deserialized, an error of kind `io::ErrorKind::InvalidData` is returned
     ///   with additional error details from the `serde_json` deserialization process.
+    /// - If an element template cannot be expanded, an error of kind `io::ErrorKind::InvalidData` is returned.
+    /// - If an element is invalid (see `Elements::validate` and `Elements::validate_sync_target`), an error of kind `io::ErrorKind::InvalidData` is
+    ///   returned.
     ///
     /// # Example
     /// ```rust
@@ -74,7 +283,7 @@ impl Settings {
 
         let file_content = fs::read_to_string(settings_path)?;
 
-        let settings: Settings = match serde_json::from_str(&file_content) {
+        let mut settings: Settings = match serde_json::from_str(&file_content) {
             Ok(data) => data,
             Err(err) => {
                 error!("Error parsing JSON file: {}", err);
@@ -82,9 +291,56 @@ impl Settings {
             }
         };
 
+        if let Err(err) = settings.expand_templates() {
+            error!("Error expanding element templates: {}", err);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+
+        for element in &settings.elements {
+            if let Err(err) = element
+                .validate()
+                .and_then(|_| element.validate_sync_target(&settings.s3_endpoint, &settings.s3_bucket))
+            {
+                error!("Invalid element: {}", err);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+        }
+
         Ok(settings)
     }
 
+    /// Expands the element templates and appends the generated elements to `elements`.
+    ///
+    /// The templates are consumed, so the method has no effect when called again. Element titles must stay
+    /// unique, because they are used for backup names, locks and command-line arguments.
+    ///
+    /// # Returns
+    /// - `Ok(())` if all templates are expanded.
+    /// - `Err(String)` if a template is invalid or a generated title is already used by another element.
+    ///
+    /// # Example
+    /// ```rust
+    /// settings.expand_templates()?;
+    /// ```
+    pub fn expand_templates(&mut self) -> Result<(), String> {
+        let mut titles: HashSet<String> = self
+            .elements
+            .iter()
+            .map(|element| element.element_title.clone())
+            .collect();
+
+        for template in std::mem::take(&mut self.templates) {
+            for element in template.expand()? {
+                if !titles.insert(element.element_title.clone()) {
+                    return Err(format!("Duplicate element title: {}", element.element_title));
+                }
+                self.elements.push(element);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Creates and initializes an S3 bucket instance.
     ///
     /// This function uses the configuration provided in the `Settings` structure
@@ -109,35 +365,106 @@ impl Settings {
     /// let bucket = settings.get_bucket().expect("Failed to create bucket");
     /// ```
     pub fn get_bucket(&self) -> Option<Bucket> {
-        let credentials = Credentials::new(
-            Some(&self.s3_access),
-            Some(&self.s3_secret),
-            None,
-            None,
-            None,
+        self.build_bucket(&self.s3_access, &self.s3_secret)
+    }
+
+    /// Resolves the S3 bucket instance for a named set of credentials.
+    ///
+    /// If `credentials` is `None`, a clone of the `default` bucket (created with the global keys) is returned.
+    /// Otherwise, the named key pair is looked up in `s3_credentials` and a new bucket instance is created with it.
+    ///
+    /// # Arguments
+    /// - `default` - The bucket instance created with the global S3 keys.
+    /// - `credentials` - The optional name of an entry in `s3_credentials`.
+    ///
+    /// # Returns
+    /// - `Some(Bucket)` if the bucket is successfully resolved.
+    /// - `None` if the named credentials do not exist or the bucket cannot be created.
+    ///
+    /// # Example
+    /// ```rust
+    /// let bucket = settings.resolve_bucket(&bucket, element.s3_credentials.as_ref());
+    /// ```
+    pub fn resolve_bucket(&self, default: &Bucket, credentials: Option<&String>) -> Option<Bucket> {
+        match credentials {
+            None => Some(default.clone()),
+            Some(name) => match self.s3_credentials.get(name) {
+                Some(creds) => self.build_bucket(&creds.s3_access, &creds.s3_secret),
+                None => {
+                    error!("Unknown S3 credentials: {}", name);
+                    None
+                }
+            },
+        }
+    }
+
+    /// Creates an S3 bucket instance with the given access and secret keys.
+    fn build_bucket(&self, access: &str, secret: &str) -> Option<Bucket> {
+        create_bucket(
+            &self.s3_endpoint,
+            &self.s3_region,
+            &self.s3_bucket,
+            access,
+            secret,
+            &self.s3_path_style,
         )
-        .map_err(|err| {
-            error!("Error creating credentials: {}", err);
-            err
-        })
-        .ok()?;
-
-        let region = Region::Custom {
-            region: self.s3_region.clone(),
-            endpoint: self.s3_endpoint.clone(),
-        };
+    }
+}
+
+/// Creates and initializes an S3 bucket instance from connection parameters.
+///
+/// # Arguments
+/// - `endpoint` - The endpoint URL for the S3-compatible storage.
+/// - `region` - The region of the S3 bucket.
+/// - `name` - The name of the S3 bucket.
+/// - `access` - The access key for the S3 bucket.
+/// - `secret` - The secret key for the S3 bucket.
+/// - `path_style` - The addressing style for the S3 bucket.
+///
+/// # Returns
+/// - `Some(Bucket)` if the bucket is successfully created and initialized.
+/// - `None` if there is an error during the bucket creation process. The error is logged.
+///
+/// # Example
+/// ```rust
+/// let bucket = create_bucket(endpoint, region, name, access, secret, &S3PathStyle::Path);
+/// ```
+pub fn create_bucket(
+    endpoint: &str,
+    region: &str,
+    name: &str,
+    access: &str,
+    secret: &str,
+    path_style: &S3PathStyle,
+) -> Option<Bucket> {
+    let credentials = Credentials::new(
+        Some(access),
+        Some(secret),
+        None,
+        None,
+        None,
+    )
+    .map_err(|err| {
+        error!("Error creating credentials: {}", err);
+        err
+    })
+    .ok()?;
 
-        let bucket_result = Bucket::new(self.s3_bucket.as_str(), region, credentials);
+    let region = Region::Custom {
+        region: region.to_string(),
+        endpoint: endpoint.to_string(),
+    };
 
-        match bucket_result {
-            Ok(bucket) => match self.s3_path_style {
-                S3PathStyle::VirtualHost => Some(*bucket),
-                S3PathStyle::Path => Some(*bucket.with_path_style()),
-            },
-            Err(err) => {
-                error!("Error creating bucket: {}", err);
-                None
-            }
+    let bucket_result = Bucket::new(name, region, credentials);
+
+    match bucket_result {
+        Ok(bucket) => match path_style {
+            S3PathStyle::VirtualHost => Some(*bucket),
+            S3PathStyle::Path => Some(*bucket.with_path_style()),
+        },
+        Err(err) => {
+            error!("Error creating bucket: {}", err);
+            None
         }
     }
 }
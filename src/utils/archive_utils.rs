@@ -0,0 +1,603 @@
+use crate::utils::fs_utils::get_reader_sha256;
+use crate::utils::time_utils;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Timelike, Utc};
+use flate2::read::GzDecoder;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+use tar::{Archive, EntryType};
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Defines the kind of an entry in a folder archive.
+#[derive(Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// Represents an entry of a folder archive.
+///
+/// # Fields
+/// - `path` - The normalized path of the entry (see `normalize_archive_path`).
+/// - `kind` - The kind of the entry.
+/// - `size` - The size of the entry in bytes.
+/// - `modified` - The modification time of the entry, if stored in the archive.
+/// - `checksum` - The SHA-256 checksum of the file content, if requested.
+/// - `link_target` - The target of a symbolic link, or of a hard link in a tar archive.
+#[derive(Debug)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<DateTime<FixedOffset>>,
+    pub checksum: Option<String>,
+    pub link_target: Option<String>,
+}
+
+/// Lists the entries of a `tar.gz` or zip folder archive without extracting it.
+///
+/// The format is detected by the file extension. The root entry of tar archives (`./`) is skipped. Hard links in
+/// tar archives are listed as files with the size and checksum of their target, since they are extracted as
+/// regular files with the same content.
+///
+/// # Arguments
+/// - `file_path` - The path of the archive.
+/// - `with_checksums` - Whether the SHA-256 checksum of every file is calculated while reading the archive.
+///
+/// # Returns
+/// - `Ok(Vec<ArchiveEntry>)` - The entries in archive order.
+/// - `Err(Box<dyn Error>)` - If the archive cannot be read.
+///
+/// # Example
+/// ```rust
+/// let entries = list_archive(Path::new("/tmp/to_inspect/configs-2024-12-08_12-00-00.tar.gz"), false)?;
+/// ```
+pub fn list_archive(file_path: &Path, with_checksums: bool) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    if is_zip_archive(file_path) {
+        list_zip_archive(file_path, with_checksums)
+    } else {
+        list_tar_archive(file_path, with_checksums)
+    }
+}
+
+/// Lists the entries of a `tar.gz` archive.
+fn list_tar_archive(file_path: &Path, with_checksums: bool) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let mut archive = Archive::new(GzDecoder::new(File::open(file_path)?));
+    let mut entries = Vec::new();
+    let mut hard_links = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = normalize_archive_path(&entry.path()?.to_string_lossy());
+        if path.is_empty() {
+            continue;
+        }
+
+        let header = entry.header();
+        let is_hard_link = header.entry_type() == EntryType::Link;
+        let kind = match header.entry_type() {
+            EntryType::Directory => EntryKind::Directory,
+            EntryType::Symlink => EntryKind::Symlink,
+            _ => EntryKind::File,
+        };
+        let size = header.size()?;
+        let modified = DateTime::from_timestamp(header.mtime()? as i64, 0)
+            .map(|date| time_utils::to_configured(&date));
+        let link_target = entry
+            .link_name()?
+            .map(|target| target.to_string_lossy().to_string());
+
+        let checksum = if with_checksums && kind == EntryKind::File && !is_hard_link {
+            Some(get_reader_sha256(&mut entry)?)
+        } else {
+            None
+        };
+
+        if is_hard_link {
+            hard_links.push(entries.len());
+        }
+
+        entries.push(ArchiveEntry {
+            path,
+            kind,
+            size,
+            modified,
+            checksum,
+            link_target,
+        });
+    }
+
+    // A hard link stores no content, it refers to an earlier entry of the archive.
+    for index in hard_links {
+        let target = entries[index]
+            .link_target
+            .as_deref()
+            .map(normalize_archive_path)
+            .and_then(|target| entries.iter().find(|entry| entry.path == target))
+            .map(|entry| (entry.size, entry.checksum.clone()));
+
+        if let Some((size, checksum)) = target {
+            entries[index].size = size;
+            entries[index].checksum = checksum;
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Lists the entries of a zip archive.
+fn list_zip_archive(file_path: &Path, with_checksums: bool) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(file_path)?)?;
+    let mut entries = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let path = normalize_archive_path(file.name());
+
+        let kind = if file.is_dir() {
+            EntryKind::Directory
+        } else if file.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        };
+        let size = file.size();
+        let modified = file
+            .last_modified()
+            .and_then(|time| get_system_time(&time))
+            .map(|time| time_utils::to_configured(&DateTime::<Utc>::from(time)));
+
+        let (checksum, link_target) = match kind {
+            EntryKind::File if with_checksums => (Some(get_reader_sha256(&mut file)?), None),
+            EntryKind::Symlink => {
+                let mut link_target = String::new();
+                io::Read::read_to_string(&mut file, &mut link_target)?;
+                (None, Some(link_target))
+            }
+            _ => (None, None),
+        };
+
+        entries.push(ArchiveEntry {
+            path,
+            kind,
+            size,
+            modified,
+            checksum,
+            link_target,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Creates a zip archive with the contents of a directory.
+///
+/// The archive is written with a pure-Rust deflate encoder, so no external `zip` or `tar` binary is required.
+/// Paths in the archive are relative to `source`. Unix permissions and modification times are stored for every
+/// entry, and symbolic links are stored as links instead of being followed.
+///
+/// # Arguments
+/// - `source` - The directory to archive.
+/// - `file_path` - The path of the zip archive to write.
+///
+/// # Returns
+/// - `Ok(())` if the archive is written successfully.
+/// - `Err(Box<dyn Error>)` if the directory cannot be read or the archive cannot be written.
+///
+/// # Example
+/// ```rust
+/// create_zip_archive(Path::new("/etc/nginx"), Path::new("/tmp/backups/nginx.zip"))?;
+/// ```
+pub fn create_zip_archive(source: &Path, file_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut zip = ZipWriter::new(File::create(file_path)?);
+
+    for entry in WalkDir::new(source).min_depth(1) {
+        let entry = entry?;
+        let name = entry
+            .path()
+            .strip_prefix(source)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let metadata = entry.path().symlink_metadata()?;
+
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(metadata.len() >= u32::MAX as u64);
+        if let Some(mode) = get_unix_mode(&metadata) {
+            options = options.unix_permissions(mode);
+        }
+        if let Some(modified) = get_zip_time(&metadata) {
+            options = options.last_modified_time(modified);
+        }
+
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            zip.add_symlink(name, target.to_string_lossy(), options)?;
+        } else if metadata.is_dir() {
+            zip.add_directory(name, options)?;
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut File::open(entry.path())?, &mut zip)?;
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Extracts a zip archive into a directory.
+///
+/// Entries with absolute paths or `..` components are rejected. Symbolic links are recreated as links on Unix.
+/// Zip archives do not store file ownership, so extracted files belong to the user running reback.
+/// If `paths` is not empty, only the entries matching these paths (or located inside them) are extracted.
+///
+/// The archive is extracted in three passes, so it cannot write outside of `target`:
+/// - Directories and regular files are created first. An entry is rejected if its existing parent directory
+///   resolves to a location outside of `target` (e.g., through a symbolic link that already exists at the
+///   destination). Existing files and links at the path of an entry are replaced, never followed.
+/// - Symbolic links are created after all regular files, so no entry of the archive is written through a link
+///   created by the archive itself.
+/// - The permissions and modification times of directories are applied last, since creating their contents would
+///   change the modification times and read-only permissions would prevent it.
+///
+/// # Arguments
+/// - `file_path` - The path of the zip archive.
+/// - `target` - The directory where the contents are extracted.
+/// - `paths` - The normalized paths (see `normalize_archive_path`) to extract. Empty to extract everything.
+/// - `preserve_permissions` - Whether the stored Unix permissions are applied (otherwise the umask is used).
+/// - `preserve_timestamps` - Whether the stored modification times are applied (otherwise the current time is used).
+///
+/// # Returns
+/// - `Ok(())` if the archive is extracted successfully.
+/// - `Err(Box<dyn Error>)` if the archive cannot be read or contains unsafe paths.
+///
+/// # Example
+/// ```rust
+/// extract_zip_archive(Path::new("/tmp/to_restore/nginx.zip"), Path::new("/etc/nginx"), &[], true, true)?;
+/// ```
+pub fn extract_zip_archive(
+    file_path: &Path,
+    target: &Path,
+    paths: &[String],
+    preserve_permissions: bool,
+    preserve_timestamps: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(file_path)?)?;
+
+    fs::create_dir_all(target)?;
+    let target = target.canonicalize()?;
+
+    let mut directories = Vec::new();
+    let mut symlinks = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let name = file
+            .enclosed_name()
+            .ok_or_else(|| format!("Unsafe path in zip archive: {}", file.name()))?;
+
+        if !paths.is_empty() && !is_selected_path(&name.to_string_lossy(), paths) {
+            continue;
+        }
+
+        let out_path = target.join(name);
+
+        let modified = file
+            .last_modified()
+            .and_then(|time| get_system_time(&time))
+            .filter(|_| preserve_timestamps);
+        let mode = file.unix_mode().filter(|_| preserve_permissions);
+
+        if file.is_dir() {
+            check_inside_target(&target, &out_path)?;
+            fs::create_dir_all(&out_path)?;
+            directories.push((out_path, mode, modified));
+            continue;
+        }
+
+        if let Some(parent) = out_path.parent() {
+            check_inside_target(&target, parent)?;
+            fs::create_dir_all(parent)?;
+        }
+
+        if file.is_symlink() {
+            let mut link_target = String::new();
+            io::Read::read_to_string(&mut file, &mut link_target)?;
+            symlinks.push((out_path, link_target));
+            continue;
+        }
+
+        remove_existing_file(&out_path)?;
+
+        // `create_new` fails instead of following a link created at the path in the meantime.
+        let mut out_file = fs::OpenOptions::new().write(true).create_new(true).open(&out_path)?;
+        io::copy(&mut file, &mut out_file)?;
+
+        if let Some(modified) = modified {
+            out_file.set_modified(modified)?;
+        }
+
+        if let Some(mode) = mode {
+            set_unix_mode(&out_path, mode)?;
+        }
+    }
+
+    for (out_path, link_target) in symlinks {
+        if let Some(parent) = out_path.parent() {
+            check_inside_target(&target, parent)?;
+        }
+
+        remove_existing_file(&out_path)?;
+        create_symlink(&link_target, &out_path)?;
+    }
+
+    // Children are processed before their parents, so a read-only parent does not prevent updating them.
+    directories.sort_by_key(|(path, _, _)| std::cmp::Reverse(path.components().count()));
+
+    for (out_path, mode, modified) in directories {
+        if let Some(modified) = modified {
+            File::open(&out_path)?.set_modified(modified)?;
+        }
+
+        if let Some(mode) = mode {
+            set_unix_mode(&out_path, mode)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a path resolves to a location inside the (canonical) target directory.
+///
+/// The deepest existing ancestor of the path is resolved, so symbolic links that already exist at the destination
+/// are taken into account before any directory is created through them.
+fn check_inside_target(target: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut existing = path;
+    while existing.symlink_metadata().is_err() {
+        existing = match existing.parent() {
+            Some(parent) => parent,
+            None => break,
+        };
+    }
+
+    let resolved = existing.canonicalize()?;
+    if !resolved.starts_with(target) {
+        return Err(format!(
+            "Refusing to extract {}: it resolves to {} outside of {}",
+            path.display(),
+            resolved.display(),
+            target.display()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Removes an existing file or symbolic link (without following it) before an entry is extracted to its path.
+fn remove_existing_file(path: &Path) -> io::Result<()> {
+    match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is a directory", path.display()),
+        )),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Normalizes a path inside a folder archive given by the user.
+///
+/// Leading `./` and `/` as well as trailing `/` are removed, so `./etc/nginx/` and `/etc/nginx` both become
+/// `etc/nginx`.
+///
+/// # Example
+/// ```rust
+/// assert_eq!(normalize_archive_path("./etc/nginx/"), "etc/nginx");
+/// ```
+pub fn normalize_archive_path(path: &str) -> String {
+    let mut path = path.trim();
+
+    while let Some(stripped) = path.strip_prefix("./").or_else(|| path.strip_prefix('/')) {
+        path = stripped;
+    }
+
+    path.trim_end_matches('/').to_string()
+}
+
+/// Checks whether an archive entry equals one of the selected paths or is located inside one of them.
+fn is_selected_path(name: &str, paths: &[String]) -> bool {
+    let name = normalize_archive_path(name);
+
+    paths.iter().any(|path| {
+        name == *path
+            || name
+                .strip_prefix(path.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Checks whether a backup file is a zip archive by its extension.
+pub fn is_zip_archive(file_path: &Path) -> bool {
+    file_path.extension().is_some_and(|extension| extension == "zip")
+}
+
+/// Returns the Unix permission bits of a file, if the platform provides them.
+#[cfg(unix)]
+fn get_unix_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+/// Returns the Unix permission bits of a file, if the platform provides them.
+#[cfg(not(unix))]
+fn get_unix_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Sets the Unix permission bits of a file, if the platform supports them.
+#[cfg(unix)]
+fn set_unix_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))
+}
+
+/// Sets the Unix permission bits of a file, if the platform supports them.
+#[cfg(not(unix))]
+fn set_unix_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Creates a symbolic link. An existing file at the link path must be removed first (see `remove_existing_file`).
+#[cfg(unix)]
+fn create_symlink(link_target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link_target, path)
+}
+
+/// Writes the target of a symbolic link into a regular file on platforms without Unix symlinks.
+#[cfg(not(unix))]
+fn create_symlink(link_target: &str, path: &Path) -> io::Result<()> {
+    fs::write(path, link_target)
+}
+
+/// Converts a zip timestamp (stored in the configured timezone) to a system time.
+fn get_system_time(time: &zip::DateTime) -> Option<SystemTime> {
+    let date = NaiveDate::from_ymd_opt(time.year() as i32, time.month() as u32, time.day() as u32)?
+        .and_hms_opt(time.hour() as u32, time.minute() as u32, time.second() as u32)?;
+
+    time_utils::from_naive(&date).map(SystemTime::from)
+}
+
+/// Converts the modification time of a file to a zip timestamp in the configured timezone.
+///
+/// Zip timestamps have no timezone and only cover the years 1980-2107, so `None` is returned for times outside
+/// of this range.
+fn get_zip_time(metadata: &fs::Metadata) -> Option<zip::DateTime> {
+    let modified = time_utils::to_configured(&DateTime::<Utc>::from(metadata.modified().ok()?));
+
+    zip::DateTime::from_date_and_time(
+        u16::try_from(modified.year()).ok()?,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        modified.second() as u8,
+    )
+    .ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("reback-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn zip_symlink_is_created_after_files_and_never_followed() {
+        let dir = temp_dir("zip-symlink");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+
+        // The link entry comes first, so extracting in archive order would write `link/evil` into `outside`.
+        let archive_path = dir.join("archive.zip");
+        let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+        zip.add_symlink("link", outside.to_string_lossy(), SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file("link/evil", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+
+        let target = dir.join("target");
+        let result = extract_zip_archive(&archive_path, &target, &[], false, false);
+
+        assert!(result.is_err());
+        assert!(!outside.join("evil").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn zip_entries_are_not_written_through_existing_symlinks() {
+        let dir = temp_dir("zip-existing-symlink");
+        let outside = dir.join("outside");
+        let target = dir.join("target");
+        fs::create_dir_all(&outside).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(&outside, target.join("conf")).unwrap();
+
+        let archive_path = dir.join("archive.zip");
+        let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+        zip.start_file("conf/app.conf", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"config").unwrap();
+        zip.finish().unwrap();
+
+        let error = extract_zip_archive(&archive_path, &target, &[], false, false).unwrap_err();
+
+        assert!(error.to_string().contains("outside of"), "{}", error);
+        assert!(!outside.join("app.conf").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn zip_directory_modes_are_applied_last() {
+        let dir = temp_dir("zip-directory-mode");
+        let archive_path = dir.join("archive.zip");
+        let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+        zip.add_directory("readonly/", SimpleFileOptions::default().unix_permissions(0o555))
+            .unwrap();
+        zip.start_file("readonly/file", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"content").unwrap();
+        zip.finish().unwrap();
+
+        let target = dir.join("target");
+        extract_zip_archive(&archive_path, &target, &[], true, false).unwrap();
+
+        assert_eq!(fs::read(target.join("readonly/file")).unwrap(), b"content");
+        let mode = get_unix_mode(&fs::metadata(target.join("readonly")).unwrap());
+        assert_eq!(mode, Some(0o555));
+
+        set_unix_mode(&target.join("readonly"), 0o755).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn tar_hard_link_is_listed_with_target_content() {
+        let dir = temp_dir("tar-hard-link");
+        let archive_path = dir.join("archive.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(File::create(&archive_path).unwrap(), Default::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "./data/file", &b"content"[..]).unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        builder.append_link(&mut header, "./data/link", "./data/file").unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let entries = list_archive(&archive_path, true).unwrap();
+        let file = entries.iter().find(|entry| entry.path == "data/file").unwrap();
+        let link = entries.iter().find(|entry| entry.path == "data/link").unwrap();
+
+        assert_eq!(link.kind, EntryKind::File);
+        assert_eq!(link.size, 7);
+        assert_eq!(link.checksum, file.checksum);
+        assert!(link.checksum.is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
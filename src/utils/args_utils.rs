@@ -0,0 +1,137 @@
+use crate::structures::elements::Elements;
+use crate::structures::settings::Settings;
+use chrono::Duration;
+
+/// Returns the value following a named option in the command-line arguments.
+///
+/// # Arguments
+/// - `args` - The command-line arguments.
+/// - `name` - The name of the option (e.g., `--expires`).
+///
+/// # Returns
+/// - `Some(&String)` containing the value of the option if it is present.
+/// - `None` if the option is missing or has no value.
+///
+/// # Example
+/// ```rust
+/// let expires = get_option_value(&args, "--expires");
+/// ```
+pub fn get_option_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+}
+
+/// Returns the positional arguments following the command name.
+///
+/// Options (arguments starting with `--`) are skipped. Options listed in `value_options` are skipped together
+/// with the value that follows them.
+///
+/// # Arguments
+/// - `args` - The command-line arguments.
+/// - `value_options` - The names of the options that take a value.
+///
+/// # Returns
+/// A vector of references to the positional arguments.
+///
+/// # Example
+/// ```rust
+/// // reback share my_pg_db --expires 24h
+/// let positional = get_positional_args(&args, &["--expires"]); // ["my_pg_db"]
+/// ```
+pub fn get_positional_args<'a>(args: &'a [String], value_options: &[&str]) -> Vec<&'a String> {
+    let mut positional = Vec::new();
+    let mut iter = args.iter().skip(2);
+
+    while let Some(arg) = iter.next() {
+        if value_options.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with("--") {
+            positional.push(arg);
+        }
+    }
+
+    positional
+}
+
+/// Finds an element in the settings by its title.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `title` - The title of the element.
+///
+/// # Returns
+/// - `Some(&Elements)` if an element with the given title exists.
+/// - `None` otherwise.
+pub fn find_element<'a>(settings: &'a Settings, title: &str) -> Option<&'a Elements> {
+    settings
+        .elements
+        .iter()
+        .find(|element| element.element_title == title)
+}
+
+/// Parses a human-readable duration such as `30s`, `15m`, `24h` or `7d`.
+///
+/// A value without a suffix is interpreted as a number of seconds.
+///
+/// # Arguments
+/// - `value` - The duration string.
+///
+/// # Returns
+/// - `Ok(Duration)` if the value is parsed successfully.
+/// - `Err(String)` if the value is not a valid duration.
+///
+/// # Example
+/// ```rust
+/// let expires = parse_duration("24h")?;
+/// ```
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", value))?;
+
+    let duration = match unit {
+        "s" => Duration::try_seconds(number),
+        "m" => Duration::try_minutes(number),
+        "h" => Duration::try_hours(number),
+        "d" => Duration::try_days(number),
+        _ => return Err(format!("Invalid duration unit: {}", value)),
+    };
+
+    duration.ok_or_else(|| format!("Duration is too large: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        let cases = [
+            ("90", Some(Duration::seconds(90))),
+            ("90s", Some(Duration::seconds(90))),
+            ("15m", Some(Duration::minutes(15))),
+            (" 24h ", Some(Duration::hours(24))),
+            ("7d", Some(Duration::days(7))),
+            ("0d", Some(Duration::zero())),
+            ("", None),
+            ("d", None),
+            ("7w", None),
+            ("-1h", None),
+            ("1.5h", None),
+            ("99999999999999999999d", None),
+            ("9223372036854775807d", None),
+            ("9223372036854775807s", None),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(parse_duration(value).ok(), expected, "{}", value);
+        }
+    }
+}
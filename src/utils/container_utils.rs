@@ -0,0 +1,212 @@
+use std::process::Command;
+
+/// The image used to query the version of a PostgreSQL server.
+const POSTGRES_PROBE_IMAGE: &str = "postgres:alpine";
+
+/// The image used to query the version of a MySQL or MariaDB server.
+const MYSQL_PROBE_IMAGE: &str = "mysql:lts";
+
+/// Represents an official client image that matches the version of a database server.
+///
+/// # Fields
+/// - `image` - The Docker image with the client tools (e.g., `postgres:16`).
+/// - `dump` - The dump tool inside the image (e.g., `pg_dump`).
+/// - `client` - The client used for restores inside the image (e.g., `psql`).
+#[derive(Debug)]
+pub struct ClientImage {
+    pub image: String,
+    pub dump: &'static str,
+    pub client: &'static str,
+}
+
+/// Detects the version of a PostgreSQL server and returns the matching `postgres` client image.
+///
+/// The version is queried with `SHOW server_version_num` from a probe container. Versions `10` and newer are
+/// mapped to the major version (e.g., `160002` -> `postgres:16`), older ones to `major.minor` (e.g., `90624` ->
+/// `postgres:9.6`).
+///
+/// # Arguments
+/// - `host` - The database host.
+/// - `port` - The database port.
+/// - `db_name` - The name of the database.
+/// - `db_user` - The database user.
+/// - `db_password` - The password of the user.
+///
+/// # Returns
+/// - `Ok(ClientImage)` - The client image matching the server version.
+/// - `Err(String)` - An error message if the version cannot be detected.
+///
+/// # Example
+/// ```rust
+/// let client = get_postgres_client("localhost", 5432, "my_db", "user", "password")?;
+/// ```
+pub fn get_postgres_client(
+    host: &str,
+    port: u16,
+    db_name: &str,
+    db_user: &str,
+    db_password: &str,
+) -> Result<ClientImage, String> {
+    let command = get_docker_run_command(
+        POSTGRES_PROBE_IMAGE,
+        "PGPASSWORD",
+        &format!(
+            "psql -U {} -h {} -p {} -d {} -tAc 'SHOW server_version_num'",
+            quote_shell_argument(db_user),
+            quote_shell_argument(host),
+            port,
+            quote_shell_argument(db_name)
+        ),
+        false,
+    );
+
+    let version = run_query(&command, &[("PGPASSWORD", db_password.to_string())])?;
+    let version: u32 = version
+        .parse()
+        .map_err(|_| format!("Unexpected PostgreSQL server version: {}", version))?;
+
+    let tag = if version >= 100000 {
+        (version / 10000).to_string()
+    } else {
+        format!("{}.{}", version / 10000, version / 100 % 100)
+    };
+
+    Ok(ClientImage {
+        image: format!("postgres:{}", tag),
+        dump: "pg_dump",
+        client: "psql",
+    })
+}
+
+/// Detects the version of a MySQL or MariaDB server and returns the matching client image.
+///
+/// The version is queried with `SELECT VERSION()` from a probe container and mapped to `major.minor`
+/// (e.g., `8.0.36` -> `mysql:8.0`, `10.11.6-MariaDB` -> `mariadb:10.11`). For MariaDB 11 and newer,
+/// the `mariadb-dump` and `mariadb` tools are used, because the `mysql*` aliases are no longer shipped.
+///
+/// # Arguments
+/// - `host` - The database host.
+/// - `port` - The database port.
+/// - `db_user` - The database user.
+/// - `db_password` - The password of the user.
+///
+/// # Returns
+/// - `Ok(ClientImage)` - The client image matching the server version.
+/// - `Err(String)` - An error message if the version cannot be detected.
+///
+/// # Example
+/// ```rust
+/// let client = get_mysql_client("localhost", 3306, "user", "password")?;
+/// ```
+pub fn get_mysql_client(
+    host: &str,
+    port: u16,
+    db_user: &str,
+    db_password: &str,
+) -> Result<ClientImage, String> {
+    let command = get_docker_run_command(
+        MYSQL_PROBE_IMAGE,
+        "MYSQL_PWD",
+        &format!(
+            "mysql -u {} -h {} -P {} -N -e 'SELECT VERSION()'",
+            quote_shell_argument(db_user),
+            quote_shell_argument(host),
+            port
+        ),
+        false,
+    );
+
+    let version = run_query(&command, &[("MYSQL_PWD", db_password.to_string())])?;
+    let mut parts = version.split(['.', '-']);
+    let (major, minor) = match (parts.next(), parts.next()) {
+        (Some(major), Some(minor)) => (major, minor),
+        _ => return Err(format!("Unexpected MySQL server version: {}", version)),
+    };
+    let major_number: u32 = major
+        .parse()
+        .map_err(|_| format!("Unexpected MySQL server version: {}", version))?;
+
+    if version.to_lowercase().contains("mariadb") {
+        let (dump, client) = if major_number >= 11 {
+            ("mariadb-dump", "mariadb")
+        } else {
+            ("mysqldump", "mysql")
+        };
+
+        return Ok(ClientImage {
+            image: format!("mariadb:{}.{}", major, minor),
+            dump,
+            client,
+        });
+    }
+
+    Ok(ClientImage {
+        image: format!("mysql:{}.{}", major, minor),
+        dump: "mysqldump",
+        client: "mysql",
+    })
+}
+
+/// Builds a `docker run` command that runs a client tool in a disposable container.
+///
+/// The container uses the host network, so `localhost` refers to the host where reback is running.
+/// The password is not part of the command: `-e <password_env>` without a value passes the variable from the
+/// environment of the `docker` process, which the caller sets with `Command::env` (see `run_query`). This way the
+/// password appears neither in the process list nor in the logged command.
+///
+/// # Arguments
+/// - `image` - The Docker image to run.
+/// - `password_env` - The name of the environment variable with the password (e.g., `PGPASSWORD`).
+/// - `command` - The command to run inside the container.
+/// - `interactive` - Whether stdin is attached to the container (needed for restores).
+///
+/// # Returns
+/// The shell command string.
+///
+/// # Example
+/// ```rust
+/// let command = get_docker_run_command("postgres:16", "PGPASSWORD", "pg_dump my_db", false);
+/// let output = run_query(&command, &[("PGPASSWORD", password)])?;
+/// ```
+pub fn get_docker_run_command(image: &str, password_env: &str, command: &str, interactive: bool) -> String {
+    format!(
+        "docker run --rm{} --network host -e {} {} {}",
+        if interactive { " -i" } else { "" },
+        password_env,
+        image,
+        command,
+    )
+}
+
+/// Runs a shell command (e.g., a database query) and returns its trimmed standard output.
+///
+/// # Arguments
+/// - `command` - The shell command.
+/// - `env` - Environment variables of the command (e.g., the password in `PGPASSWORD`).
+///
+/// # Returns
+/// - `Ok(String)` - The standard output of the command.
+/// - `Err(String)` - An error message with the standard error output if the command fails.
+pub fn run_query(command: &str, env: &[(&str, String)]) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().cloned())
+        .output()
+        .map_err(|e| format!("Failed to execute command '{}': {}", command, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to query the server: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Quotes an argument of a shell command, so the shell passes it to the program as is (e.g., `it's` ->
+/// `'it'\''s'`).
+pub fn quote_shell_argument(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
@@ -1,24 +1,60 @@
-use chrono::{Duration, Local, TimeZone};
+use crate::structures::elements::PARTIAL_SNAPSHOT_SUFFIX;
+use crate::utils::time_utils;
+use crate::utils::incremental_utils::select_outdated_chains;
+use crate::utils::retention_utils::RetentionPolicy;
+use crate::utils::s3_utils::get_backup_time;
+use crate::utils::storage_utils::StoredBackup;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
 use log::{info, warn};
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::SystemTime;
 
 /// Checks for and deletes outdated local backup files based on their last modified time.
 ///
-/// This function scans the specified directory for files, retrieves their last modified time from
-/// the filesystem metadata, and compares it to the current date. Files older than the specified
-/// retention period will be deleted.
+/// The files that are not kept by the retention policy are found with `get_outdated_local_backups` and deleted.
 ///
 /// # Arguments
 /// - `path` - The path to the directory containing the backup files.
-/// - `retention` - The retention period in days. Files older than this period will be deleted.
+/// - `policy` - The retention policy of the local backups (see `RetentionPolicy::local`).
 ///
 /// # Returns
 /// - `Ok(())` if the function completes successfully, i.e., the outdated backup files are checked and
 ///   deleted as necessary.
-/// - An error of type `io::Error` if reading the directory, retrieving metadata, or deleting a file fails.
+/// - An error of type `io::Error` if reading the directory or deleting a file fails.
+///
+/// # Example
+/// ```rust
+/// let backup_dir: Path = /* directory path */;
+/// check_outdated_local_backups(&backup_dir, &RetentionPolicy::local(&element))?;
+/// ```
+pub fn check_outdated_local_backups(path: &Path, policy: &RetentionPolicy) -> io::Result<()> {
+    for (file_path, _) in get_outdated_local_backups(path, policy)? {
+        fs::remove_file(&file_path)?;
+        info!("Deleted outdated backup: {:?}", file_path);
+    }
+
+    info!("Check and delete outdated local backups completed");
+
+    Ok(())
+}
+
+/// Returns the local backup files that are not kept by the retention policy.
+///
+/// This function scans the specified directory for files and retrieves their last modified time from
+/// the filesystem metadata. The files are then checked with `select_outdated_chains`, so the archives of an
+/// incremental chain are kept or deleted together, like in the storage targets.
+///
+/// # Arguments
+/// - `path` - The path to the directory containing the backup files.
+/// - `policy` - The retention policy of the local backups (see `RetentionPolicy::local`).
+///
+/// # Returns
+/// - `Ok(Vec<(PathBuf, DateTime<FixedOffset>)>)` - The outdated files with their modified times, newest first.
+/// - An error of type `io::Error` if reading the directory fails.
 ///
 /// # Notes
 /// - The function uses the `modified` time from the file metadata, which represents the last time the file
@@ -28,12 +64,15 @@ use std::time::SystemTime;
 ///
 /// # Example
 /// ```rust
-/// let backup_dir: Path = /* directory path */;
-/// let retention_days: u64 = 30;
-/// check_outdated_local_backups(&backup_dir, &retention_days)?;
+/// for (file_path, modified) in get_outdated_local_backups(&backup_dir, &RetentionPolicy::local(&element))? {
+///     println!("{} {}", modified, file_path.display());
+/// }
 /// ```
-pub fn check_outdated_local_backups(path: &Path, retention: &u64) -> io::Result<()> {
-    let now = Local::now();
+pub fn get_outdated_local_backups(
+    path: &Path,
+    policy: &RetentionPolicy,
+) -> io::Result<Vec<(PathBuf, DateTime<FixedOffset>)>> {
+    let mut backups = Vec::new();
 
     for entry in fs::read_dir(path)? {
         let entry = entry?;
@@ -43,12 +82,12 @@ pub fn check_outdated_local_backups(path: &Path, retention: &u64) -> io::Result<
             if let Ok(metadata) = fs::metadata(&file_path) {
                 if let Ok(modified_time) = metadata.modified() {
                     if let Ok(file_date) = modified_time.duration_since(SystemTime::UNIX_EPOCH) {
-                        if let Some(file_date) = Local.timestamp_opt(file_date.as_secs() as i64, 0).single() {
-                            let file_age = now - file_date;
-                            if file_age > Duration::days(*retention as i64) {
-                                fs::remove_file(&file_path)?;
-                                info!("Deleted outdated backup: {:?}", file_path);
-                            }
+                        if let Some(file_date) = DateTime::<Utc>::from_timestamp(file_date.as_secs() as i64, 0) {
+                            backups.push(StoredBackup {
+                                key: file_path.to_string_lossy().to_string(),
+                                time: time_utils::to_configured(&file_date),
+                                size: Some(metadata.len()),
+                            });
                         }
                     }
                 } else {
@@ -60,7 +99,158 @@ pub fn check_outdated_local_backups(path: &Path, retention: &u64) -> io::Result<
         }
     }
 
-    info!("Check and delete outdated local backups completed");
+    let outdated = select_outdated_chains(backups, policy).map_err(io::Error::other)?;
+
+    Ok(outdated
+        .into_iter()
+        .map(|backup| (PathBuf::from(backup.key), backup.time))
+        .collect())
+}
+
+/// Returns the local snapshots of a folder element, sorted from the oldest to the newest.
+///
+/// The time of a snapshot is taken from its directory name (`element-title-YYYY-MM-DD_HH-MM-SS`). Entries
+/// without a timestamp in the name and partial snapshots that are still being created (or were interrupted) are
+/// ignored.
+///
+/// # Arguments
+/// - `snapshots_dir` - The directory containing the snapshots.
+///
+/// # Returns
+/// - `Ok(Vec<(PathBuf, DateTime<FixedOffset>)>)` - The snapshots with their times. Empty if the directory does
+///   not exist.
+/// - An error of type `io::Error` if the directory cannot be read.
+///
+/// # Example
+/// ```rust
+/// let latest = get_local_snapshots(&snapshots_dir)?.pop();
+/// ```
+pub fn get_local_snapshots(snapshots_dir: &Path) -> io::Result<Vec<(PathBuf, DateTime<FixedOffset>)>> {
+    if !snapshots_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+
+    for entry in fs::read_dir(snapshots_dir)? {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(PARTIAL_SNAPSHOT_SUFFIX) {
+            continue;
+        }
+
+        if let Some(time) = get_backup_time(&name, "") {
+            snapshots.push((entry.path(), time));
+        }
+    }
+
+    snapshots.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+    Ok(snapshots)
+}
+
+/// Deletes local snapshots that are older than the retention period.
+///
+/// The newest snapshot is always kept, because it is the base for the hardlinks of the next snapshot.
+///
+/// # Arguments
+/// - `snapshots_dir` - The directory containing the snapshots.
+/// - `retention` - The retention period in days.
+///
+/// # Returns
+/// - `Ok(())` if the outdated snapshots are deleted successfully.
+/// - An error of type `io::Error` if a snapshot cannot be deleted.
+///
+/// # Example
+/// ```rust
+/// check_outdated_local_snapshots(&snapshots_dir, &30)?;
+/// ```
+pub fn check_outdated_local_snapshots(snapshots_dir: &Path, retention: &u64) -> io::Result<()> {
+    let now = time_utils::now();
+    let mut snapshots = get_local_snapshots(snapshots_dir)?;
+    snapshots.pop();
+
+    for (snapshot, time) in snapshots {
+        if now - time > Duration::days(*retention as i64) {
+            fs::remove_dir_all(&snapshot)?;
+            info!("Deleted outdated snapshot: {:?}", snapshot);
+        }
+    }
 
     Ok(())
 }
+
+/// Calculates the SHA-256 checksum of a file.
+///
+/// # Arguments
+/// - `path` - The path to the file.
+///
+/// # Returns
+/// - `Ok(String)` containing the checksum as a lowercase hex string.
+/// - An error of type `io::Error` if the file cannot be read.
+///
+/// # Example
+/// ```rust
+/// let checksum = get_file_sha256(&file_path)?;
+/// ```
+pub fn get_file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    get_reader_sha256(&mut file)
+}
+
+/// Calculates the SHA-256 checksum of everything read from a reader (e.g., an entry of an archive).
+///
+/// # Returns
+/// - `Ok(String)` containing the checksum as a lowercase hex string.
+/// - An error of type `io::Error` if the reader fails.
+pub fn get_reader_sha256<R: io::Read>(reader: &mut R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    io::copy(reader, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// A temporary file that is deleted when it goes out of scope, so it is also removed on early returns and errors.
+///
+/// # Example
+/// ```rust
+/// let temp_file = TempFile::new(temp_dir.join("source.tmp"));
+/// bucket.get_object_to_writer(&key, &mut File::create(temp_file.path()).await?).await?;
+/// // The file is deleted here, even if the download failed.
+/// ```
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(path: PathBuf) -> Self {
+        TempFile(path)
+    }
+
+    /// Returns a temporary file in a directory with a name that is unique across processes and tasks (the process
+    /// ID and a counter), so parallel runs sharing the directory never overwrite each other's files.
+    pub fn unique(dir: &Path, name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let sequence = COUNTER.fetch_add(1, Ordering::Relaxed);
+        TempFile(dir.join(format!("{}-{}-{}", std::process::id(), sequence, name)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.0) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to delete temporary file {}: {}", self.0.display(), e),
+        }
+    }
+}
@@ -0,0 +1,776 @@
+use crate::structures::elements::{Elements, BACKUP_TIMESTAMP_FORMAT};
+use crate::utils::fs_utils::get_file_sha256;
+use crate::utils::retention_utils::RetentionPolicy;
+use crate::utils::storage_utils::{BackupSelector, StorageBackend, StoredBackup};
+use crate::utils::time_utils;
+use chrono::{Duration, NaiveDateTime};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The subdirectory of the local backup directory where the state of incremental folder backups is stored.
+pub const INCREMENTAL_STATE_DIR: &str = "incremental";
+
+/// The default number of days after which a new full backup is started.
+pub const DEFAULT_FULL_BACKUP_INTERVAL_DAYS: u64 = 7;
+
+/// Represents the level of an archive in an incremental chain.
+///
+/// The level is stored in the extension of the archive: `element-title-YYYY-MM-DD_HH-MM-SS.full.tar.gz` for full
+/// backups and `element-title-YYYY-MM-DD_HH-MM-SS.inc<N>.tar.gz` for the `N`-th increment after it.
+///
+/// # Variants
+/// - `Full` - A full archive that starts a new chain.
+/// - `Incremental` - An archive with the changes since the previous archive of the chain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupLevel {
+    Full,
+    Incremental(u32),
+}
+
+impl BackupLevel {
+    /// Returns the extension of archives of this level (e.g., `full.tar.gz` or `inc3.tar.gz`).
+    pub fn extension(&self) -> String {
+        match self {
+            BackupLevel::Full => String::from("full.tar.gz"),
+            BackupLevel::Incremental(sequence) => format!("inc{}.tar.gz", sequence),
+        }
+    }
+
+    /// Detects the level of an archive from its file name or key. Encrypted archives (`.age`) are also recognized.
+    ///
+    /// # Returns
+    /// - `Some(BackupLevel)` - The level of the archive.
+    /// - `None` - If the file is not part of an incremental chain.
+    ///
+    /// # Example
+    /// ```rust
+    /// let level = BackupLevel::from_file_name("files/files-2025-01-10_02-00-00.inc2.tar.gz");
+    /// assert_eq!(level, Some(BackupLevel::Incremental(2)));
+    /// ```
+    pub fn from_file_name(name: &str) -> Option<BackupLevel> {
+        let name = name.rsplit('/').next().unwrap_or(name);
+        let name = name.strip_suffix(".age").unwrap_or(name);
+        let (_, level) = name.strip_suffix(".tar.gz")?.rsplit_once('.')?;
+
+        if level == "full" {
+            return Some(BackupLevel::Full);
+        }
+
+        level
+            .strip_prefix("inc")
+            .and_then(|sequence| sequence.parse().ok())
+            .map(BackupLevel::Incremental)
+    }
+}
+
+/// The state of the current chain of an element, stored as `<element-title>.json` next to the tar snapshot file
+/// `<element-title>.snar`.
+///
+/// # Fields
+/// - `full_backup` - The timestamp of the full backup that started the chain.
+/// - `sequence` - The number of the last increment (`0` right after the full backup).
+#[derive(Debug, Serialize, Deserialize)]
+struct ChainState {
+    full_backup: String,
+    sequence: u32,
+}
+
+/// Represents an incremental backup that is being created.
+///
+/// `tar` updates the snapshot file while it creates the archive, so it works on a copy (`snapshot_file`), which
+/// only replaces the state of the chain with `commit` once the archive is created.
+///
+/// # Fields
+/// - `level` - The level of the new archive.
+/// - `snapshot_file` - The temporary snapshot file passed to `tar --listed-incremental`.
+pub struct IncrementalBackup {
+    pub level: BackupLevel,
+    pub snapshot_file: PathBuf,
+    state_dir: PathBuf,
+    title: String,
+    state: ChainState,
+}
+
+impl IncrementalBackup {
+    /// Saves the snapshot file and the state of the chain after the archive is created.
+    ///
+    /// # Returns
+    /// - `Ok(())` - If the state is saved.
+    /// - `Err(String)` - An error message if the state files cannot be written.
+    pub fn commit(self) -> Result<(), String> {
+        let snapshot_path = self.state_dir.join(format!("{}.snar", self.title));
+        let state_path = self.state_dir.join(format!("{}.json", self.title));
+
+        fs::rename(&self.snapshot_file, &snapshot_path)
+            .map_err(|e| format!("Failed to save snapshot file {}: {}", snapshot_path.display(), e))?;
+
+        let state = serde_json::to_string(&self.state).map_err(|e| e.to_string())?;
+        fs::write(&state_path, state)
+            .map_err(|e| format!("Failed to save chain state {}: {}", state_path.display(), e))
+    }
+
+    /// Discards the temporary snapshot file if the archive cannot be created.
+    pub fn abort(self) {
+        let _ = fs::remove_file(&self.snapshot_file);
+    }
+}
+
+/// Prepares the next incremental backup of a folder element.
+///
+/// A full backup is started if there is no chain yet, its state cannot be read, or the full backup of the chain is
+/// older than `interval_days`. Otherwise, the next increment of the chain is created.
+///
+/// # Arguments
+/// - `path` - The local backup directory of the element.
+/// - `title` - The title of the element.
+/// - `now` - The formatted timestamp of the backup.
+/// - `interval_days` - The number of days after which a new full backup is started.
+///
+/// # Returns
+/// - `Ok(IncrementalBackup)` - The prepared backup.
+/// - `Err(String)` - An error message if the state directory cannot be created or the snapshot file cannot be copied.
+///
+/// # Example
+/// ```rust
+/// let backup = prepare_incremental_backup(&path, &element.element_title, &now, 7)?;
+/// ```
+pub fn prepare_incremental_backup(
+    path: &Path,
+    title: &str,
+    now: &str,
+    interval_days: u64,
+) -> Result<IncrementalBackup, String> {
+    let state_dir = path.join(INCREMENTAL_STATE_DIR);
+    fs::create_dir_all(&state_dir)
+        .map_err(|e| format!("Failed to create state dir {}: {}", state_dir.display(), e))?;
+
+    let snapshot_path = state_dir.join(format!("{}.snar", title));
+    let snapshot_file = state_dir.join(format!("{}.snar.tmp", title));
+    let _ = fs::remove_file(&snapshot_file);
+
+    let current = fs::read_to_string(state_dir.join(format!("{}.json", title)))
+        .ok()
+        .and_then(|state| serde_json::from_str::<ChainState>(&state).ok())
+        .filter(|_| snapshot_path.exists())
+        .filter(|state| {
+            NaiveDateTime::parse_from_str(&state.full_backup, BACKUP_TIMESTAMP_FORMAT)
+                .ok()
+                .and_then(|full_backup| time_utils::from_naive(&full_backup))
+                .is_some_and(|full_backup| time_utils::now() - full_backup < Duration::days(interval_days as i64))
+        });
+
+    let (level, state) = match current {
+        Some(state) => {
+            fs::copy(&snapshot_path, &snapshot_file).map_err(|e| {
+                format!("Failed to copy snapshot file {}: {}", snapshot_path.display(), e)
+            })?;
+
+            let sequence = state.sequence + 1;
+            info!(
+                "Creating increment {} of the chain started at {}",
+                sequence, state.full_backup
+            );
+
+            (
+                BackupLevel::Incremental(sequence),
+                ChainState {
+                    full_backup: state.full_backup,
+                    sequence,
+                },
+            )
+        }
+        None => {
+            info!("Creating a full backup that starts a new chain");
+
+            (
+                BackupLevel::Full,
+                ChainState {
+                    full_backup: now.to_string(),
+                    sequence: 0,
+                },
+            )
+        }
+    };
+
+    Ok(IncrementalBackup {
+        level,
+        snapshot_file,
+        state_dir,
+        title: title.to_string(),
+        state,
+    })
+}
+
+/// Deletes the state of the chain of an element, so its next backup is a full backup.
+///
+/// This is used when an increment could not be uploaded, because the following increments would not contain its
+/// changes.
+///
+/// # Arguments
+/// - `path` - The local backup directory of the element.
+/// - `title` - The title of the element.
+pub fn reset_incremental_chain(path: &Path, title: &str) {
+    let state_dir = path.join(INCREMENTAL_STATE_DIR);
+    let _ = fs::remove_file(state_dir.join(format!("{}.snar", title)));
+    let _ = fs::remove_file(state_dir.join(format!("{}.json", title)));
+
+    warn!("The next backup of {} will be a full backup", title);
+}
+
+/// Downloads a complete chain of a folder: a full backup and its increments.
+///
+/// With `BackupSelector::Latest`, the latest full backup and all its increments are downloaded. Other selectors
+/// choose the archive the folder is restored to, and the chain is downloaded up to and including that archive.
+///
+/// The chain from the full backup to the restored archive is checked with `validate_chain` before anything is
+/// downloaded, and every downloaded archive is checked against its recorded checksum (see
+/// `StorageBackend::get_checksum`). A broken or corrupted chain would silently lose changes, so an error is returned
+/// and nothing is restored. Archives without a recorded checksum (e.g., in local or SFTP targets) are restored with
+/// a warning.
+///
+/// # Arguments
+/// - `storage` - The storage target containing the backups.
+/// - `folder` - The folder of the element.
+/// - `selector` - The archive the folder is restored to.
+/// - `dir` - The local directory where the archives are downloaded.
+///
+/// # Returns
+/// - `Ok(Vec<PathBuf>)` - The downloaded archives in the order they must be extracted.
+/// - `Err(Box<dyn Error>)` - If there is no full backup, the selected archive is not found or not part of a chain,
+///   the chain is broken, an archive does not match its checksum, or a download fails.
+///
+/// # Example
+/// ```rust
+/// for file_path in download_chain(&storage, &element.s3_folder, &BackupSelector::Latest, &restore_dir).await? {
+///     element.perform_restore(&file_path, &[]).await?;
+/// }
+/// ```
+pub async fn download_chain<S: StorageBackend>(
+    storage: &S,
+    folder: &str,
+    selector: &BackupSelector,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut backups = storage.list(folder).await?;
+    let mut target = None;
+
+    if !matches!(selector, BackupSelector::Latest) {
+        let selected = selector.select(folder, &backups).ok_or(format!(
+            "No {} found in {} of {}",
+            selector.describe(),
+            folder,
+            storage.describe()
+        ))?;
+
+        if BackupLevel::from_file_name(&selected.key).is_none() {
+            return Err(format!("Backup {} is not part of an incremental chain", selected.key).into());
+        }
+
+        let (key, time) = (selected.key.clone(), selected.time);
+        backups.retain(|backup| backup.time < time || backup.key == key);
+        target = Some(key);
+    }
+
+    let chain = get_latest_chain(backups)
+        .ok_or(format!("No full backup found in {} of {}", folder, storage.describe()))?;
+
+    if let Some(target) = target {
+        if chain.last().map(|last| &last.key) != Some(&target) {
+            return Err(format!(
+                "The incremental chain of {} is broken: no full backup found for {}",
+                folder, target
+            )
+            .into());
+        }
+    }
+
+    validate_chain(folder, &chain)?;
+
+    info!(
+        "Restoring chain of {} with {} increments from {}",
+        chain[0].key,
+        chain.len() - 1,
+        storage.describe()
+    );
+
+    let mut file_paths = Vec::new();
+    for backup in &chain {
+        let file_path = storage.download(&backup.key, dir).await?;
+        verify_chain_archive(storage, folder, &backup.key, &file_path).await?;
+        file_paths.push(file_path);
+    }
+
+    Ok(file_paths)
+}
+
+/// Returns the latest chain that starts with a full backup, sorted by time, or `None` if there is no full backup.
+///
+/// # Example
+/// ```rust
+/// let chain = get_latest_chain(storage.list(&element.s3_folder).await?).ok_or("No full backup found")?;
+/// validate_chain(&element.s3_folder, &chain)?;
+/// ```
+pub fn get_latest_chain(backups: Vec<StoredBackup>) -> Option<Vec<StoredBackup>> {
+    group_chains(backups)
+        .into_iter()
+        .rev()
+        .find(|chain| is_full_backup(&chain[0]))
+}
+
+/// Checks that a command working on a single backup is not used with an incremental folder element.
+///
+/// An increment only contains the changes since the previous archive of its chain, so on its own it is not a
+/// complete copy of the folder (e.g., `diff` would report every unchanged file as added).
+///
+/// # Arguments
+/// - `element` - The element to check.
+/// - `command` - The name of the command used in the error message (e.g., `diff`).
+///
+/// # Returns
+/// - `Ok(())` if the element is not incremental.
+/// - `Err(String)` - An error message otherwise.
+///
+/// # Example
+/// ```rust
+/// check_not_incremental(element, "diff")?;
+/// ```
+pub fn check_not_incremental(element: &Elements, command: &str) -> Result<(), String> {
+    if element.is_incremental() {
+        return Err(format!(
+            "Element '{}' has incremental backups, which `reback {}` does not support: a single archive only contains \
+             the changes since the previous one. Restore the chain with `reback restore` instead",
+            element.element_title, command
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validates the links of an incremental chain, from its full backup to its last archive.
+///
+/// The first archive must be a full backup, and the increments must be numbered without gaps (`inc1`, `inc2`, ...).
+/// A gap means that an increment is missing (e.g., it was deleted manually or by an older retention run).
+///
+/// # Arguments
+/// - `folder` - The folder of the element, used in the error message.
+/// - `chain` - The archives of the chain sorted by time (see `group_chains`).
+///
+/// # Returns
+/// - `Ok(())` - If the chain is complete.
+/// - `Err(String)` - An error message naming the first missing link.
+///
+/// # Example
+/// ```rust
+/// validate_chain(&element.s3_folder, &chain)?;
+/// ```
+pub fn validate_chain(folder: &str, chain: &[StoredBackup]) -> Result<(), String> {
+    let Some(first) = chain.first() else {
+        return Err(format!("The incremental chain of {} is empty", folder));
+    };
+
+    if !is_full_backup(first) {
+        return Err(format!(
+            "The incremental chain of {} is broken: no full backup found for {}",
+            folder, first.key
+        ));
+    }
+
+    for (expected, backup) in (1..).zip(chain.iter().skip(1)) {
+        if BackupLevel::from_file_name(&backup.key) != Some(BackupLevel::Incremental(expected)) {
+            return Err(format!(
+                "The incremental chain of {} is broken: expected increment {} of {}, found {}",
+                folder, expected, first.key, backup.key
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks a downloaded archive of a chain against the checksum recorded in the storage target.
+async fn verify_chain_archive<S: StorageBackend>(
+    storage: &S,
+    folder: &str,
+    key: &str,
+    file_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let Some(expected) = storage.get_checksum(key).await? else {
+        warn!("No checksum recorded for {} in {}, restoring it unverified", key, storage.describe());
+        return Ok(());
+    };
+
+    if get_file_sha256(file_path)? != expected {
+        return Err(format!(
+            "The incremental chain of {} is corrupted: {} does not match its checksum",
+            folder, key
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Checks that deleting outdated backups does not break an incremental chain that is still needed.
+///
+/// Every archive of a chain depends on all earlier archives of the chain. If any archive of a chain is kept, all
+/// archives before it must be kept too; deleting them would leave the kept archives unrestorable. This is called by
+/// every retention and prune path (see `StorageBackend::get_outdated`, `check_outdated_s3_backups`,
+/// `get_outdated_local_backups` and `get_outdated_chains`) before anything is deleted.
+///
+/// # Arguments
+/// - `backups` - All backups of the folder.
+/// - `outdated` - The backups that are about to be deleted.
+///
+/// # Returns
+/// - `Ok(())` - If no kept archive depends on an outdated one.
+/// - `Err(String)` - An error message naming the archive that cannot be deleted.
+///
+/// # Example
+/// ```rust
+/// let outdated = policy.get_outdated(backups.clone(), |backup| backup.time);
+/// check_pruning(&backups, &outdated)?;
+/// ```
+pub fn check_pruning(backups: &[StoredBackup], outdated: &[StoredBackup]) -> Result<(), String> {
+    let is_outdated = |backup: &StoredBackup| outdated.iter().any(|other| other.key == backup.key);
+
+    for chain in group_chains(backups.iter().collect()) {
+        if !is_full_backup(chain[0]) {
+            continue;
+        }
+
+        let Some(last_kept) = chain.iter().rposition(|backup| !is_outdated(backup)) else {
+            continue;
+        };
+
+        if let Some(needed) = chain[..last_kept].iter().find(|backup| is_outdated(backup)) {
+            return Err(format!(
+                "Refusing to delete {}: it is needed to restore {} of the incremental chain of {}",
+                needed.key, chain[last_kept].key, chain[0].key
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the outdated chains of a folder (see `get_outdated_chains`).
+///
+/// # Arguments
+/// - `storage` - The storage target containing the backups.
+/// - `folder` - The folder of the element.
+/// - `policy` - The retention policy of the element.
+///
+/// # Returns
+/// - `Ok(())` - If the outdated chains are deleted.
+/// - `Err(Box<dyn Error>)` - If the folder cannot be listed or a backup cannot be deleted.
+///
+/// # Example
+/// ```rust
+/// delete_outdated_chains(&storage, &element.s3_folder, &RetentionPolicy::stored(&element)).await?;
+/// ```
+pub async fn delete_outdated_chains<S: StorageBackend>(
+    storage: &S,
+    folder: &str,
+    policy: &RetentionPolicy,
+) -> Result<(), Box<dyn Error>> {
+    for backup in get_outdated_chains(storage, folder, policy).await? {
+        storage.delete(&backup.key).await?;
+        info!("Deleted outdated backup from {}: {}", storage.describe(), backup.key);
+    }
+
+    info!("Check and delete outdated chains in {} completed", storage.describe());
+
+    Ok(())
+}
+
+/// Returns the archives of the chains of a folder that are not kept by the retention policy.
+///
+/// The policy is applied to whole chains, using the time of their newest archive, so a full backup is never
+/// deleted while its increments are still needed. Archives outside of chains are checked by their own time.
+///
+/// # Arguments
+/// - `storage` - The storage target containing the backups.
+/// - `folder` - The folder of the element.
+/// - `policy` - The retention policy of the element.
+///
+/// # Returns
+/// - `Ok(Vec<StoredBackup>)` - The archives of the outdated chains.
+/// - `Err(Box<dyn Error>)` - If the folder cannot be listed.
+///
+/// # Example
+/// ```rust
+/// let outdated = get_outdated_chains(&storage, &element.s3_folder, &RetentionPolicy::stored(&element)).await?;
+/// ```
+pub async fn get_outdated_chains<S: StorageBackend>(
+    storage: &S,
+    folder: &str,
+    policy: &RetentionPolicy,
+) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+    let backups = storage.list_for_prune(folder).await?;
+
+    Ok(select_outdated_chains(backups, policy)?)
+}
+
+/// Returns the backups of the chains that are not kept by the retention policy.
+///
+/// Like `get_outdated_chains`, but for backups that are already listed (e.g., the files in the local backup
+/// directory). Backups outside of chains are checked by their own time, so for elements without incremental
+/// backups the result is the same as with `RetentionPolicy::get_outdated`.
+///
+/// # Arguments
+/// - `backups` - All backups of the element.
+/// - `policy` - The retention policy of the element.
+///
+/// # Returns
+/// - `Ok(Vec<StoredBackup>)` - The backups of the outdated chains.
+/// - `Err(String)` - If deleting them would still break a kept chain (see `check_pruning`).
+///
+/// # Example
+/// ```rust
+/// let outdated = select_outdated_chains(backups, &RetentionPolicy::local(&element))?;
+/// ```
+pub fn select_outdated_chains(
+    backups: Vec<StoredBackup>,
+    policy: &RetentionPolicy,
+) -> Result<Vec<StoredBackup>, String> {
+    let chains = group_chains(backups.clone());
+
+    // `group_chains` never creates empty chains.
+    let outdated: Vec<_> = policy
+        .get_outdated(chains, |chain| chain[chain.len() - 1].time)
+        .into_iter()
+        .flatten()
+        .collect();
+
+    check_pruning(&backups, &outdated)?;
+
+    Ok(outdated)
+}
+
+/// Checks whether a backup is a full backup of an incremental chain.
+fn is_full_backup(backup: &StoredBackup) -> bool {
+    BackupLevel::from_file_name(&backup.key) == Some(BackupLevel::Full)
+}
+
+/// Groups backups (or references to them) into chains sorted by time. Every full backup starts a new chain, and
+/// increments are added to the chain of the preceding full backup. Other backups form chains of their own.
+fn group_chains<B: Borrow<StoredBackup>>(mut backups: Vec<B>) -> Vec<Vec<B>> {
+    backups.sort_by_key(|backup| backup.borrow().time);
+
+    let mut chains: Vec<Vec<B>> = Vec::new();
+    for backup in backups {
+        let is_increment = matches!(
+            BackupLevel::from_file_name(&backup.borrow().key),
+            Some(BackupLevel::Incremental(_))
+        );
+
+        match chains.last_mut() {
+            Some(chain) if is_increment && is_full_backup(chain[0].borrow()) => chain.push(backup),
+            _ => chains.push(vec![backup]),
+        }
+    }
+
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+
+    /// An in-memory storage target with backups, their contents and recorded checksums.
+    struct MemoryStorage {
+        backups: Vec<StoredBackup>,
+        contents: HashMap<String, Vec<u8>>,
+        checksums: HashMap<String, String>,
+    }
+
+    impl MemoryStorage {
+        /// Creates a storage with one backup per file name, one day apart, with recorded checksums.
+        fn new(names: &[&str]) -> Self {
+            let start = time_utils::now() - Duration::days(names.len() as i64);
+            let mut storage = MemoryStorage {
+                backups: Vec::new(),
+                contents: HashMap::new(),
+                checksums: HashMap::new(),
+            };
+
+            for (index, name) in names.iter().enumerate() {
+                let key = format!("files/{}", name);
+                let content = name.as_bytes().to_vec();
+
+                storage.checksums.insert(key.clone(), format!("{:x}", Sha256::digest(&content)));
+                storage.contents.insert(key.clone(), content);
+                storage.backups.push(StoredBackup {
+                    key,
+                    time: start + Duration::days(index as i64),
+                    size: None,
+                });
+            }
+
+            storage
+        }
+    }
+
+    impl StorageBackend for MemoryStorage {
+        fn describe(&self) -> String {
+            String::from("memory")
+        }
+
+        async fn upload(&self, _file_path: &Path, _folder: &str) -> Result<(), Box<dyn Error>> {
+            Err("not supported".into())
+        }
+
+        async fn list(&self, _folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+            Ok(self.backups.clone())
+        }
+
+        async fn download(&self, key: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+            let file_path = dir.join(key);
+            fs::create_dir_all(file_path.parent().unwrap())?;
+            fs::write(&file_path, &self.contents[key])?;
+
+            Ok(file_path)
+        }
+
+        async fn delete(&self, _key: &str) -> Result<(), Box<dyn Error>> {
+            Err("not supported".into())
+        }
+
+        async fn get_checksum(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+            Ok(self.checksums.get(key).cloned())
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("reback-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn policy(days: u64) -> RetentionPolicy {
+        RetentionPolicy {
+            days,
+            keep_daily: 0,
+            keep_weekly: 0,
+            keep_monthly: 0,
+            keep_yearly: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn download_chain_restores_complete_chain() {
+        let storage = MemoryStorage::new(&["a.full.tar.gz", "b.inc1.tar.gz", "c.inc2.tar.gz"]);
+        let dir = temp_dir("complete-chain");
+
+        let file_paths = download_chain(&storage, "files", &BackupSelector::Latest, &dir).await.unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(file_paths.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn download_chain_refuses_chain_with_gap() {
+        let storage = MemoryStorage::new(&["a.full.tar.gz", "b.inc1.tar.gz", "c.inc3.tar.gz"]);
+        let dir = temp_dir("broken-chain");
+
+        let error = download_chain(&storage, "files", &BackupSelector::Latest, &dir)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("expected increment 2"), "{}", error);
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn download_chain_refuses_corrupted_archive() {
+        let mut storage = MemoryStorage::new(&["a.full.tar.gz", "b.inc1.tar.gz"]);
+        storage.contents.insert(String::from("files/a.full.tar.gz"), b"corrupted".to_vec());
+        let dir = temp_dir("corrupted-chain");
+
+        let error = download_chain(&storage, "files", &BackupSelector::Latest, &dir)
+            .await
+            .unwrap_err();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(error.to_string().contains("does not match its checksum"), "{}", error);
+    }
+
+    #[test]
+    fn check_pruning_refuses_to_break_kept_chain() {
+        let storage = MemoryStorage::new(&["a.full.tar.gz", "b.inc1.tar.gz", "c.inc2.tar.gz"]);
+        let outdated = vec![storage.backups[0].clone()];
+
+        let error = check_pruning(&storage.backups, &outdated).unwrap_err();
+
+        assert!(error.contains("files/a.full.tar.gz"), "{}", error);
+    }
+
+    #[test]
+    fn check_pruning_allows_whole_chains_and_plain_backups() {
+        let storage = MemoryStorage::new(&[
+            "a.full.tar.gz",
+            "b.inc1.tar.gz",
+            "c.tar.gz",
+            "d.full.tar.gz",
+            "e.inc1.tar.gz",
+        ]);
+        let outdated = storage.backups[..3].to_vec();
+
+        assert!(check_pruning(&storage.backups, &outdated).is_ok());
+    }
+
+    #[tokio::test]
+    async fn get_outdated_refuses_to_prune_base_of_kept_increments() {
+        // The full backup is 3 days old and its increments are younger, so a per-file policy of 2 days would
+        // delete only the full backup.
+        let storage = MemoryStorage::new(&["a.full.tar.gz", "b.inc1.tar.gz", "c.inc2.tar.gz"]);
+
+        let error = storage.get_outdated("files", &policy(2)).await.unwrap_err();
+        assert!(error.to_string().contains("Refusing to delete files/a.full.tar.gz"), "{}", error);
+
+        let outdated = get_outdated_chains(&storage, "files", &policy(2)).await.unwrap();
+        assert!(outdated.is_empty());
+    }
+
+    #[test]
+    fn backup_level_from_file_name() {
+        let cases = [
+            ("files-2025-01-10_02-00-00.full.tar.gz", Some(BackupLevel::Full)),
+            ("files/files-2025-01-10_02-00-00.inc2.tar.gz", Some(BackupLevel::Incremental(2))),
+            ("files-2025-01-10_02-00-00.inc12.tar.gz.age", Some(BackupLevel::Incremental(12))),
+            ("files-2025-01-10_02-00-00.tar.gz", None),
+            ("files-2025-01-10_02-00-00.full.sql", None),
+            ("files-2025-01-10_02-00-00.incx.tar.gz", None),
+        ];
+
+        for (name, level) in cases {
+            assert_eq!(BackupLevel::from_file_name(name), level, "{}", name);
+        }
+    }
+
+    #[test]
+    fn group_chains_joins_increments_to_preceding_full_backup() {
+        let storage = MemoryStorage::new(&[
+            "a.inc1.tar.gz",
+            "b.full.tar.gz",
+            "c.inc1.tar.gz",
+            "d.tar.gz",
+            "e.full.tar.gz",
+            "f.inc1.tar.gz",
+            "g.inc2.tar.gz",
+        ]);
+
+        let chains: Vec<Vec<&str>> = group_chains(storage.backups.iter().collect())
+            .iter()
+            .map(|chain| chain.iter().map(|backup| &backup.key[6..7]).collect())
+            .collect();
+
+        assert_eq!(chains, [vec!["a"], vec!["b", "c"], vec!["d"], vec!["e", "f", "g"]]);
+    }
+}
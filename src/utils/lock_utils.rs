@@ -0,0 +1,416 @@
+use crate::utils::time_utils;
+use chrono::{DateTime, Duration};
+use log::{info, warn};
+use s3::error::S3Error;
+use s3::Bucket;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::process::Command;
+use tokio::runtime::Handle;
+
+/// The S3 prefix where the locks of the elements are stored.
+pub const LOCK_PREFIX: &str = ".reback/locks";
+
+/// The default number of seconds to wait before verifying that a written lock has not been overwritten by another
+/// host (see `Settings::lock_settle_seconds`).
+pub const DEFAULT_LOCK_SETTLE_SECS: u64 = 5;
+
+/// The storage of the lock objects.
+///
+/// The locks are stored in the S3 bucket of the element (`Bucket`). The trait separates the locking logic from S3,
+/// so it can be tested against an in-memory store.
+pub trait LockStore: Clone {
+    /// Reads a lock object, returning `None` if it does not exist.
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+
+    /// Writes a lock object, replacing an existing one.
+    async fn write(&self, key: &str, content: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Deletes a lock object.
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>>;
+}
+
+impl LockStore for Bucket {
+    /// Only a `404` response means that there is no lock. Any other failure (e.g., a network error, `403` or `5xx`)
+    /// is returned as an error, so a host that cannot check the lock never overwrites the live lock of another host.
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match self.head_object(key).await {
+            Ok((_, 200)) => {}
+            Ok((_, 404)) | Err(S3Error::HttpFailWithBody(404, _)) => return Ok(None),
+            Ok((_, status)) => return Err(format!("Unexpected status {} when reading lock {}", status, key).into()),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Some(self.get_object(key).await?.bytes().to_vec()))
+    }
+
+    async fn write(&self, key: &str, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.put_object(key, content).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.delete_object(key).await?;
+
+        Ok(())
+    }
+}
+
+/// Represents the content of a lock object in S3.
+///
+/// # Fields
+/// - `owner` - The identifier of the process holding the lock (`hostname:pid`).
+/// - `expires_at` - The time (RFC 3339) after which the lock can be taken by another host.
+#[derive(Debug, Serialize, Deserialize)]
+struct S3Lock {
+    owner: String,
+    expires_at: String,
+}
+
+/// Tries to acquire the S3 lock of an element for a period of time.
+///
+/// The lock is stored as `.reback/locks/<element_title>.json` in the bucket. If a valid lock of another host
+/// exists, the lock is not acquired. Otherwise, a new lock is written, and after `settle_secs` seconds it is read
+/// again to make sure no other host has overwritten it in the meantime. With `settle_secs` set to `0`, the lock is
+/// read back right away, which is sufficient for storages with strong read-after-write consistency.
+///
+/// The acquired lock is represented by an `S3LockGuard`, which releases it when it is dropped, unless
+/// `S3LockGuard::keep` is called (e.g., after a successful backup, so other hosts skip the element for the rest
+/// of the period).
+///
+/// The lock is read, written and deleted, so the credentials of `bucket` need `GetObject`, `PutObject` and
+/// `DeleteObject` on `.reback/locks/*` (see `Elements::lock_bucket_credentials`).
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket where the lock is stored.
+/// - `element_title` - The title of the element.
+/// - `period_minutes` - The lifetime of the lock in minutes.
+/// - `settle_secs` - The number of seconds to wait before reading the written lock back.
+///
+/// # Returns
+/// - `Ok(Some(S3LockGuard))` if the lock is acquired by this process.
+/// - `Ok(None)` if the lock is held by another host.
+/// - `Err(Box<dyn Error>)` if the lock cannot be read or written.
+///
+/// # Example
+/// ```rust
+/// if let Some(lock) = acquire_s3_lock(&bucket, &element.element_title, 60, 5).await? {
+///     /* perform the backup */
+///     lock.release().await;
+/// }
+/// ```
+pub async fn acquire_s3_lock<S: LockStore>(
+    bucket: &S,
+    element_title: &str,
+    period_minutes: u64,
+    settle_secs: u64,
+) -> Result<Option<S3LockGuard<S>>, Box<dyn Error>> {
+    let key = get_lock_key(element_title);
+    let owner = get_lock_owner();
+
+    if let Some(lock) = read_lock(bucket, &key).await? {
+        if lock.owner != owner && !is_expired(&lock) {
+            info!(
+                "Element {} is locked by {} until {}",
+                element_title, lock.owner, lock.expires_at
+            );
+            return Ok(None);
+        }
+    }
+
+    let lock = S3Lock {
+        owner: owner.clone(),
+        expires_at: (time_utils::now() + Duration::minutes(period_minutes as i64)).to_rfc3339(),
+    };
+
+    bucket
+        .write(&key, serde_json::to_string(&lock)?.as_bytes())
+        .await?;
+
+    if settle_secs > 0 {
+        tokio::time::sleep(std::time::Duration::from_secs(settle_secs)).await;
+    }
+
+    match read_lock(bucket, &key).await? {
+        Some(lock) if lock.owner == owner => {
+            info!(
+                "Acquired lock for {} until {}",
+                element_title, lock.expires_at
+            );
+            Ok(Some(S3LockGuard {
+                bucket: bucket.clone(),
+                element_title: element_title.to_string(),
+                held: true,
+            }))
+        }
+        Some(lock) => {
+            info!("Lock for {} was taken by {}", element_title, lock.owner);
+            Ok(None)
+        }
+        None => Err(format!("Lock for {} disappeared after writing", element_title).into()),
+    }
+}
+
+/// An S3 lock acquired by `acquire_s3_lock`.
+///
+/// The lock is released when the guard is dropped, so it is released on every exit path of the code holding it
+/// (errors, early returns and panics). Prefer `release` in async code, since dropping the guard has to block the
+/// current thread until the lock is deleted.
+///
+/// # Fields
+/// - `bucket` - The S3 bucket where the lock is stored.
+/// - `element_title` - The title of the locked element.
+/// - `held` - Whether the lock still has to be released.
+pub struct S3LockGuard<S: LockStore = Bucket> {
+    bucket: S,
+    element_title: String,
+    held: bool,
+}
+
+impl<S: LockStore> S3LockGuard<S> {
+    /// Keeps the lock until it expires instead of releasing it.
+    pub fn keep(mut self) {
+        self.held = false;
+        info!("Keeping lock for {} until it expires", self.element_title);
+    }
+
+    /// Releases the lock.
+    pub async fn release(mut self) {
+        self.held = false;
+        release_s3_lock(&self.bucket, &self.element_title).await;
+    }
+}
+
+impl<S: LockStore> Drop for S3LockGuard<S> {
+    fn drop(&mut self) {
+        if !self.held {
+            return;
+        }
+
+        // Drop cannot be async, so the lock is released by blocking the current thread on the runtime.
+        match Handle::try_current() {
+            Ok(handle) => tokio::task::block_in_place(|| {
+                handle.block_on(release_s3_lock(&self.bucket, &self.element_title))
+            }),
+            Err(_) => warn!(
+                "Failed to release lock for {}: no runtime available, the lock expires on its own",
+                self.element_title
+            ),
+        }
+    }
+}
+
+/// Releases the S3 lock of an element if it is held by this process.
+///
+/// This is used after a failed backup (see `S3LockGuard`), so another host can perform the backup within the same
+/// period. Failures are logged as warnings, since the lock expires on its own.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket where the lock is stored.
+/// - `element_title` - The title of the element.
+///
+/// # Example
+/// ```rust
+/// release_s3_lock(&bucket, &element.element_title).await;
+/// ```
+pub async fn release_s3_lock<S: LockStore>(bucket: &S, element_title: &str) {
+    let key = get_lock_key(element_title);
+
+    match read_lock(bucket, &key).await {
+        Ok(Some(lock)) if lock.owner == get_lock_owner() => {
+            match bucket.delete(&key).await {
+                Ok(_) => info!("Released lock for {}", element_title),
+                Err(e) => warn!("Failed to release lock for {}: {}", element_title, e),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to read lock for {}: {}", element_title, e),
+    }
+}
+
+/// Returns the S3 key of the lock of an element.
+fn get_lock_key(element_title: &str) -> String {
+    format!("{}/{}.json", LOCK_PREFIX, element_title)
+}
+
+/// Returns the identifier of this process (`hostname:pid`).
+fn get_lock_owner() -> String {
+    format!("{}:{}", get_hostname(), std::process::id())
+}
+
+/// Returns the name of this host, or `unknown` if it cannot be determined.
+pub fn get_hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| {
+            Command::new("hostname")
+                .output()
+                .ok()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Reads the lock object, returning `None` if it does not exist. Read failures are returned as errors.
+async fn read_lock<S: LockStore>(bucket: &S, key: &str) -> Result<Option<S3Lock>, Box<dyn Error>> {
+    match bucket.read(key).await? {
+        Some(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        None => Ok(None),
+    }
+}
+
+/// Checks whether a lock has expired. Locks with an invalid expiration time are treated as expired.
+fn is_expired(lock: &S3Lock) -> bool {
+    DateTime::parse_from_rfc3339(&lock.expires_at)
+        .map(|expires_at| expires_at < time_utils::now())
+        .unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// An in-memory lock store. With `fail_reads`, every read fails (e.g., a `403` response). With `taken_by`, every
+    /// written lock is replaced by a lock of that owner, as if another host wrote its lock at the same time.
+    #[derive(Clone, Default)]
+    struct MemoryLockStore {
+        objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        fail_reads: bool,
+        taken_by: Option<String>,
+    }
+
+    impl MemoryLockStore {
+        fn insert(&self, element_title: &str, owner: &str, expires_at: DateTime<chrono::FixedOffset>) {
+            let lock = S3Lock {
+                owner: owner.to_string(),
+                expires_at: expires_at.to_rfc3339(),
+            };
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(get_lock_key(element_title), serde_json::to_vec(&lock).unwrap());
+        }
+
+        fn owner(&self, element_title: &str) -> Option<String> {
+            self.objects
+                .lock()
+                .unwrap()
+                .get(&get_lock_key(element_title))
+                .map(|content| serde_json::from_slice::<S3Lock>(content).unwrap().owner)
+        }
+    }
+
+    impl LockStore for MemoryLockStore {
+        async fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+            if self.fail_reads {
+                return Err("403 Forbidden".into());
+            }
+
+            Ok(self.objects.lock().unwrap().get(key).cloned())
+        }
+
+        async fn write(&self, key: &str, content: &[u8]) -> Result<(), Box<dyn Error>> {
+            let content = match &self.taken_by {
+                Some(owner) => serde_json::to_vec(&S3Lock {
+                    owner: owner.clone(),
+                    expires_at: (time_utils::now() + Duration::minutes(60)).to_rfc3339(),
+                })?,
+                None => content.to_vec(),
+            };
+            self.objects.lock().unwrap().insert(key.to_string(), content);
+
+            Ok(())
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+            self.objects.lock().unwrap().remove(key);
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn acquires_and_releases_a_free_lock() {
+        let store = MemoryLockStore::default();
+
+        let lock = acquire_s3_lock(&store, "db", 60, 0).await.unwrap().unwrap();
+        assert_eq!(store.owner("db"), Some(get_lock_owner()));
+
+        lock.release().await;
+        assert_eq!(store.owner("db"), None);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_lock_until_it_expires() {
+        let store = MemoryLockStore::default();
+
+        acquire_s3_lock(&store, "db", 60, 0).await.unwrap().unwrap().keep();
+
+        assert_eq!(store.owner("db"), Some(get_lock_owner()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn releases_the_lock_when_the_guard_is_dropped() {
+        let store = MemoryLockStore::default();
+
+        drop(acquire_s3_lock(&store, "db", 60, 0).await.unwrap().unwrap());
+
+        assert_eq!(store.owner("db"), None);
+    }
+
+    #[tokio::test]
+    async fn skips_a_valid_lock_of_another_host() {
+        let store = MemoryLockStore::default();
+        store.insert("db", "other:1", time_utils::now() + Duration::minutes(30));
+
+        assert!(acquire_s3_lock(&store, "db", 60, 0).await.unwrap().is_none());
+        assert_eq!(store.owner("db").as_deref(), Some("other:1"));
+    }
+
+    #[tokio::test]
+    async fn takes_over_an_expired_lock_of_another_host() {
+        let store = MemoryLockStore::default();
+        store.insert("db", "other:1", time_utils::now() - Duration::minutes(1));
+
+        let lock = acquire_s3_lock(&store, "db", 60, 0).await.unwrap().unwrap();
+
+        assert_eq!(store.owner("db"), Some(get_lock_owner()));
+        lock.keep();
+    }
+
+    #[tokio::test]
+    async fn skips_a_lock_overwritten_by_another_host() {
+        let store = MemoryLockStore {
+            taken_by: Some(String::from("other:1")),
+            ..Default::default()
+        };
+
+        assert!(acquire_s3_lock(&store, "db", 60, 0).await.unwrap().is_none());
+        assert_eq!(store.owner("db").as_deref(), Some("other:1"));
+    }
+
+    #[tokio::test]
+    async fn fails_if_the_lock_cannot_be_read() {
+        let store = MemoryLockStore {
+            fail_reads: true,
+            ..Default::default()
+        };
+
+        assert!(acquire_s3_lock(&store, "db", 60, 0).await.is_err());
+        assert!(store.objects.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn release_keeps_the_lock_of_another_host() {
+        let store = MemoryLockStore::default();
+        store.insert("db", "other:1", time_utils::now() + Duration::minutes(30));
+
+        release_s3_lock(&store, "db").await;
+
+        assert_eq!(store.owner("db").as_deref(), Some("other:1"));
+    }
+}
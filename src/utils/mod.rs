@@ -1,4 +1,28 @@
 pub mod process_backup;
-mod s3_utils;
-mod fs_utils;
+pub mod process_cat;
+pub mod process_daemon;
+pub mod process_diff;
+pub mod process_gc;
+pub mod process_import;
+pub mod process_inspect;
+pub mod process_list;
+pub mod process_migrate;
+pub mod process_prune;
 pub mod process_restore;
+pub mod process_share;
+pub mod process_update;
+pub mod process_verify;
+
+pub(crate) mod archive_utils;
+pub(crate) mod args_utils;
+pub(crate) mod container_utils;
+pub(crate) mod fs_utils;
+pub(crate) mod incremental_utils;
+pub(crate) mod lock_utils;
+pub(crate) mod notify_utils;
+pub(crate) mod pipeline_utils;
+pub(crate) mod retention_utils;
+pub(crate) mod s3_utils;
+pub(crate) mod storage_utils;
+pub(crate) mod stream_utils;
+pub(crate) mod time_utils;
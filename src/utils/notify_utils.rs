@@ -0,0 +1,233 @@
+use crate::structures::settings::{Notifications, Settings};
+use crate::utils::lock_utils::get_hostname;
+use log::warn;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// The timeout of a single notification request, so an unreachable endpoint does not delay backups.
+const NOTIFICATION_TIMEOUT_SECS: u64 = 15;
+
+/// The maximum length of a Telegram message in characters.
+const TELEGRAM_MAX_LENGTH: usize = 4096;
+
+/// The maximum length of a Discord message in characters.
+const DISCORD_MAX_LENGTH: usize = 2000;
+
+/// Represents the result of processing one element, used in notifications.
+///
+/// # Fields
+/// - `title` - The title of the element.
+/// - `size` - The size of the backup file in bytes (`0` if unknown, e.g., for S3 sync elements).
+/// - `error` - The error message if the element failed.
+#[derive(Debug)]
+pub struct ElementReport {
+    pub title: String,
+    pub size: u64,
+    pub error: Option<String>,
+}
+
+impl ElementReport {
+    /// Creates the report of a successfully processed element.
+    pub fn success(title: &str, size: u64) -> Self {
+        ElementReport {
+            title: title.to_string(),
+            size,
+            error: None,
+        }
+    }
+
+    /// Creates the report of a failed element.
+    pub fn failure(title: &str, error: String) -> Self {
+        ElementReport {
+            title: title.to_string(),
+            size: 0,
+            error: Some(error),
+        }
+    }
+}
+
+/// Sends a notification about the result of one element.
+///
+/// Successful elements are only reported if `on_success` is enabled, failed ones if `on_failure` is enabled
+/// (the default).
+///
+/// # Arguments
+/// - `settings` - The configuration containing the notification channels.
+/// - `action` - The performed action (`backup` or `restore`).
+/// - `report` - The result of the element.
+///
+/// # Example
+/// ```rust
+/// notify_element(&settings, "backup", &ElementReport::success("my_pg_db", 1024)).await;
+/// ```
+pub async fn notify_element(settings: &Settings, action: &str, report: &ElementReport) {
+    let notifications = &settings.notifications;
+    let enabled = match report.error {
+        Some(_) => notifications.on_failure,
+        None => notifications.on_success,
+    };
+
+    if !enabled {
+        return;
+    }
+
+    let host = get_hostname();
+    let text = match &report.error {
+        Some(error) => format!("[{}] {} of {} failed: {}", host, action, report.title, error),
+        None => format!(
+            "[{}] {} of {} succeeded{}",
+            host,
+            action,
+            report.title,
+            format_size_suffix(report.size)
+        ),
+    };
+    let payload = json!({
+        "host": host,
+        "action": action,
+        "event": "element",
+        "element": report_to_json(report),
+        "message": text,
+    });
+
+    send_notification(notifications, &text, &payload).await;
+}
+
+/// Sends a summary of a `backup` or `restore` run: the number of processed and failed elements, the total size
+/// and the errors of the failed elements.
+///
+/// Nothing is sent if `summary` is disabled or no element was processed.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the notification channels.
+/// - `action` - The performed action (`backup` or `restore`).
+/// - `reports` - The results of all processed elements.
+///
+/// # Example
+/// ```rust
+/// notify_summary(&settings, "backup", &reports).await;
+/// ```
+pub async fn notify_summary(settings: &Settings, action: &str, reports: &[ElementReport]) {
+    let notifications = &settings.notifications;
+    if !notifications.summary || reports.is_empty() {
+        return;
+    }
+
+    let host = get_hostname();
+    let failed: Vec<&ElementReport> = reports.iter().filter(|report| report.error.is_some()).collect();
+    let total_size: u64 = reports.iter().map(|report| report.size).sum();
+
+    let mut text = format!(
+        "[{}] {} finished: {} elements, {} succeeded, {} failed{}",
+        host,
+        action,
+        reports.len(),
+        reports.len() - failed.len(),
+        failed.len(),
+        format_size_suffix(total_size)
+    );
+    for report in &failed {
+        text.push_str(&format!(
+            "\n- {}: {}",
+            report.title,
+            report.error.as_deref().unwrap_or_default()
+        ));
+    }
+
+    let payload = json!({
+        "host": host,
+        "action": action,
+        "event": "summary",
+        "elements": reports.iter().map(report_to_json).collect::<Vec<_>>(),
+        "failed": failed.len(),
+        "total_size": total_size,
+        "message": text,
+    });
+
+    send_notification(notifications, &text, &payload).await;
+}
+
+/// Converts an element report to the JSON object sent to generic webhooks.
+fn report_to_json(report: &ElementReport) -> Value {
+    json!({
+        "title": report.title,
+        "status": if report.error.is_some() { "failure" } else { "success" },
+        "size": report.size,
+        "error": report.error,
+    })
+}
+
+/// Formats a size in bytes as a human-readable suffix (e.g., ` (12.3 MB)`), or an empty string for `0`.
+fn format_size_suffix(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    if size == 0 {
+        return String::new();
+    }
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!(" ({:.1} {})", value, UNITS[unit])
+}
+
+/// Sends a message to every configured channel. Failures are logged and ignored.
+async fn send_notification(notifications: &Notifications, text: &str, payload: &Value) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(NOTIFICATION_TIMEOUT_SECS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to create HTTP client for notifications: {}", e);
+            return;
+        }
+    };
+
+    let mut requests = Vec::new();
+
+    if let (Some(token), Some(chat_id)) = (
+        &notifications.telegram_bot_token,
+        &notifications.telegram_chat_id,
+    ) {
+        requests.push((
+            "Telegram",
+            format!("https://api.telegram.org/bot{}/sendMessage", token),
+            json!({
+                "chat_id": chat_id,
+                "text": text.chars().take(TELEGRAM_MAX_LENGTH).collect::<String>(),
+            }),
+        ));
+    }
+    if let Some(url) = &notifications.slack_webhook_url {
+        requests.push(("Slack", url.clone(), json!({ "text": text })));
+    }
+    if let Some(url) = &notifications.discord_webhook_url {
+        requests.push((
+            "Discord",
+            url.clone(),
+            json!({ "content": text.chars().take(DISCORD_MAX_LENGTH).collect::<String>() }),
+        ));
+    }
+    if let Some(url) = &notifications.webhook_url {
+        requests.push(("webhook", url.clone(), payload.clone()));
+    }
+
+    for (channel, url, body) in requests {
+        // The URL is removed from the error, because it can contain a token.
+        if let Err(e) = post_json(&client, &url, &body).await {
+            warn!("Failed to send {} notification: {}", channel, e.without_url());
+        }
+    }
+}
+
+/// Sends a JSON POST request and checks the response status.
+async fn post_json(client: &reqwest::Client, url: &str, body: &Value) -> Result<(), reqwest::Error> {
+    client.post(url).json(body).send().await?.error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,515 @@
+use crate::structures::elements::Elements;
+use crate::structures::settings::{Compression, Encryption, Settings};
+use age::secrecy::SecretString;
+use log::info;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The extensions of backup files that are already compressed and are not compressed again.
+const COMPRESSED_EXTENSIONS: [&str; 3] = ["gz", "zip", "zst"];
+
+/// The default gzip compression level.
+const GZIP_DEFAULT_LEVEL: u32 = 6;
+
+/// The default zstd compression level.
+const ZSTD_DEFAULT_LEVEL: i32 = 3;
+
+/// Compresses and encrypts a backup file according to the settings before it is uploaded.
+///
+/// The file is compressed first (with the `compression` of the element or, if not set, of the settings) and then
+/// encrypted (with `Settings::encryption`). Each stage appends its extension (e.g., `my_db-2025-01-10_02-00-00.sql`
+/// becomes `my_db-2025-01-10_02-00-00.sql.zst.age`), so `decode_backup` can detect what to undo. The input of each
+/// stage is deleted once the stage is finished. The stages run on a blocking thread, so they do not stall other
+/// elements backed up at the same time.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the global compression and encryption.
+/// - `element` - The element the backup belongs to.
+/// - `file_path` - The backup file created by `Elements::perform_backup`.
+///
+/// # Returns
+/// - `Ok(PathBuf)` - The path of the processed file (the same path if neither stage is configured).
+/// - `Err(String)` - An error message if a stage fails. The backup file and all intermediate files are deleted, so
+///   no unencrypted copy is left behind.
+///
+/// # Example
+/// ```rust
+/// let file_path = encode_backup(&settings, &element, &file_path).await?;
+/// ```
+pub async fn encode_backup(settings: &Settings, element: &Elements, file_path: &Path) -> Result<PathBuf, String> {
+    let compression = element.compression.as_ref().or(settings.compression.as_ref()).cloned();
+    let encryption = settings.encryption.clone();
+    let mut current = file_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let result = (|| -> Result<PathBuf, Box<dyn Error>> {
+            if let Some(compression) = &compression {
+                current = compress_file(&current, compression)?;
+            }
+
+            if let Some(encryption) = &encryption {
+                current = encrypt_file(&current, encryption)?;
+            }
+
+            Ok(current.clone())
+        })();
+
+        result.map_err(|e| {
+            let _ = fs::remove_file(&current);
+            e.to_string()
+        })
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()))
+}
+
+/// Decrypts and decompresses a downloaded backup file, so it can be restored or inspected.
+///
+/// The stages are detected by the extensions added by `encode_backup`: `.age` is decrypted with
+/// `Settings::encryption`, and `.zst` and `.gz` are decompressed. A `.gz` extension is only removed if it follows
+/// another extension (e.g., `.sql.gz`), because `.tar.gz` archives and MongoDB archives (`.gz` right after the
+/// timestamp) are restored as they are. The input of each stage is deleted once the stage is finished.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the encryption keys.
+/// - `file_path` - The downloaded backup file.
+///
+/// # Returns
+/// - `Ok(PathBuf)` - The path of the decoded file (the same path if the file is neither encrypted nor compressed).
+/// - `Err(String)` - An error message if the file is encrypted but no keys are configured, the keys do not match,
+///   or the file cannot be decompressed.
+///
+/// # Example
+/// ```rust
+/// let file_path = decode_backup(&settings, &downloaded_path)?;
+/// element.perform_restore(&file_path, &[]).await?;
+/// ```
+pub fn decode_backup(settings: &Settings, file_path: &Path) -> Result<PathBuf, String> {
+    let mut current = file_path.to_path_buf();
+
+    loop {
+        let file_name = current
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let next = if let Some(stem) = file_name.strip_suffix(".age") {
+            let encryption = settings.encryption.as_ref().ok_or(format!(
+                "Backup {} is encrypted, but no encryption is configured",
+                file_name
+            ))?;
+            decrypt_file(&current, &current.with_file_name(stem), encryption)
+        } else if let Some(stem) = file_name.strip_suffix(".zst") {
+            decompress_file(&current, &current.with_file_name(stem), true)
+        } else if let Some(stem) = file_name.strip_suffix(".gz").filter(|stem| is_compressed_by_pipeline(stem)) {
+            decompress_file(&current, &current.with_file_name(stem), false)
+        } else {
+            return Ok(current);
+        };
+
+        current = next.map_err(|e| format!("Failed to decode backup {}: {}", file_name, e))?;
+    }
+}
+
+/// Compresses and encrypts a backup stream the same way as `encode_backup`, without a file on disk.
+///
+/// Used for streaming backups (see `stream_utils`), where the output of a dump is uploaded while it is created.
+///
+/// # Fields
+/// - `file_name` - The name of the encoded backup (e.g., `my_db-2025-01-10_02-00-00.sql.zst.age`).
+pub struct StreamEncoder {
+    pub file_name: String,
+    compression: Option<Compression>,
+    encryptor: Option<age::Encryptor>,
+}
+
+impl StreamEncoder {
+    /// Prepares the compression and encryption of a backup stream according to the settings.
+    ///
+    /// # Arguments
+    /// - `settings` - The configuration containing the global compression and encryption.
+    /// - `element` - The element the backup belongs to.
+    /// - `file_name` - The name of the backup before it is encoded (e.g., `my_db-2025-01-10_02-00-00.sql`).
+    ///
+    /// # Returns
+    /// - `Ok(StreamEncoder)` - The prepared encoder.
+    /// - `Err(String)` - An error message if the encryption keys are invalid or missing.
+    ///
+    /// # Example
+    /// ```rust
+    /// let encoder = StreamEncoder::new(&settings, &element, "my_db-2025-01-10_02-00-00.sql")?;
+    /// encoder.encode(dump_output, &mut upload_writer)?;
+    /// ```
+    pub fn new(settings: &Settings, element: &Elements, file_name: &str) -> Result<Self, String> {
+        let mut file_name = file_name.to_string();
+
+        let mut compression = None;
+
+        if let Some(configured) = element.compression.as_ref().or(settings.compression.as_ref()) {
+            if let Some(extension) = get_compression_extension(configured, Path::new(&file_name)) {
+                file_name = format!("{}.{}", file_name, extension);
+                compression = Some(configured.clone());
+            }
+        }
+
+        let encryptor = match &settings.encryption {
+            Some(encryption) => {
+                file_name = format!("{}.age", file_name);
+                Some(get_encryptor(encryption).map_err(|e| e.to_string())?)
+            }
+            None => None,
+        };
+
+        Ok(StreamEncoder {
+            file_name,
+            compression,
+            encryptor,
+        })
+    }
+
+    /// Reads the whole backup from `reader`, compresses and encrypts it, and writes the result into `writer`.
+    pub fn encode(self, reader: impl Read, writer: impl Write) -> io::Result<()> {
+        match self.encryptor {
+            Some(encryptor) => {
+                let mut output = encryptor.wrap_output(writer)?;
+                compress_stream(reader, &mut output, self.compression.as_ref())?;
+                output.finish()?;
+            }
+            None => compress_stream(reader, writer, self.compression.as_ref())?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks whether a `.gz` extension was added by `encode_backup`, i.e., whether the rest of the file name ends with
+/// another extension. Timestamps contain `-`, so the "extension" of a name like `my_db-2025-01-10_02-00-00` is
+/// not mistaken for a real one.
+fn is_compressed_by_pipeline(stem: &str) -> bool {
+    stem.rsplit_once('.')
+        .is_some_and(|(_, extension)| extension != "tar" && !extension.contains('-'))
+}
+
+/// Compresses a file into `<file>.<extension>` and deletes the original file.
+fn compress_file(file_path: &Path, compression: &Compression) -> Result<PathBuf, Box<dyn Error>> {
+    let Some(extension) = get_compression_extension(compression, file_path) else {
+        return Ok(file_path.to_path_buf());
+    };
+
+    let output_path = append_extension(file_path, extension);
+
+    transform_file(file_path, &output_path, |reader, writer| {
+        compress_stream(reader, writer, Some(compression))
+    })?;
+
+    info!("Compressed backup: {}", output_path.display());
+
+    Ok(output_path)
+}
+
+/// Returns the extension added by a compression, or `None` if the file is not compressed (no compression is
+/// configured or the file is already compressed).
+fn get_compression_extension(compression: &Compression, file_path: &Path) -> Option<&'static str> {
+    let extension = match compression {
+        Compression::None => return None,
+        Compression::Gzip { .. } => "gz",
+        Compression::Zstd { .. } => "zst",
+    };
+
+    if file_path
+        .extension()
+        .is_some_and(|current| COMPRESSED_EXTENSIONS.iter().any(|compressed| current == *compressed))
+    {
+        info!("Skipping compression of {}: the file is already compressed", file_path.display());
+        return None;
+    }
+
+    Some(extension)
+}
+
+/// Copies `reader` into `writer`, compressed with `compression` (or as is, if no compression is set).
+fn compress_stream(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    compression: Option<&Compression>,
+) -> io::Result<()> {
+    match compression {
+        Some(Compression::Gzip { level }) => {
+            let level = level.unwrap_or(GZIP_DEFAULT_LEVEL);
+            if level > 9 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid gzip level {}", level),
+                ));
+            }
+
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::new(level));
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Some(Compression::Zstd { level }) => {
+            let mut encoder = zstd::stream::write::Encoder::new(writer, level.unwrap_or(ZSTD_DEFAULT_LEVEL))?;
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Some(Compression::None) | None => {
+            io::copy(&mut reader, &mut writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Decompresses a zstd (`is_zstd = true`) or gzip file into `output_path` and deletes the compressed file.
+fn decompress_file(file_path: &Path, output_path: &Path, is_zstd: bool) -> Result<PathBuf, Box<dyn Error>> {
+    transform_file(file_path, output_path, |reader, writer| {
+        if is_zstd {
+            io::copy(&mut zstd::stream::read::Decoder::new(reader)?, writer)?;
+        } else {
+            io::copy(&mut flate2::read::GzDecoder::new(reader), writer)?;
+        }
+
+        Ok(())
+    })?;
+
+    info!("Decompressed backup: {}", output_path.display());
+
+    Ok(output_path.to_path_buf())
+}
+
+/// Encrypts a file into `<file>.age` and deletes the original file.
+fn encrypt_file(file_path: &Path, encryption: &Encryption) -> Result<PathBuf, Box<dyn Error>> {
+    let encryptor = get_encryptor(encryption)?;
+    let output_path = append_extension(file_path, "age");
+
+    transform_file(file_path, &output_path, |mut reader, writer| {
+        let mut output = encryptor.wrap_output(writer)?;
+        io::copy(&mut reader, &mut output)?;
+        output.finish()?;
+
+        Ok(())
+    })?;
+
+    info!("Encrypted backup: {}", output_path.display());
+
+    Ok(output_path)
+}
+
+/// Creates an age encryptor for the passphrase or the recipients of the encryption.
+fn get_encryptor(encryption: &Encryption) -> Result<age::Encryptor, Box<dyn Error>> {
+    let passphrase = get_passphrase(encryption)?;
+
+    let recipients: Vec<Box<dyn age::Recipient>> = match passphrase {
+        Some(_) if !encryption.recipients.is_empty() => {
+            return Err("Encryption cannot use both a passphrase and recipients".into());
+        }
+        Some(passphrase) => vec![Box::new(age::scrypt::Recipient::new(passphrase))],
+        None if encryption.recipients.is_empty() => {
+            return Err("Encryption requires a passphrase, passphrase_env or recipients".into());
+        }
+        None => encryption
+            .recipients
+            .iter()
+            .map(|recipient| {
+                age::x25519::Recipient::from_str(recipient)
+                    .map(|recipient| Box::new(recipient) as Box<dyn age::Recipient>)
+                    .map_err(|e| format!("Invalid age recipient {}: {}", recipient, e))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    Ok(age::Encryptor::with_recipients(recipients.iter().map(|recipient| recipient.as_ref()))?)
+}
+
+/// Decrypts an `.age` file into `output_path` and deletes the encrypted file.
+fn decrypt_file(file_path: &Path, output_path: &Path, encryption: &Encryption) -> Result<PathBuf, Box<dyn Error>> {
+    let mut identities: Vec<Box<dyn age::Identity>> = Vec::new();
+
+    if let Some(passphrase) = get_passphrase(encryption)? {
+        identities.push(Box::new(age::scrypt::Identity::new(passphrase)));
+    }
+
+    if let Some(identity_file) = &encryption.identity_file {
+        identities.extend(
+            age::IdentityFile::from_file(identity_file.clone())
+                .map_err(|e| format!("Failed to read identity file {}: {}", identity_file, e))?
+                .into_identities()?,
+        );
+    }
+
+    if identities.is_empty() {
+        return Err("Decryption requires a passphrase, passphrase_env or identity_file".into());
+    }
+
+    transform_file(file_path, output_path, |reader, writer| {
+        let mut reader = age::Decryptor::new(reader)
+            .and_then(|decryptor| decryptor.decrypt(identities.iter().map(|identity| identity.as_ref())))
+            .map_err(io::Error::other)?;
+        io::copy(&mut reader, writer)?;
+
+        Ok(())
+    })?;
+
+    info!("Decrypted backup: {}", output_path.display());
+
+    Ok(output_path.to_path_buf())
+}
+
+/// Returns the passphrase from `passphrase` or the environment variable named by `passphrase_env`.
+fn get_passphrase(encryption: &Encryption) -> Result<Option<SecretString>, String> {
+    if let Some(passphrase) = &encryption.passphrase {
+        return Ok(Some(SecretString::from(passphrase.clone())));
+    }
+
+    match &encryption.passphrase_env {
+        Some(name) => env::var(name)
+            .map(|passphrase| Some(SecretString::from(passphrase)))
+            .map_err(|_| format!("Environment variable {} with the encryption passphrase is not set", name)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the path with an additional extension (e.g., `.sql` -> `.sql.zst`).
+fn append_extension(file_path: &Path, extension: &str) -> PathBuf {
+    let mut path = OsString::from(file_path.as_os_str());
+    path.push(".");
+    path.push(extension);
+
+    PathBuf::from(path)
+}
+
+/// Writes `output_path` from `file_path` with a transformation and deletes `file_path` afterwards.
+///
+/// If the transformation fails, the incomplete output file is deleted and `file_path` is kept.
+fn transform_file(
+    file_path: &Path,
+    output_path: &Path,
+    transform: impl FnOnce(BufReader<File>, &mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let reader = BufReader::new(File::open(file_path)?);
+    let mut writer = BufWriter::new(File::create(output_path)?);
+
+    if let Err(e) = transform(reader, &mut writer).and_then(|_| writer.flush()) {
+        let _ = fs::remove_file(output_path);
+        return Err(e);
+    }
+
+    fs::remove_file(file_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    const CONTENT: &[u8] = b"CREATE TABLE shop (id integer);\nINSERT INTO shop VALUES (1);\n";
+
+    /// Creates an empty directory for a test and a backup file with `CONTENT` in it.
+    fn backup_file(name: &str) -> (PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("reback-pipeline-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let file_path = dir.join("my_db-2025-01-10_02-00-00.sql");
+        fs::write(&file_path, CONTENT).unwrap();
+
+        (dir, file_path)
+    }
+
+    fn encryption(passphrase: Option<&str>, recipients: Vec<String>, identity_file: Option<String>) -> Encryption {
+        Encryption {
+            passphrase: passphrase.map(String::from),
+            passphrase_env: None,
+            recipients,
+            identity_file,
+        }
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        let cases = [
+            ("gzip", Compression::Gzip { level: None }, "gz", false),
+            ("zstd", Compression::Zstd { level: Some(19) }, "zst", true),
+        ];
+
+        for (name, compression, extension, is_zstd) in cases {
+            let (dir, file_path) = backup_file(name);
+
+            let compressed = compress_file(&file_path, &compression).unwrap();
+            assert_eq!(compressed, append_extension(&file_path, extension));
+            assert!(!file_path.exists());
+
+            let decompressed = decompress_file(&compressed, &file_path, is_zstd).unwrap();
+            assert_eq!(fs::read(&decompressed).unwrap(), CONTENT, "{}", name);
+            assert!(!compressed.exists());
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn passphrase_encryption_round_trips() {
+        let (dir, file_path) = backup_file("passphrase");
+        let passphrase = encryption(Some("correct horse"), Vec::new(), None);
+
+        let encrypted = encrypt_file(&file_path, &passphrase).unwrap();
+        assert_ne!(fs::read(&encrypted).unwrap(), CONTENT);
+
+        let wrong = encryption(Some("wrong horse"), Vec::new(), None);
+        assert!(decrypt_file(&encrypted, &file_path, &wrong).is_err());
+        assert!(encrypted.exists() && !file_path.exists());
+
+        let decrypted = decrypt_file(&encrypted, &file_path, &passphrase).unwrap();
+        assert_eq!(fs::read(&decrypted).unwrap(), CONTENT);
+        assert!(!encrypted.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn x25519_encryption_round_trips() {
+        let (dir, file_path) = backup_file("x25519");
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = dir.join("identity.txt");
+        fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+
+        let encrypted = encrypt_file(
+            &file_path,
+            &encryption(None, vec![identity.to_public().to_string()], None),
+        )
+        .unwrap();
+
+        let decrypted = decrypt_file(
+            &encrypted,
+            &file_path,
+            &encryption(None, Vec::new(), Some(identity_file.to_string_lossy().to_string())),
+        )
+        .unwrap();
+        assert_eq!(fs::read(&decrypted).unwrap(), CONTENT);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_gz_extensions_added_by_the_pipeline() {
+        let cases = [
+            ("my_db-2025-01-10_02-00-00.sql", true),
+            ("my_db-2025-01-10_02-00-00-1.sql", true),
+            ("files-2025-01-10_02-00-00.tar", false),
+            ("files-2025-01-10_02-00-00.full.tar", false),
+            ("files-2025-01-10_02-00-00.inc2.tar", false),
+            ("mongo-2025-01-10_02-00-00", false),
+            ("mongo-2025-01-10_02-00-00-1", false),
+        ];
+
+        for (stem, expected) in cases {
+            assert_eq!(is_compressed_by_pipeline(stem), expected, "{}.gz", stem);
+        }
+    }
+}
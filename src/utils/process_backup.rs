@@ -1,6 +1,16 @@
+use crate::structures::elements::{Elements, LOCAL_SNAPSHOTS_DIR};
 use crate::structures::settings::Settings;
-use crate::utils::fs_utils::check_outdated_local_backups;
-use crate::utils::s3_utils::{check_outdated_s3_backups, upload_file_to_s3};
+use crate::utils::fs_utils::{check_outdated_local_backups, check_outdated_local_snapshots};
+use crate::utils::incremental_utils::{delete_outdated_chains, reset_incremental_chain};
+use crate::utils::lock_utils::{acquire_s3_lock, DEFAULT_LOCK_SETTLE_SECS};
+use crate::utils::notify_utils::{notify_element, notify_summary, ElementReport};
+use crate::utils::pipeline_utils::encode_backup;
+use crate::utils::process_restore::expand_restored_databases;
+use crate::utils::retention_utils::RetentionPolicy;
+use crate::utils::s3_utils::{check_outdated_s3_mirror, sync_s3_prefix};
+use crate::utils::storage_utils::{get_storages, Storage, StorageBackend};
+use crate::utils::stream_utils::stream_backup_to_s3;
+use futures::stream::{self, StreamExt};
 use log::{error, info, warn};
 use s3::Bucket;
 use std::fs;
@@ -12,13 +22,42 @@ use std::path::Path;
 /// for each element:
 /// - Creates a backup directory if it does not already exist.
 /// - Performs the backup using the parameters defined for the element.
-/// - Uploads the resulting backup file to the specified S3 bucket.
-/// - Deletes outdated local backups based on the retention days specified.
-/// - Deletes outdated backups from the S3 bucket based on the retention days specified for S3 backups.
+/// - Compresses and encrypts the backup file if `compression` or `encryption` is configured (see `pipeline_utils`).
+/// - Uploads the resulting backup file to the storage targets of the element (the S3 bucket by default).
+/// - Deletes outdated local backups (and local folder snapshots) based on the retention days specified.
+/// - Deletes outdated backups from every storage target based on the retention days specified for S3 backups.
+///   If the element has `keep_daily`, `keep_weekly`, `keep_monthly` or `keep_yearly`, older backups are thinned out
+///   instead of deleted (see `RetentionPolicy`), both locally and in the storage targets.
+/// - Sends notifications about the result of each element and a summary of the run (see `notify_utils`).
+///
+/// S3 sync elements do not produce a backup file. Instead, the source prefix is mirrored into the element's
+/// S3 folder by `sync_element`.
+///
+/// If an element has `lock_period_minutes` set, an S3 lock is acquired before the backup, and the element is
+/// skipped if another host already holds the lock. The lock is only kept if the backup reached every storage target
+/// (or the S3 sync succeeded). Otherwise, it is released, so another host can retry within the same period.
+///
+/// If an element has several storage targets (see `storage_utils`), the backup is uploaded to each of them. A failed
+/// target does not stop the upload to the others, but the element is reported as failed.
+///
+/// For incremental folder elements, outdated backups are deleted by whole chains (see `incremental_utils`). If an
+/// archive cannot be processed or uploaded, the chain is reset, so the next backup is a full backup.
+///
+/// Elements with `all_databases: auto` query the server for its databases first, and each database is backed up
+/// as a separate element named `element-title-database` into `s3_folder/database`.
+///
+/// If an element references named S3 credentials, they are used instead of the default bucket keys.
+/// The prune credentials (`s3_prune_credentials`) are only loaded right before outdated S3 backups are listed and deleted.
+///
+/// If `max_parallel_backups` is greater than `1`, up to that many elements are backed up at the same time
+/// (see `run_parallel_backups`). Otherwise, the elements are backed up one after another.
+///
+/// If streaming is enabled (`stream_backups`), database dumps are uploaded to S3 while they are created, without
+/// a local backup file (see `stream_utils`).
 ///
 /// # Arguments
 /// - `settings` - The configuration containing backup settings and elements to back up.
-/// - `bucket` - The S3 bucket where the backup files will be uploaded.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backup files will be uploaded.
 ///
 /// # Behavior
 /// - The function will attempt to process each element in the `settings`. If any operation fails (directory creation,
@@ -42,60 +81,531 @@ pub async fn start_backup_process(settings: &Settings, bucket: &Bucket) {
         warn!("Elements list is empty");
         return;
     }
-    for element in &settings.elements {
-        let path_str = format!("{}/{}", settings.backup_dir, element.element_title);
-        let path = Path::new(&path_str);
 
-        if !path.exists() {
-            if let Err(e) = fs::create_dir_all(path) {
-                error!("Failed to create backup dir {}: {}", path.display(), e);
-                continue;
+    let reports = match settings.max_parallel_backups {
+        Some(limit) if limit > 1 => run_parallel_backups(settings, bucket, limit).await,
+        _ => {
+            let mut reports = Vec::new();
+            for element in &settings.elements {
+                reports.extend(run_element_backup(settings, bucket, element).await);
             }
-            info!("Created backup dir {}", path.display());
+            reports
         }
+    };
+
+    notify_summary(settings, "backup", &reports).await;
+}
+
+/// Backs up the configured elements with up to `limit` elements at the same time.
+///
+/// The backups run concurrently on the runtime: their dump and archive commands, compression and encryption run on
+/// blocking threads, and their uploads are asynchronous, so a long backup does not hold up the others. The next
+/// element is started as soon as one of the running backups finishes. The reports are returned in the order of the
+/// elements in the configuration, so the summary does not depend on which backup finishes first. The log messages
+/// of concurrent backups are interleaved.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the elements to back up.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backup files will be uploaded.
+/// - `limit` - The maximum number of concurrent backups (`max_parallel_backups`).
+///
+/// # Returns
+/// The reports of all backed up elements.
+///
+/// # Example
+/// ```rust
+/// let reports = run_parallel_backups(&settings, &bucket, 4).await;
+/// ```
+async fn run_parallel_backups(settings: &Settings, bucket: &Bucket, limit: usize) -> Vec<ElementReport> {
+    info!(
+        "Backing up {} elements with up to {} parallel backups",
+        settings.elements.len(),
+        limit
+    );
 
-        let file_path = match element.perform_backup(&path).await {
-            Ok(f) => f,
+    let mut results: Vec<_> = stream::iter(settings.elements.iter().enumerate())
+        .map(|(index, element)| async move { (index, run_element_backup(settings, bucket, element).await) })
+        .buffer_unordered(limit)
+        .collect()
+        .await;
+
+    results.sort_by_key(|(index, _)| *index);
+
+    results.into_iter().flat_map(|(_, reports)| reports).collect()
+}
+
+/// Backs up a single configured element.
+///
+/// Elements with `all_databases: auto` are expanded with `Elements::expand_databases` first, and each discovered
+/// database is backed up separately. Errors are logged and do not stop the backups of other databases.
+/// A notification is sent for the result of every backup.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the backup directory and named S3 credentials.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backup files will be uploaded.
+/// - `element` - The element to back up.
+///
+/// # Returns
+/// The reports of the backed up elements (one per discovered database). Skipped elements are not reported.
+///
+/// # Example
+/// ```rust
+/// let reports = run_element_backup(&settings, &bucket, &settings.elements[0]).await;
+/// ```
+pub async fn run_element_backup(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+) -> Vec<ElementReport> {
+    let elements = match element.expand_databases() {
+        Ok(elements) => elements,
+        Err(e) => {
+            error!("{}", e);
+            let report = ElementReport::failure(&element.element_title, e);
+            notify_element(settings, "backup", &report).await;
+            return vec![report];
+        }
+    };
+
+    let mut reports = Vec::new();
+    for element in &elements {
+        let report = match backup_element(settings, bucket, element).await {
+            Ok(Some(size)) => ElementReport::success(&element.element_title, size),
+            Ok(None) => continue,
             Err(e) => {
-                warn!(
-                    "Backup process encountered an error for {}: {}",
-                    element.element_title, e
-                );
-                continue;
+                error!("{}", e);
+                ElementReport::failure(&element.element_title, e)
             }
         };
 
-        if let Err(e) = upload_file_to_s3(&bucket, &file_path, &element.s3_folder).await {
-            error!(
-                "Failed to upload file to S3 for {}: {}",
+        notify_element(settings, "backup", &report).await;
+        reports.push(report);
+    }
+
+    if element.discovers_databases() {
+        delete_dropped_databases(settings, bucket, element, &elements).await;
+    }
+
+    reports
+}
+
+/// Applies the retention policies to the backups of databases that no longer exist on the server of an element
+/// with `all_databases: auto`.
+///
+/// The backed up databases are found in the subfolders of the element's S3 folder, like by `prune`, so the backups
+/// of a dropped database are thinned out and eventually deleted instead of being kept forever. Errors are logged.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the backup directory and named S3 credentials.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `element` - The element with `all_databases: auto`.
+/// - `discovered` - The elements of the databases that were found on the server in this run.
+async fn delete_dropped_databases(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+    discovered: &[Elements],
+) {
+    let stored = match expand_restored_databases(settings, bucket, element).await {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    for element in stored.iter().filter(|stored| {
+        !discovered
+            .iter()
+            .any(|discovered| discovered.element_title == stored.element_title)
+    }) {
+        info!(
+            "Applying retention to {}, which is no longer on the server",
+            element.element_title
+        );
+
+        let path_str = format!("{}/{}", settings.backup_dir, element.element_title);
+        let storages = get_storages(settings, bucket, element);
+
+        if let Err(e) = delete_outdated_backups(element, Path::new(&path_str), &storages).await {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Performs the backup of a single element: creates the backup (or syncs an S3 sync element), uploads it to every
+/// storage target and deletes outdated local and stored backups.
+///
+/// Elements with `all_databases: auto` are expanded by `run_element_backup` before this function is called,
+/// so each discovered database is backed up as a separate element.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the backup directory and named S3 credentials.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backup file will be uploaded.
+/// - `element` - The element to back up.
+///
+/// # Returns
+/// - `Ok(Some(u64))` - The size of the uploaded backup file (`0` for S3 sync elements).
+/// - `Ok(None)` - The element was skipped, because the backup is performed by another host.
+/// - `Err(String)` - An error message if the backup, the upload to any storage target or the deletion of outdated
+///   backups fails.
+///
+/// # Example
+/// ```rust
+/// for element in &element.expand_databases()? {
+///     backup_element(&settings, &bucket, element).await?;
+/// }
+/// ```
+async fn backup_element(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+) -> Result<Option<u64>, String> {
+    let path_str = format!("{}/{}", settings.backup_dir, element.element_title);
+    let path = Path::new(&path_str);
+
+    if !path.exists() {
+        if let Err(e) = fs::create_dir_all(path) {
+            return Err(format!("Failed to create backup dir {}: {}", path.display(), e));
+        }
+        info!("Created backup dir {}", path.display());
+    }
+
+    let upload_bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            return Err(format!(
+                "Failed to create S3 bucket for {}",
+                element.element_title
+            ));
+        }
+    };
+
+    let lock = match element.lock_period_minutes {
+        Some(period) => {
+            let settle_secs = settings.lock_settle_seconds.unwrap_or(DEFAULT_LOCK_SETTLE_SECS);
+            let Some(lock_bucket) = settings.resolve_bucket(bucket, element.lock_bucket_credentials()) else {
+                return Err(format!(
+                    "Failed to create S3 lock bucket for {}",
+                    element.element_title
+                ));
+            };
+
+            match acquire_s3_lock(&lock_bucket, &element.element_title, period, settle_secs).await {
+                Ok(Some(lock)) => Some(lock),
+                Ok(None) => {
+                    info!(
+                        "Skipping {}: the backup is performed by another host",
+                        element.element_title
+                    );
+                    return Ok(None);
+                }
+                Err(e) => {
+                    return Err(format!(
+                        "Failed to acquire lock for {}: {}",
+                        element.element_title, e
+                    ));
+                }
+            }
+        }
+        None => None,
+    };
+
+    let storages = get_storages(settings, bucket, element);
+    let is_sync = element.get_sync_source().is_some();
+
+    let uploaded = match element.get_sync_source() {
+        Some(source) => sync_element(settings, bucket, element, source, path)
+            .await
+            .map(|_| (0, Vec::new())),
+        None => create_backup(settings, &upload_bucket, element, path, &storages).await,
+    };
+
+    // The lock is only kept if every target received the backup. On all other paths (including panics, see
+    // `S3LockGuard`), it is released, so another host can retry within the same period.
+    if let Some(lock) = lock {
+        match &uploaded {
+            Ok((_, errors)) if errors.is_empty() => lock.keep(),
+            _ => lock.release().await,
+        }
+    }
+
+    let (size, mut errors) = uploaded?;
+
+    if is_sync {
+        return Ok(Some(size));
+    }
+
+    if let Err(e) = delete_outdated_backups(element, path, &storages).await {
+        errors.push(e);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+
+    Ok(Some(size))
+}
+
+/// Deletes the outdated local backups, local snapshots and stored backups of an element.
+///
+/// The local backups are checked with `RetentionPolicy::local` and the backups in every storage target with
+/// `RetentionPolicy::stored` (whole chains for incremental elements). A failed storage target does not stop the
+/// retention of the others.
+///
+/// # Arguments
+/// - `element` - The element whose backups are checked.
+/// - `path` - The local backup directory of the element. Skipped if it does not exist.
+/// - `storages` - The storage targets of the element.
+///
+/// # Returns
+/// - `Ok(())` if all outdated backups are deleted.
+/// - `Err(String)` - An error message if the local backups or any storage target cannot be checked or cleaned.
+///
+/// # Example
+/// ```rust
+/// let storages = get_storages(&settings, &bucket, &element);
+/// delete_outdated_backups(&element, path, &storages).await?;
+/// ```
+async fn delete_outdated_backups(
+    element: &Elements,
+    path: &Path,
+    storages: &[Storage<'_>],
+) -> Result<(), String> {
+    if path.exists() {
+        if let Err(e) = check_outdated_local_backups(path, &RetentionPolicy::local(element)) {
+            return Err(format!(
+                "Failed to delete outdated local backups for {}: {}",
                 element.element_title, e
-            );
-            continue;
+            ));
         }
 
-        if let Err(e) = check_outdated_local_backups(
-            &path,
+        if let Err(e) = check_outdated_local_snapshots(
+            &path.join(LOCAL_SNAPSHOTS_DIR),
             &element.backup_retention_days,
         ) {
             error!(
-                "Failed to delete outdated local backups for {}: {}",
+                "Failed to delete outdated local snapshots for {}: {}",
                 element.element_title, e
             );
-            continue;
         }
+    }
 
-        if let Err(e) = check_outdated_s3_backups(
-            &bucket,
-            &element.s3_folder,
-            &element.s3_backup_retention_days,
-        )
-        .await
-        {
-            error!(
-                "Failed to delete outdated backups from S3 for {}: {}",
+    let policy = RetentionPolicy::stored(element);
+    let mut errors = Vec::new();
+
+    for storage in storages {
+        let result = if element.is_incremental() {
+            delete_outdated_chains(storage, &element.s3_folder, &policy).await
+        } else {
+            storage.delete_outdated(&element.s3_folder, &policy).await
+        };
+
+        if let Err(e) = result {
+            errors.push(format!(
+                "Failed to delete outdated backups from {} for {}: {}",
+                storage.describe(),
+                element.element_title,
+                e
+            ));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+
+    Ok(())
+}
+
+/// Creates the backup of an element and uploads it to every storage target.
+///
+/// If streaming is enabled for the element (`stream_backups`), the element is a database with a dump command and
+/// its only storage target is S3, the dump is streamed directly into the bucket with `stream_backup_to_s3`.
+/// Otherwise, the backup is written to a local file, compressed and encrypted with `encode_backup` and uploaded
+/// to each storage target. A failed target does not stop the upload to the others.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the compression, encryption and streaming settings.
+/// - `upload_bucket` - The S3 bucket (created with the credentials of the element) used for streaming.
+/// - `element` - The element to back up.
+/// - `path` - The local backup directory of the element.
+/// - `storages` - The storage targets of the element.
+///
+/// # Returns
+/// - `Ok((u64, Vec<String>))` - The size of the backup and the errors of the storage targets that failed to
+///   receive it (at least one target received the backup).
+/// - `Err(String)` - An error message if the backup cannot be created or encoded, or no target received it.
+///
+/// # Example
+/// ```rust
+/// let storages = get_storages(&settings, &bucket, &element);
+/// let (size, errors) = create_backup(&settings, &upload_bucket, &element, path, &storages).await?;
+/// ```
+async fn create_backup(
+    settings: &Settings,
+    upload_bucket: &Bucket,
+    element: &Elements,
+    path: &Path,
+    storages: &[Storage<'_>],
+) -> Result<(u64, Vec<String>), String> {
+    if element.stream_backups.unwrap_or(settings.stream_backups) {
+        if element.has_dump_command() && matches!(storages, [Storage::S3(_)]) {
+            let dump = element.get_dump_command()?;
+
+            return stream_backup_to_s3(settings, upload_bucket, element, &dump)
+                .await
+                .map(|size| (size, Vec::new()))
+                .map_err(|e| {
+                    format!(
+                        "Failed to stream the backup of {} to S3: {}",
+                        element.element_title, e
+                    )
+                });
+        }
+
+        info!(
+            "Streaming is only supported for database dumps stored in S3 only, backing up {} through a local file",
+            element.element_title
+        );
+    }
+
+    let file_path = match element.perform_backup(path).await {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(format!(
+                "Backup process encountered an error for {}: {}",
                 element.element_title, e
-            );
-            continue;
+            ));
+        }
+    };
+
+    let file_path = match encode_backup(settings, element, &file_path).await {
+        Ok(f) => f,
+        Err(e) => {
+            if element.is_incremental() {
+                reset_incremental_chain(path, &element.element_title);
+            }
+            return Err(format!(
+                "Failed to compress or encrypt the backup of {}: {}",
+                element.element_title, e
+            ));
+        }
+    };
+
+    let size = fs::metadata(&file_path).map(|metadata| metadata.len()).unwrap_or(0);
+    let mut errors = Vec::new();
+
+    for storage in storages {
+        if let Err(e) = storage.upload(&file_path, &element.s3_folder).await {
+            errors.push(format!(
+                "Failed to upload file to {} for {}: {}",
+                storage.describe(),
+                element.element_title,
+                e
+            ));
+        }
+    }
+
+    // The targets that did not receive the increment could not restore the following ones.
+    if !errors.is_empty() && element.is_incremental() {
+        reset_incremental_chain(path, &element.element_title);
+    }
+
+    if errors.len() == storages.len() {
+        return Err(errors.join("; "));
+    }
+
+    Ok((size, errors))
+}
+
+/// Mirrors the source prefix of an S3 sync element into the element's S3 folder.
+///
+/// After the objects are synced, mirrored objects that were removed from the source and exceed
+/// `s3_backup_retention_days` are deleted using the prune credentials of the element.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the named S3 credentials.
+/// - `bucket` - The S3 bucket (created with the default keys) where the objects will be mirrored.
+/// - `element` - The S3 sync element.
+/// - `source` - The result of `Elements::get_sync_source`.
+/// - `path` - The local directory used to stage objects that cannot be copied server-side.
+///
+/// # Returns
+/// - `Ok(())` if the objects are mirrored and outdated mirrored objects are deleted.
+/// - `Err(String)` - An error message if any step fails.
+///
+/// # Example
+/// ```rust
+/// if let Some(source) = element.get_sync_source() {
+///     sync_element(&settings, &bucket, &element, source, &path).await?;
+/// }
+/// ```
+async fn sync_element(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+    source: Result<(Bucket, &String), String>,
+    path: &Path,
+) -> Result<(), String> {
+    let (source_bucket, source_prefix) = source?;
+
+    let upload_bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            return Err(format!(
+                "Failed to create S3 bucket for {}",
+                element.element_title
+            ));
         }
+    };
+
+    let source_keys = match sync_s3_prefix(
+        &source_bucket,
+        source_prefix,
+        &upload_bucket,
+        &element.s3_folder,
+        path,
+    )
+    .await
+    {
+        Ok(keys) => keys,
+        Err(e) => {
+            return Err(format!(
+                "Failed to sync S3 objects for {}: {}",
+                element.element_title, e
+            ));
+        }
+    };
+
+    let prune_credentials = element
+        .s3_prune_credentials
+        .as_ref()
+        .or(element.s3_credentials.as_ref());
+
+    let prune_bucket = match settings.resolve_bucket(bucket, prune_credentials) {
+        Some(b) => b,
+        None => {
+            return Err(format!(
+                "Failed to create S3 bucket for pruning {}",
+                element.element_title
+            ));
+        }
+    };
+
+    if let Err(e) = check_outdated_s3_mirror(
+        &prune_bucket,
+        &element.s3_folder,
+        &source_keys,
+        &element.s3_backup_retention_days,
+    )
+    .await
+    {
+        return Err(format!(
+            "Failed to delete outdated mirrored objects from S3 for {}: {}",
+            element.element_title, e
+        ));
     }
+
+    Ok(())
 }
@@ -0,0 +1,86 @@
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::{find_element, get_positional_args};
+use crate::utils::incremental_utils::check_not_incremental;
+use crate::utils::s3_utils::{resolve_s3_backup_key, wait_for_glacier_restore};
+use crate::utils::storage_utils::check_s3_storage;
+use log::{error, info};
+use s3::Bucket;
+use tokio::io::AsyncWriteExt;
+
+/// Streams a backup of the selected element to the standard output.
+///
+/// The command has the form `reback cat <element> [key]`. If no key is provided, the latest backup of the
+/// element is streamed. The key can be either the full S3 key or the file name within the element's S3 folder.
+/// The backup is written to stdout as it is downloaded, without creating a temporary file, which allows
+/// pipelines such as `reback cat my_pg_db | psql -d scratch`. Compressed and encrypted backups are streamed as they
+/// are stored (e.g., `reback cat my_pg_db | age -d -i key.txt | zstd -d | psql -d scratch`).
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// If any of the following occurs, the error is logged and written to stderr, and the process exits with status
+/// `1`, so a pipeline such as `reback cat my_pg_db | pg_restore` does not silently succeed without a backup:
+/// - The element is not specified or not found.
+/// - The element is not stored in S3 (see `check_s3_storage`) or has incremental backups (see
+///   `check_not_incremental`).
+/// - The backup cannot be found or downloaded.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "cat", "my_pg_db"];
+/// cat_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn cat_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    match stream_backup(settings, bucket, args).await {
+        Ok(key) => info!("Streamed {} to stdout", key),
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Streams the selected backup to the standard output (see `cat_process`).
+///
+/// # Returns
+/// - `Ok(String)` - The key of the streamed backup.
+/// - `Err(String)` - An error message if the backup cannot be found or streamed.
+async fn stream_backup(settings: &Settings, bucket: &Bucket, args: &[String]) -> Result<String, String> {
+    let positional = get_positional_args(args, &[]);
+
+    let title = positional.first().ok_or("No element provided to stream.")?;
+    let element = find_element(settings, title).ok_or(format!("No matching element found: {}", title))?;
+
+    check_s3_storage(settings, element)?;
+    check_not_incremental(element, "cat")?;
+
+    let bucket = settings
+        .resolve_bucket(bucket, element.s3_credentials.as_ref())
+        .ok_or(format!("Failed to create S3 bucket for {}", element.element_title))?;
+
+    let key = resolve_s3_backup_key(&bucket, &element.s3_folder, positional.get(1).copied())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    wait_for_glacier_restore(&bucket, &key, &settings.glacier_restore)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut stdout = tokio::io::stdout();
+
+    bucket
+        .get_object_to_writer(&key, &mut stdout)
+        .await
+        .map_err(|e| format!("Failed to stream {} to stdout: {}", key, e))?;
+
+    stdout
+        .flush()
+        .await
+        .map_err(|e| format!("Failed to flush stdout: {}", e))?;
+
+    Ok(key)
+}
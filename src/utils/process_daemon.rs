@@ -0,0 +1,164 @@
+use crate::structures::settings::Settings;
+use crate::utils::process_backup::run_element_backup;
+use crate::utils::time_utils::{get_next_run, now, parse_schedule};
+use chrono::{DateTime, FixedOffset};
+use croner::Cron;
+use log::{error, info, warn};
+use s3::Bucket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+/// Represents an element scheduled by the daemon.
+///
+/// # Fields
+/// - `index` - The index of the element in `Settings::elements`.
+/// - `schedule` - The parsed cron schedule of the element.
+/// - `next_run` - The time of the next backup.
+/// - `running` - Whether a backup of the element is currently running.
+struct ScheduledElement {
+    index: usize,
+    schedule: Cron,
+    next_run: DateTime<FixedOffset>,
+    running: Arc<AtomicBool>,
+}
+
+/// Clears the `running` flag of a scheduled element when its backup ends, including by a panic.
+struct RunningGuard(Arc<AtomicBool>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Runs reback as a long-running process that backs up each element according to its `schedule`.
+///
+/// The command has the form `reback daemon`. Every element with a `schedule` (cron expression) is backed up
+/// independently with `run_element_backup`, so the backups behave exactly like `reback backup` for that element.
+/// Elements without a schedule are ignored. Schedules are evaluated in the configured timezone.
+///
+/// Backups run concurrently on separate threads, so a long folder archive does not delay an hourly database dump.
+/// If the previous backup of an element is still running when the next run is due, the run is skipped and a warning
+/// is logged. A backup that panics is logged as failed and does not block the next runs of its element. The next
+/// scheduled run of each element is logged after every run. Notifications are sent for each element, but there is
+/// no end-of-run summary.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the elements and their schedules.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backup files will be uploaded.
+///
+/// # Errors
+/// This function will log an error and return if:
+/// - A schedule is not a valid cron expression.
+/// - No element has a schedule.
+///
+/// # Example
+/// ```rust
+/// daemon_process(settings, bucket).await;
+/// ```
+pub async fn daemon_process(settings: Settings, bucket: Bucket) {
+    let mut scheduled = Vec::new();
+
+    for (index, element) in settings.elements.iter().enumerate() {
+        let Some(expression) = &element.schedule else {
+            continue;
+        };
+
+        let schedule = match parse_schedule(expression) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                error!("Invalid schedule for {}: {}", element.element_title, e);
+                return;
+            }
+        };
+
+        match get_next_run(&schedule) {
+            Some(next_run) => {
+                info!("Next backup of {}: {}", element.element_title, next_run);
+                scheduled.push(ScheduledElement {
+                    index,
+                    schedule,
+                    next_run,
+                    running: Arc::new(AtomicBool::new(false)),
+                });
+            }
+            None => warn!("Schedule of {} has no upcoming runs", element.element_title),
+        }
+    }
+
+    if scheduled.is_empty() {
+        error!("No elements with a schedule found. Exiting.");
+        return;
+    }
+
+    info!("Daemon started with {} scheduled elements", scheduled.len());
+
+    let settings = Arc::new(settings);
+    let bucket = Arc::new(bucket);
+
+    loop {
+        let Some(next_run) = scheduled.iter().map(|element| element.next_run).min() else {
+            error!("No upcoming scheduled backups. Exiting.");
+            return;
+        };
+
+        if let Ok(delay) = (next_run - now()).to_std() {
+            tokio::time::sleep(delay).await;
+        }
+
+        let current_time = now();
+
+        scheduled.retain_mut(|element| {
+            if element.next_run > current_time {
+                return true;
+            }
+
+            let title = &settings.elements[element.index].element_title;
+
+            if element.running.swap(true, Ordering::SeqCst) {
+                warn!(
+                    "Skipping scheduled backup of {}: the previous backup is still running",
+                    title
+                );
+            } else {
+                info!("Starting scheduled backup of {}", title);
+
+                let settings = Arc::clone(&settings);
+                let bucket = Arc::clone(&bucket);
+                let running = Arc::clone(&element.running);
+                let index = element.index;
+                let runtime = Handle::current();
+
+                // Backups run external commands synchronously, so each one gets its own blocking thread
+                // instead of occupying a runtime worker.
+                let backup = tokio::task::spawn_blocking(move || {
+                    // Clears the flag even if the backup panics, so the next runs of the element are not skipped.
+                    let _running = RunningGuard(running);
+                    let element = &settings.elements[index];
+                    runtime.block_on(run_element_backup(&settings, &bucket, element));
+                    info!("Finished scheduled backup of {}", element.element_title);
+                });
+
+                let title = title.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = backup.await {
+                        error!("Scheduled backup of {} failed: {}", title, e);
+                    }
+                });
+            }
+
+            match get_next_run(&element.schedule) {
+                Some(next_run) => {
+                    info!("Next backup of {}: {}", title, next_run);
+                    element.next_run = next_run;
+                    true
+                }
+                None => {
+                    warn!("Schedule of {} has no upcoming runs", title);
+                    false
+                }
+            }
+        });
+    }
+}
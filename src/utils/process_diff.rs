@@ -0,0 +1,203 @@
+use crate::structures::backup_params::BackupParams;
+use crate::structures::settings::Settings;
+use crate::utils::archive_utils::{list_archive, normalize_archive_path, EntryKind};
+use crate::utils::args_utils::{find_element, get_positional_args};
+use crate::utils::fs_utils::get_file_sha256;
+use crate::utils::incremental_utils::check_not_incremental;
+use crate::utils::pipeline_utils::decode_backup;
+use crate::utils::s3_utils::{download_s3_object, resolve_s3_backup_key, wait_for_glacier_restore};
+use crate::utils::storage_utils::check_s3_storage;
+use log::{error, info, warn};
+use s3::Bucket;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Compares a folder element with its latest backup (or the backup with the given key).
+///
+/// The command has the form `reback diff <element> [key]`. The archive is downloaded to `<backup_dir>/to_diff`
+/// and indexed without extracting it. Each difference is printed as one line:
+/// - `added` - The file exists in the live directory, but not in the backup.
+/// - `removed` - The file exists in the backup, but not in the live directory (a restore would recreate it).
+/// - `changed` - The content or link target differs (a restore would overwrite it).
+///
+/// Files are compared by size and SHA-256 checksum, symbolic links by their targets. Directories are not reported.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - The element is not specified, not found or is not a folder element.
+/// - The element is not stored in S3 (see `check_s3_storage`) or has incremental backups (see
+///   `check_not_incremental`).
+/// - The backup cannot be downloaded or read.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "diff", "configs"];
+/// diff_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn diff_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let positional = get_positional_args(args, &[]);
+
+    let element = match positional.first() {
+        Some(title) => match find_element(settings, title) {
+            Some(element) => element,
+            None => {
+                error!("No matching element found: {}", title);
+                return;
+            }
+        },
+        None => {
+            error!("No element provided to diff.");
+            return;
+        }
+    };
+
+    let target_path = match &element.params {
+        Some(BackupParams::Folder { target_path, .. }) => Path::new(target_path),
+        _ => {
+            error!("Element '{}' is not a folder element", element.element_title);
+            return;
+        }
+    };
+
+    if let Err(e) = check_s3_storage(settings, element).and_then(|_| check_not_incremental(element, "diff")) {
+        error!("{}", e);
+        return;
+    }
+
+    let bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for {}", element.element_title);
+            return;
+        }
+    };
+
+    let key = match resolve_s3_backup_key(&bucket, &element.s3_folder, positional.get(1).copied()).await {
+        Ok(key) => key,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = wait_for_glacier_restore(&bucket, &key, &settings.glacier_restore).await {
+        error!("{}", e);
+        return;
+    }
+
+    let temp_dir = Path::new(&settings.backup_dir).join("to_diff");
+    let file_path = match download_s3_object(&bucket, &key, &temp_dir).await {
+        Ok(file_path) => file_path,
+        Err(e) => {
+            error!("Failed to download backup {}: {}", key, e);
+            return;
+        }
+    };
+
+    let file_path = match decode_backup(settings, &file_path) {
+        Ok(decoded_path) => decoded_path,
+        Err(e) => {
+            error!("{}", e);
+            let _ = fs::remove_file(&file_path);
+            return;
+        }
+    };
+
+    let entries = list_archive(&file_path, true);
+
+    if let Err(e) = fs::remove_file(&file_path) {
+        warn!("Failed to remove temporary file {}: {}", file_path.display(), e);
+    }
+
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read backup {}: {}", key, e);
+            return;
+        }
+    };
+
+    let mut backup_files = BTreeMap::new();
+    for entry in entries {
+        if entry.kind != EntryKind::Directory {
+            backup_files.insert(entry.path.clone(), entry);
+        }
+    }
+
+    let mut live_files = BTreeMap::new();
+    for entry in WalkDir::new(target_path).min_depth(1) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Failed to read {}: {}", target_path.display(), e);
+                continue;
+            }
+        };
+
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        if let Ok(relative) = entry.path().strip_prefix(target_path) {
+            let path = normalize_archive_path(&relative.to_string_lossy().replace('\\', "/"));
+            live_files.insert(path, entry.into_path());
+        }
+    }
+
+    println!("Comparing {} with {}", target_path.display(), key);
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+
+    for (path, entry) in &backup_files {
+        let live_path = match live_files.get(path) {
+            Some(live_path) => live_path,
+            None => {
+                println!("removed\t{}", path);
+                removed += 1;
+                continue;
+            }
+        };
+
+        let is_changed = match entry.kind {
+            EntryKind::Symlink => fs::read_link(live_path)
+                .map(|target| Some(target.to_string_lossy().to_string()) != entry.link_target)
+                .unwrap_or(true),
+            _ => match fs::symlink_metadata(live_path) {
+                Ok(metadata) if metadata.is_file() && metadata.len() == entry.size => {
+                    get_file_sha256(live_path).ok() != entry.checksum
+                }
+                _ => true,
+            },
+        };
+
+        if is_changed {
+            println!("changed\t{}", path);
+            changed += 1;
+        }
+    }
+
+    for path in live_files.keys() {
+        if !backup_files.contains_key(path) {
+            println!("added\t{}", path);
+            added += 1;
+        }
+    }
+
+    println!("{} added, {} removed, {} changed", added, removed, changed);
+
+    info!(
+        "Compared {} with {}: {} added, {} removed, {} changed",
+        target_path.display(),
+        key,
+        added,
+        removed,
+        changed
+    );
+}
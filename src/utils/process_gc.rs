@@ -0,0 +1,199 @@
+use crate::structures::backup_params::BackupParams;
+use crate::structures::elements::BACKUP_TIMESTAMP_FORMAT;
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::get_option_value;
+use crate::utils::lock_utils::LOCK_PREFIX;
+use crate::utils::s3_utils::CHECKSUM_PREFIX;
+use chrono::NaiveDateTime;
+use log::{error, info};
+use s3::Bucket;
+
+/// Lists (and optionally deletes) S3 objects that do not belong to any configured element.
+///
+/// The command has the form `reback gc [--prefix <prefix> | --all] [--credentials <name>] [--delete]`. The objects
+/// under the scanned prefix (see `get_gc_prefix`) are checked with `is_known_object`. Objects that are not known
+/// are printed together with their size. With `--delete`, they are also deleted using the default keys or the
+/// `s3_credentials` entry selected by `--credentials`.
+///
+/// Without `--delete`, the whole bucket is scanned unless `--prefix` is given, so objects left in folders that no
+/// element uses anymore (e.g., after an element was renamed) are reported too. Deleting requires an explicit
+/// `--prefix` or `--all`, so objects of other applications sharing the bucket are never deleted by accident.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - `--delete` is given without `--prefix` or `--all`.
+/// - The credentials cannot be resolved.
+/// - The bucket cannot be listed.
+/// - An orphan object cannot be deleted. Other objects are still processed.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "gc", "--prefix", "old_backups", "--delete"];
+/// gc_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn gc_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let delete = args.iter().any(|arg| arg == "--delete");
+    let prefix = match get_gc_prefix(args, delete) {
+        Ok(prefix) => prefix,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    let bucket = match settings.resolve_bucket(bucket, get_option_value(args, "--credentials")) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for garbage collection");
+            return;
+        }
+    };
+
+    info!("Scanning {} for orphan objects", if prefix.is_empty() { "the whole bucket" } else { &prefix });
+
+    let results = match bucket.list(prefix, None).await {
+        Ok(results) => results,
+        Err(e) => {
+            error!("Failed to get list of s3 objects: {}", e);
+            return;
+        }
+    };
+
+    let mut orphans = 0;
+    let mut orphans_size = 0;
+
+    for result in results {
+        for object in result.contents {
+            if is_known_object(settings, &object.key) {
+                continue;
+            }
+
+            orphans += 1;
+            orphans_size += object.size;
+            println!("{}\t{}", object.size, object.key);
+
+            if delete {
+                match bucket.delete_object(&object.key).await {
+                    Ok(_) => info!("Deleted orphan object: {}", object.key),
+                    Err(e) => error!("Failed to delete orphan object {}: {}", object.key, e),
+                }
+            }
+        }
+    }
+
+    info!(
+        "Garbage collection completed: {} orphan objects, {} bytes{}",
+        orphans,
+        orphans_size,
+        if delete { " deleted" } else { "" }
+    );
+}
+
+/// Returns the S3 prefix scanned by the `gc` command.
+///
+/// # Arguments
+/// - `args` - The command-line arguments.
+/// - `delete` - Whether the orphan objects are deleted (`--delete`).
+///
+/// # Returns
+/// - `Ok(String)` - The value of `--prefix` if it is provided, otherwise `""` (the whole bucket).
+/// - `Err(String)` - An error message if `--delete` is given without `--prefix` or `--all`.
+fn get_gc_prefix(args: &[String], delete: bool) -> Result<String, String> {
+    if let Some(prefix) = get_option_value(args, "--prefix") {
+        return Ok(prefix.trim_start_matches('/').to_string());
+    }
+
+    if delete && !args.iter().any(|arg| arg == "--all") {
+        return Err(String::from(
+            "gc --delete requires --prefix <prefix> or --all, review the orphan objects without --delete first",
+        ));
+    }
+
+    Ok(String::new())
+}
+
+/// Checks whether an S3 object belongs to one of the configured elements.
+///
+/// An object is known if it is an element lock (stored under `.reback/locks`), a checksum (stored under
+/// `.reback/checksums`) of a known backup, if it is stored in the S3 folder of an S3 sync element, or if it is stored
+/// directly in the S3 folder of another element and its file name follows the backup naming pattern of that element
+/// (`element-title-YYYY-MM-DD_HH-MM-SS.ext`). Backups of databases discovered with `all_databases: auto` are stored
+/// in subfolders (`database/element-title-database-YYYY-MM-DD_HH-MM-SS.ext`).
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `key` - The S3 key of the object.
+///
+/// # Returns
+/// `true` if the object belongs to a configured element, `false` otherwise.
+fn is_known_object(settings: &Settings, key: &str) -> bool {
+    if key.starts_with(LOCK_PREFIX) {
+        return true;
+    }
+
+    if let Some(backup_key) = key
+        .strip_prefix(CHECKSUM_PREFIX)
+        .and_then(|key| key.strip_prefix('/'))
+        .and_then(|key| key.strip_suffix(".sha256"))
+    {
+        return is_known_object(settings, backup_key);
+    }
+
+    settings.elements.iter().any(|element| {
+        let folder = format!("{}/", element.s3_folder.trim_matches('/'));
+        let file_name = match key.strip_prefix(&folder) {
+            Some(file_name) => file_name,
+            None => return false,
+        };
+
+        if let Some(BackupParams::S3Sync { .. }) = element.params {
+            return true;
+        }
+
+        // Discovered databases are stored as `folder/database/title-database-timestamp...`.
+        let (file_name, title) = match file_name.split_once('/') {
+            Some((database, file_name)) if element.discovers_databases() => {
+                (file_name, format!("{}-{}", element.element_title, database))
+            }
+            _ => (file_name, element.element_title.clone()),
+        };
+
+        file_name
+            .strip_prefix(&title)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.get(..19))
+            .is_some_and(|timestamp| {
+                NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).is_ok()
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn gc_reports_the_whole_bucket_by_default() {
+        assert_eq!(get_gc_prefix(&args(&["reback", "gc"]), false).unwrap(), "");
+        assert_eq!(get_gc_prefix(&args(&["reback", "gc", "--prefix", "/old"]), false).unwrap(), "old");
+    }
+
+    #[test]
+    fn gc_deletes_only_with_an_explicit_scope() {
+        assert!(get_gc_prefix(&args(&["reback", "gc", "--delete"]), true).is_err());
+        assert_eq!(get_gc_prefix(&args(&["reback", "gc", "--delete", "--all"]), true).unwrap(), "");
+        assert_eq!(
+            get_gc_prefix(&args(&["reback", "gc", "--delete", "--prefix", "old/"]), true).unwrap(),
+            "old/"
+        );
+    }
+}
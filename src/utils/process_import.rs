@@ -0,0 +1,358 @@
+use crate::structures::elements::{Elements, BACKUP_TIMESTAMP_FORMAT};
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::{find_element, get_option_value, get_positional_args};
+use crate::utils::fs_utils::get_file_sha256;
+use crate::utils::incremental_utils::BackupLevel;
+use crate::utils::s3_utils::{
+    add_sequence_suffix, get_backup_checksum, get_backup_time, get_s3_objects_list, put_backup_checksum,
+};
+use crate::utils::time_utils;
+use crate::utils::storage_utils::check_s3_storage;
+use chrono::{DateTime, FixedOffset, Utc};
+use log::{error, info, warn};
+use s3::Bucket;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::BufReader;
+
+/// Imports pre-existing backups into the S3 folder of the selected element.
+///
+/// The command has the form `reback import <element> --from-s3 <prefix>` or
+/// `reback import <element> --from-dir <path>`. Every object under the S3 prefix (in the element's bucket)
+/// or every file in the local directory is stored in the element's S3 folder under a ReBack file name
+/// (`element-title-YYYY-MM-DD_HH-MM-SS.ext`). The timestamp is taken from the original file name if it
+/// already contains one, otherwise from the object's `last_modified` property or the file's modification time.
+/// This makes the imported backups restorable and subject to the element's retention period. Both sources store
+/// the backups under the same keys as regular backups (see `get_import_target`), so existing backups are never
+/// overwritten, and backups that were already imported are skipped, so an interrupted import can be run again.
+/// Files imported from a directory get a recorded checksum like regular backups (see `put_backup_checksum`).
+///
+/// With `--dry-run`, the planned imports are printed without copying anything.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - The element or the source is not specified, or the element is not found.
+/// - The element is not stored in S3 (see `check_s3_storage`).
+/// - The source cannot be listed.
+/// - A backup cannot be copied or uploaded. Other backups are still imported.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "import", "my_pg_db", "--from-s3", "old_dumps"];
+/// import_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn import_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let positional = get_positional_args(args, &["--from-s3", "--from-dir"]);
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+
+    let element = match positional.first() {
+        Some(title) => match find_element(settings, title) {
+            Some(element) => element,
+            None => {
+                error!("No matching element found: {}", title);
+                return;
+            }
+        },
+        None => {
+            error!("No element provided to import backups into.");
+            return;
+        }
+    };
+
+    if let Err(e) = check_s3_storage(settings, element) {
+        error!("{}", e);
+        return;
+    }
+
+    let bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for {}", element.element_title);
+            return;
+        }
+    };
+
+    let result = if let Some(prefix) = get_option_value(args, "--from-s3") {
+        import_from_s3(&bucket, element, prefix, dry_run).await
+    } else if let Some(dir) = get_option_value(args, "--from-dir") {
+        import_from_dir(&bucket, element, Path::new(dir), dry_run).await
+    } else {
+        error!("No import source provided. Use --from-s3 <prefix> or --from-dir <path>.");
+        return;
+    };
+
+    match result {
+        Ok(count) => info!(
+            "Imported {} backups into {} for {}",
+            count, element.s3_folder, element.element_title
+        ),
+        Err(e) => error!(
+            "Failed to import backups for {}: {}",
+            element.element_title, e
+        ),
+    }
+}
+
+/// Copies every object under an S3 prefix into the element's S3 folder using server-side copies.
+///
+/// # Returns
+/// - `Ok(usize)` containing the number of imported backups.
+/// - `Err(Box<dyn Error>)` if the prefix cannot be listed.
+async fn import_from_s3(
+    bucket: &Bucket,
+    element: &Elements,
+    prefix: &str,
+    dry_run: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let prefix = prefix.trim_matches('/').to_string();
+    let mut count = 0;
+
+    for result in get_s3_objects_list(bucket, &prefix).await? {
+        for object in result.contents {
+            let file_name = object.key.rsplit('/').next().unwrap_or(&object.key);
+            if file_name.is_empty() {
+                continue;
+            }
+
+            let backup_time = match get_backup_time(file_name, &object.last_modified) {
+                Some(time) => time,
+                None => {
+                    warn!("Failed to determine backup time for {}, skipping", object.key);
+                    continue;
+                }
+            };
+
+            let target_name = get_import_file_name(element, file_name, backup_time);
+            if format!("{}/{}", element.s3_folder, target_name) == object.key.trim_start_matches('/') {
+                continue;
+            }
+
+            let source = ImportSource {
+                size: object.size,
+                e_tag: object.e_tag.clone(),
+                checksum: get_backup_checksum(bucket, &object.key).await.ok().flatten(),
+            };
+
+            let target_key = match get_import_target(bucket, &element.s3_folder, &target_name, &source).await {
+                ImportTarget::Imported(key) => {
+                    info!("{} is already imported as {}, skipping", object.key, key);
+                    continue;
+                }
+                ImportTarget::New(key) => key,
+            };
+
+            println!("{} -> {}", object.key, target_key);
+            if dry_run {
+                continue;
+            }
+
+            if let Err(e) = bucket.copy_object_internal(&object.key, &target_key).await {
+                error!("Failed to import {}: {}", object.key, e);
+                continue;
+            }
+
+            info!("Imported {} as {}", object.key, target_key);
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Uploads every file in a local directory into the element's S3 folder.
+///
+/// # Returns
+/// - `Ok(usize)` containing the number of imported backups.
+/// - `Err(Box<dyn Error>)` if the directory cannot be read.
+async fn import_from_dir(
+    bucket: &Bucket,
+    element: &Elements,
+    dir: &Path,
+    dry_run: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let file_path = entry?.path();
+        if !file_path.is_file() {
+            continue;
+        }
+
+        let file_name = match file_path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+
+        let modified = fs::metadata(&file_path)
+            .and_then(|metadata| metadata.modified())
+            .map(|time| {
+                time_utils::to_configured(&DateTime::<Utc>::from(time)).to_rfc3339()
+            })
+            .unwrap_or_default();
+
+        let backup_time = match get_backup_time(&file_name, &modified) {
+            Some(time) => time,
+            None => {
+                warn!("Failed to determine backup time for {}, skipping", file_path.display());
+                continue;
+            }
+        };
+
+        let checksum = get_file_sha256(&file_path)?;
+        let source = ImportSource {
+            size: fs::metadata(&file_path)?.len(),
+            e_tag: None,
+            checksum: Some(checksum.clone()),
+        };
+
+        let target_name = get_import_file_name(element, &file_name, backup_time);
+        let target_key = match get_import_target(bucket, &element.s3_folder, &target_name, &source).await {
+            ImportTarget::Imported(key) => {
+                info!("{} is already imported as {}, skipping", file_path.display(), key);
+                continue;
+            }
+            ImportTarget::New(key) => key,
+        };
+
+        println!("{} -> {}", file_path.display(), target_key);
+        if dry_run {
+            continue;
+        }
+
+        let mut reader = BufReader::new(File::open(&file_path).await?);
+        if let Err(e) = bucket.put_object_stream(&mut reader, &target_key).await {
+            error!("Failed to import {}: {}", file_path.display(), e);
+            continue;
+        }
+
+        put_backup_checksum(bucket, &target_key, &checksum).await;
+
+        info!("Imported {} as {}", file_path.display(), target_key);
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// The size and content identifiers of a backup to import.
+struct ImportSource {
+    size: u64,
+    e_tag: Option<String>,
+    checksum: Option<String>,
+}
+
+/// The S3 key of an imported backup.
+enum ImportTarget {
+    /// The backup was already imported under this key.
+    Imported(String),
+    /// The backup is not imported yet and can be stored under this free key.
+    New(String),
+}
+
+/// Looks for an earlier import of a backup under its target name and the names with sequence suffixes (see
+/// `get_available_s3_key`) and returns its key or the first free key.
+///
+/// An existing object is the same backup if it has the same size and the same recorded checksum or, without
+/// checksums, the same ETag (unless the source was uploaded in parts, see `is_migrated` of `migrate`).
+async fn get_import_target(
+    bucket: &Bucket,
+    s3_folder: &str,
+    target_name: &str,
+    source: &ImportSource,
+) -> ImportTarget {
+    let mut key = format!("/{}/{}", s3_folder, target_name);
+    let mut sequence = 0;
+
+    while let Ok((head, 200)) = bucket.head_object(&key).await {
+        if head.content_length == Some(source.size as i64) {
+            let is_same = match (&source.checksum, get_backup_checksum(bucket, &key).await) {
+                (Some(source_checksum), Ok(Some(target_checksum))) => *source_checksum == target_checksum,
+                _ => source
+                    .e_tag
+                    .as_ref()
+                    .is_some_and(|e_tag| !e_tag.contains('-') && head.e_tag.as_ref() == Some(e_tag)),
+            };
+
+            if is_same {
+                return ImportTarget::Imported(key);
+            }
+        }
+
+        sequence += 1;
+        key = format!("/{}/{}", s3_folder, add_sequence_suffix(target_name, sequence));
+    }
+
+    ImportTarget::New(key)
+}
+
+/// The suffixes added to the extension of a backup file by compression and encryption.
+const OUTER_SUFFIXES: [&str; 5] = [".age", ".gz", ".zst", ".bz2", ".xz"];
+
+/// Builds the ReBack file name of an imported backup, keeping the extension of the original file.
+///
+/// # Example
+/// ```rust
+/// // "dump.sql.gz" of "my_pg_db" -> "my_pg_db-2024-05-01_02-00-00.sql.gz"
+/// let file_name = get_import_file_name(&element, "dump.sql.gz", backup_time);
+/// ```
+fn get_import_file_name(element: &Elements, file_name: &str, backup_time: DateTime<FixedOffset>) -> String {
+    format!(
+        "{}-{}{}",
+        element.element_title,
+        backup_time.format(BACKUP_TIMESTAMP_FORMAT),
+        get_import_extension(file_name)
+    )
+}
+
+/// Returns the extension of an imported file: the compression and encryption suffixes (see `OUTER_SUFFIXES`),
+/// the extension before them and, for archives of incremental chains, their level (see `BackupLevel`).
+/// Other dots in the file name (e.g., `db.2024.05.01.sql.gz`) are not part of the extension.
+fn get_import_extension(file_name: &str) -> &str {
+    let mut stem = file_name;
+    while let Some(rest) = OUTER_SUFFIXES.iter().find_map(|suffix| stem.strip_suffix(suffix)) {
+        stem = rest;
+    }
+
+    if let Some((rest, _)) = stem.rsplit_once('.').filter(|(rest, _)| !rest.is_empty()) {
+        stem = rest;
+
+        if BackupLevel::from_file_name(file_name).is_some() {
+            stem = stem.rsplit_once('.').map_or(stem, |(rest, _)| rest);
+        }
+    }
+
+    &file_name[stem.len()..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_extension_keeps_known_suffixes() {
+        let cases = [
+            ("dump.sql", ".sql"),
+            ("dump.sql.gz", ".sql.gz"),
+            ("dump.sql.zst.age", ".sql.zst.age"),
+            ("files.tar.gz", ".tar.gz"),
+            ("db.2024.05.01.sql.gz", ".sql.gz"),
+            ("my.app.backup.tar.gz", ".tar.gz"),
+            ("files-2025-01-10_02-00-00.inc2.tar.gz", ".inc2.tar.gz"),
+            ("files.full.tar.gz.age", ".full.tar.gz.age"),
+            ("dump.gz", ".gz"),
+            ("dump", ""),
+            (".hidden", ""),
+        ];
+
+        for (file_name, extension) in cases {
+            assert_eq!(get_import_extension(file_name), extension, "{}", file_name);
+        }
+    }
+}
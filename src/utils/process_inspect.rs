@@ -0,0 +1,216 @@
+use crate::structures::settings::Settings;
+use crate::utils::archive_utils::{is_zip_archive, list_archive, EntryKind};
+use crate::utils::args_utils::{find_element, get_positional_args};
+use crate::utils::incremental_utils::check_not_incremental;
+use crate::utils::pipeline_utils::decode_backup;
+use crate::utils::s3_utils::{download_s3_object, resolve_s3_backup_key, wait_for_glacier_restore};
+use crate::utils::storage_utils::check_s3_storage;
+use log::{error, info, warn};
+use s3::Bucket;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::Command;
+
+/// The signature at the beginning of PostgreSQL custom-format dumps.
+const PG_CUSTOM_DUMP_SIGNATURE: &[u8] = b"PGDMP";
+
+/// Lists the contents of a backup of the selected element without restoring it.
+///
+/// The command has the form `reback inspect <element> [key]`. If no key is provided, the latest backup is used.
+/// The backup is downloaded to `<backup_dir>/to_inspect`, and depending on its format:
+/// - For `tar.gz` and zip archives, every entry is printed with its type, size, modification time and path.
+/// - For PostgreSQL custom-format dumps, the tables are listed with `pg_restore --list`.
+/// - For plain SQL dumps (PostgreSQL and MySQL), the tables are listed from the `CREATE TABLE` statements.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - The element is not specified or not found.
+/// - The element is not stored in S3 (see `check_s3_storage`) or has incremental backups (see
+///   `check_not_incremental`).
+/// - The backup cannot be downloaded or has an unsupported format (e.g., a MongoDB archive).
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "inspect", "configs"];
+/// inspect_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn inspect_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let positional = get_positional_args(args, &[]);
+
+    let element = match positional.first() {
+        Some(title) => match find_element(settings, title) {
+            Some(element) => element,
+            None => {
+                error!("No matching element found: {}", title);
+                return;
+            }
+        },
+        None => {
+            error!("No element provided to inspect.");
+            return;
+        }
+    };
+
+    if let Err(e) = check_s3_storage(settings, element).and_then(|_| check_not_incremental(element, "inspect")) {
+        error!("{}", e);
+        return;
+    }
+
+    let bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for {}", element.element_title);
+            return;
+        }
+    };
+
+    let key = match resolve_s3_backup_key(&bucket, &element.s3_folder, positional.get(1).copied()).await {
+        Ok(key) => key,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = wait_for_glacier_restore(&bucket, &key, &settings.glacier_restore).await {
+        error!("{}", e);
+        return;
+    }
+
+    let temp_dir = Path::new(&settings.backup_dir).join("to_inspect");
+    let file_path = match download_s3_object(&bucket, &key, &temp_dir).await {
+        Ok(file_path) => file_path,
+        Err(e) => {
+            error!("Failed to download backup {}: {}", key, e);
+            return;
+        }
+    };
+
+    let file_path = match decode_backup(settings, &file_path) {
+        Ok(decoded_path) => decoded_path,
+        Err(e) => {
+            error!("{}", e);
+            let _ = fs::remove_file(&file_path);
+            return;
+        }
+    };
+
+    println!("Contents of {}:", key);
+
+    if let Err(e) = print_contents(&file_path) {
+        error!("Failed to inspect backup {}: {}", key, e);
+    } else {
+        info!("Inspected backup {}", key);
+    }
+
+    if let Err(e) = fs::remove_file(&file_path) {
+        warn!("Failed to remove temporary file {}: {}", file_path.display(), e);
+    }
+}
+
+/// Prints the contents of a downloaded backup file according to its format.
+fn print_contents(file_path: &Path) -> Result<(), Box<dyn Error>> {
+    let file_name = file_path.to_string_lossy();
+
+    if is_zip_archive(file_path) || file_name.ends_with(".tar.gz") {
+        for entry in list_archive(file_path, false)? {
+            let kind = match entry.kind {
+                EntryKind::File => '-',
+                EntryKind::Directory => 'd',
+                EntryKind::Symlink => 'l',
+            };
+            let modified = entry
+                .modified
+                .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| String::from("-"));
+
+            match entry.link_target {
+                Some(target) => println!(
+                    "{} {:>12} {} {} -> {}",
+                    kind, entry.size, modified, entry.path, target
+                ),
+                None => println!("{} {:>12} {} {}", kind, entry.size, modified, entry.path),
+            }
+        }
+        return Ok(());
+    }
+
+    if file_name.ends_with(".sql") || file_name.ends_with(".dump") {
+        let tables = if is_pg_custom_dump(file_path)? {
+            list_pg_custom_dump_tables(file_path)?
+        } else {
+            list_sql_dump_tables(file_path)?
+        };
+
+        for table in &tables {
+            println!("{}", table);
+        }
+        println!("{} tables", tables.len());
+        return Ok(());
+    }
+
+    Err(format!("Unsupported backup format: {}", file_path.display()).into())
+}
+
+/// Checks whether a file is a PostgreSQL custom-format dump by its signature.
+fn is_pg_custom_dump(file_path: &Path) -> Result<bool, Box<dyn Error>> {
+    let mut signature = [0u8; 5];
+    let read = File::open(file_path)?.read(&mut signature)?;
+
+    Ok(signature[..read] == *PG_CUSTOM_DUMP_SIGNATURE)
+}
+
+/// Lists the tables of a PostgreSQL custom-format dump using `pg_restore --list`.
+fn list_pg_custom_dump_tables(file_path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = Command::new("pg_restore").arg("--list").arg(file_path).output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "pg_restore failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    // Entries have the form `215; 1259 16386 TABLE public users postgres`.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.starts_with(';'))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace().skip_while(|part| *part != "TABLE");
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(_), Some(schema), Some(name)) if schema != "DATA" => {
+                    Some(format!("{}.{}", schema, name))
+                }
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Lists the tables of a plain SQL dump from its `CREATE TABLE` statements.
+fn list_sql_dump_tables(file_path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut tables = Vec::new();
+
+    // Dumps can contain binary data, so lines are read as bytes instead of UTF-8 strings.
+    for line in BufReader::new(File::open(file_path)?).split(b'\n') {
+        let line = String::from_utf8_lossy(&line?).to_string();
+        let Some(rest) = line.strip_prefix("CREATE TABLE ") else {
+            continue;
+        };
+        let rest = rest.strip_prefix("IF NOT EXISTS ").unwrap_or(rest);
+
+        if let Some(name) = rest.split(|c: char| c.is_whitespace() || c == '(').next() {
+            tables.push(name.trim_matches('`').to_string());
+        }
+    }
+
+    Ok(tables)
+}
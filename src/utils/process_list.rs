@@ -0,0 +1,90 @@
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::get_positional_args;
+use crate::utils::storage_utils::{get_storages, StorageBackend};
+use log::{error, info};
+use s3::Bucket;
+
+/// Prints the available backups of the selected elements.
+///
+/// The command has the form `reback list [element ...]`. If no element is provided, the backups of all elements
+/// are listed. For every storage target of an element, a header with the element title and the target is printed,
+/// followed by one line per backup, oldest first: the backup time, the size in bytes (`-` if the target does not
+/// report it) and the key. The printed time can be passed to `reback restore --at` and the key to
+/// `reback restore --key`. S3 sync elements are skipped, since they contain mirrored objects instead of backups.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - No matching elements are found for the provided arguments.
+/// - A storage target cannot be listed. Other targets and elements are still listed.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "list", "my_pg_db"];
+/// list_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn list_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let positional = get_positional_args(args, &[]);
+
+    let elements: Vec<_> = settings
+        .elements
+        .iter()
+        .filter(|element| positional.is_empty() || positional.contains(&&element.element_title))
+        .collect();
+
+    if elements.is_empty() {
+        error!("No matching elements found for the provided arguments: {:?}", args);
+        return;
+    }
+
+    for element in elements {
+        if element.get_sync_source().is_some() {
+            info!("Skipping S3 sync element {}", element.element_title);
+            continue;
+        }
+
+        for storage in get_storages(settings, bucket, element) {
+            let mut backups = match storage.list(&element.s3_folder).await {
+                Ok(backups) => backups,
+                Err(e) => {
+                    error!(
+                        "Failed to list backups of {} in {}: {}",
+                        element.element_title,
+                        storage.describe(),
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            backups.sort_by_key(|backup| backup.time);
+
+            println!("{} ({}):", element.element_title, storage.describe());
+
+            for backup in &backups {
+                let size = backup
+                    .size
+                    .map(|size| size.to_string())
+                    .unwrap_or_else(|| String::from("-"));
+
+                println!(
+                    "{} {:>12} {}",
+                    backup.time.format("%Y-%m-%d %H:%M:%S"),
+                    size,
+                    backup.key
+                );
+            }
+
+            info!(
+                "Listed {} backups of {} in {}",
+                backups.len(),
+                element.element_title,
+                storage.describe()
+            );
+        }
+    }
+}
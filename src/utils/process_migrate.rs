@@ -0,0 +1,283 @@
+use crate::structures::settings::{create_bucket, S3PathStyle, Settings};
+use crate::utils::args_utils::{find_element, get_option_value, get_positional_args};
+use crate::utils::fs_utils::{get_file_sha256, TempFile};
+use crate::utils::s3_utils::{get_backup_checksum, get_s3_objects_list, put_backup_checksum};
+use crate::utils::storage_utils::check_s3_storage;
+use log::{error, info, warn};
+use s3::serde_types::Object;
+use s3::Bucket;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::BufReader;
+
+/// The options of the `migrate` command that take a value.
+const MIGRATE_OPTIONS: [&str; 6] = [
+    "--to-endpoint",
+    "--to-region",
+    "--to-bucket",
+    "--to-folder",
+    "--to-path-style",
+    "--to-credentials",
+];
+
+/// Copies all backups of the selected element to another bucket, prefix or endpoint.
+///
+/// The command has the form `reback migrate <element> [--to-endpoint <url>] [--to-region <region>]
+/// [--to-bucket <name>] [--to-folder <folder>] [--to-path-style <path|virtual-host>] [--to-credentials <name>]`.
+/// Options that are not provided default to the values from the settings (and the element's `s3_folder`).
+/// The `--to-credentials` option selects the `s3_credentials` entry used for the target bucket.
+///
+/// When the source and the target are the same bucket, backups are copied server-side and verified by
+/// comparing their size and ETag. Otherwise, each backup is downloaded, uploaded to the target, downloaded
+/// again and verified by comparing SHA-256 checksums. The recorded checksums of the backups (see
+/// `put_backup_checksum`) are migrated along with them. Backups that already exist in the target with the same
+/// content are skipped, so an interrupted migration can be resumed (see `is_migrated`).
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - The element is not specified or not found.
+/// - The element is not stored in S3 (see `check_s3_storage`).
+/// - The target is identical to the source or cannot be created.
+/// - A backup cannot be copied or fails verification. Other backups are still migrated.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "migrate", "my_pg_db", "--to-endpoint", "https://s3.new.com", "--to-credentials", "new"];
+/// migrate_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn migrate_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let positional = get_positional_args(args, &MIGRATE_OPTIONS);
+
+    let element = match positional.first() {
+        Some(title) => match find_element(settings, title) {
+            Some(element) => element,
+            None => {
+                error!("No matching element found: {}", title);
+                return;
+            }
+        },
+        None => {
+            error!("No element provided to migrate.");
+            return;
+        }
+    };
+
+    if let Err(e) = check_s3_storage(settings, element) {
+        error!("{}", e);
+        return;
+    }
+
+    let source = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for {}", element.element_title);
+            return;
+        }
+    };
+
+    let target = match get_target_bucket(settings, args) {
+        Ok(b) => b,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    let source_folder = element.s3_folder.trim_matches('/').to_string();
+    let target_folder = get_option_value(args, "--to-folder")
+        .map(|folder| folder.trim_matches('/').to_string())
+        .unwrap_or_else(|| source_folder.clone());
+
+    let server_side = source.name() == target.name() && source.region() == target.region();
+
+    if server_side && source_folder == target_folder {
+        error!("Migration target is identical to the source for {}", element.element_title);
+        return;
+    }
+
+    let temp_dir = Path::new(&settings.backup_dir).join("to_migrate");
+    if let Err(e) = fs::create_dir_all(&temp_dir) {
+        error!("Failed to create migration dir {}: {}", temp_dir.display(), e);
+        return;
+    }
+
+    let results = match get_s3_objects_list(&source, &source_folder).await {
+        Ok(results) => results,
+        Err(_) => return,
+    };
+
+    let source_prefix = format!("{}/", source_folder);
+    let mut migrated = 0;
+    let mut failed = 0;
+
+    for result in results {
+        for object in result.contents {
+            let relative_key = match object.key.trim_start_matches('/').strip_prefix(&source_prefix) {
+                Some(relative_key) if !relative_key.is_empty() => relative_key,
+                _ => {
+                    warn!("Object {} is not in {}, skipping", object.key, source_folder);
+                    continue;
+                }
+            };
+            let target_key = format!("{}/{}", target_folder, relative_key);
+
+            if is_migrated(&source, &target, &object, &target_key).await {
+                info!("Backup {} already exists in the target, skipping", target_key);
+                continue;
+            }
+
+            let migration = if server_side {
+                copy_server_side(&source, &object, &target_key).await
+            } else {
+                copy_streamed(&source, &target, &object, &target_key, &temp_dir).await
+            };
+
+            match migration {
+                Ok(()) => {
+                    info!("Migrated {} to {}", object.key, target_key);
+                    println!("{} -> {}", object.key, target_key);
+                    migrated += 1;
+                }
+                Err(e) => {
+                    error!("Failed to migrate {}: {}", object.key, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    info!(
+        "Migration of {} completed: {} migrated, {} failed",
+        element.element_title, migrated, failed
+    );
+}
+
+/// Creates the target bucket of a migration from the command-line options and the settings.
+fn get_target_bucket(settings: &Settings, args: &[String]) -> Result<Bucket, String> {
+    let (access, secret) = match get_option_value(args, "--to-credentials") {
+        Some(name) => match settings.s3_credentials.get(name) {
+            Some(creds) => (&creds.s3_access, &creds.s3_secret),
+            None => return Err(format!("Unknown S3 credentials: {}", name)),
+        },
+        None => (&settings.s3_access, &settings.s3_secret),
+    };
+
+    let path_style = match get_option_value(args, "--to-path-style").map(|style| style.as_str()) {
+        Some("path") => &S3PathStyle::Path,
+        Some("virtual-host") => &S3PathStyle::VirtualHost,
+        Some(style) => return Err(format!("Unknown S3 path style: {}", style)),
+        None => &settings.s3_path_style,
+    };
+
+    create_bucket(
+        get_option_value(args, "--to-endpoint").unwrap_or(&settings.s3_endpoint),
+        get_option_value(args, "--to-region").unwrap_or(&settings.s3_region),
+        get_option_value(args, "--to-bucket").unwrap_or(&settings.s3_bucket),
+        access,
+        secret,
+        path_style,
+    )
+    .ok_or_else(|| "Failed to create migration target bucket".to_string())
+}
+
+/// Checks whether a backup already exists in the target with the same content.
+///
+/// The recorded SHA-256 checksums of the source and the target are compared if both exist. Otherwise, the ETags
+/// are compared, unless the source was uploaded in parts (its ETag then depends on the part size and not only on the
+/// content). The size alone is not sufficient, since an interrupted or different upload can have the same size.
+async fn is_migrated(source: &Bucket, target: &Bucket, object: &Object, target_key: &str) -> bool {
+    let head = match target.head_object(target_key).await {
+        Ok((head, 200)) => head,
+        _ => return false,
+    };
+
+    if head.content_length != Some(object.size as i64) {
+        return false;
+    }
+
+    if let (Ok(Some(source_checksum)), Ok(Some(target_checksum))) = (
+        get_backup_checksum(source, &object.key).await,
+        get_backup_checksum(target, target_key).await,
+    ) {
+        return source_checksum == target_checksum;
+    }
+
+    let is_multipart = object
+        .e_tag
+        .as_ref()
+        .is_some_and(|e_tag| e_tag.contains('-'));
+
+    !is_multipart && object.e_tag.is_some() && head.e_tag == object.e_tag
+}
+
+/// Copies a backup within the same bucket and verifies its size and ETag.
+async fn copy_server_side(
+    bucket: &Bucket,
+    object: &Object,
+    target_key: &str,
+) -> Result<(), Box<dyn Error>> {
+    bucket.copy_object_internal(&object.key, target_key).await?;
+
+    let (head, _) = bucket.head_object(target_key).await?;
+
+    if head.content_length != Some(object.size as i64) {
+        return Err(format!("Size mismatch after copying to {}", target_key).into());
+    }
+
+    let is_multipart = object
+        .e_tag
+        .as_ref()
+        .is_some_and(|e_tag| e_tag.contains('-'));
+
+    if !is_multipart && head.e_tag != object.e_tag {
+        return Err(format!("ETag mismatch after copying to {}", target_key).into());
+    }
+
+    match get_backup_checksum(bucket, &object.key).await {
+        Ok(Some(checksum)) => put_backup_checksum(bucket, target_key, &checksum).await,
+        Ok(None) => {}
+        Err(e) => warn!("Failed to read checksum of {}: {}", object.key, e),
+    }
+
+    Ok(())
+}
+
+/// Copies a backup between buckets through a temporary file and verifies its SHA-256 checksum.
+///
+/// The temporary files are deleted when the function returns, also if the copy fails.
+async fn copy_streamed(
+    source: &Bucket,
+    target: &Bucket,
+    object: &Object,
+    target_key: &str,
+    temp_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let source_file = TempFile::new(temp_dir.join("source.tmp"));
+    let verify_file = TempFile::new(temp_dir.join("verify.tmp"));
+
+    let mut writer = File::create(source_file.path()).await?;
+    source.get_object_to_writer(&object.key, &mut writer).await?;
+    let source_checksum = get_file_sha256(source_file.path())?;
+
+    let mut reader = BufReader::new(File::open(source_file.path()).await?);
+    target.put_object_stream(&mut reader, target_key).await?;
+
+    let mut writer = File::create(verify_file.path()).await?;
+    target.get_object_to_writer(target_key, &mut writer).await?;
+    let target_checksum = get_file_sha256(verify_file.path())?;
+
+    if source_checksum != target_checksum {
+        return Err(format!("Checksum mismatch after uploading to {}", target_key).into());
+    }
+
+    put_backup_checksum(target, target_key, &source_checksum).await;
+
+    Ok(())
+}
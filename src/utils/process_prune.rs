@@ -0,0 +1,251 @@
+use crate::structures::elements::Elements;
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::get_positional_args;
+use crate::utils::fs_utils::get_outdated_local_backups;
+use crate::utils::incremental_utils::select_outdated_chains;
+use crate::utils::lock_utils::{acquire_s3_lock, S3LockGuard, DEFAULT_LOCK_SETTLE_SECS};
+use crate::utils::process_restore::expand_restored_databases;
+use crate::utils::retention_utils::RetentionPolicy;
+use crate::utils::storage_utils::{get_storages, StorageBackend};
+use log::{error, info};
+use s3::Bucket;
+use std::fs;
+use std::path::Path;
+
+/// Applies the retention policies of the selected elements without creating new backups.
+///
+/// The command has the form `reback prune [element ...] [--dry-run]`. If no element is provided, all elements are
+/// pruned. The outdated backups are found exactly like after a backup (see `RetentionPolicy`): the local backups in
+/// `backup_dir` are checked with the local policy, and the backups in every storage target with the stored policy
+/// (whole chains for incremental elements). For every location, a header with the element title, the location and
+/// the policy is printed, followed by one line per outdated backup: the backup time and the key (or path).
+///
+/// With `--dry-run`, the outdated backups are only printed, so a new policy can be checked before it deletes
+/// anything. Elements with `all_databases: auto` are pruned for every database found in their S3 folder.
+/// S3 sync elements are skipped, since they contain mirrored objects instead of backups.
+///
+/// Unless `--dry-run` is set, the S3 lock of elements with `lock_period_minutes` is acquired before their backups
+/// are deleted and released afterwards. Elements whose lock is held by another host (including the lock kept for
+/// the rest of the period after a successful backup) are skipped.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - No matching elements are found for the provided arguments.
+/// - The lock of an element is held by another host or cannot be acquired. The element is skipped.
+/// - A location cannot be listed or a backup cannot be deleted. Other backups, locations and elements are still
+///   pruned.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "prune", "my_pg_db", "--dry-run"];
+/// prune_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn prune_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let positional = get_positional_args(args, &[]);
+
+    let elements: Vec<_> = settings
+        .elements
+        .iter()
+        .filter(|element| positional.is_empty() || positional.contains(&&element.element_title))
+        .collect();
+
+    if elements.is_empty() {
+        error!("No matching elements found for the provided arguments: {:?}", args);
+        return;
+    }
+
+    for element in elements {
+        if element.get_sync_source().is_some() {
+            info!("Skipping S3 sync element {}", element.element_title);
+            continue;
+        }
+
+        let expanded = if element.discovers_databases() {
+            match expand_restored_databases(settings, bucket, element).await {
+                Ok(expanded) => expanded,
+                Err(e) => {
+                    error!("{}", e);
+                    continue;
+                }
+            }
+        } else {
+            vec![element.clone()]
+        };
+
+        for element in &expanded {
+            let lock = if dry_run {
+                None
+            } else {
+                match acquire_prune_lock(settings, bucket, element).await {
+                    Ok(lock) => lock,
+                    Err(e) => {
+                        error!("{}", e);
+                        continue;
+                    }
+                }
+            };
+
+            prune_local_backups(settings, element, dry_run);
+            prune_stored_backups(settings, bucket, element, dry_run).await;
+
+            if let Some(lock) = lock {
+                lock.release().await;
+            }
+        }
+    }
+
+    info!("Prune completed{}", if dry_run { " (dry run)" } else { "" });
+}
+
+/// Acquires the S3 lock of an element with `lock_period_minutes` before its outdated backups are deleted, so
+/// `prune` never runs concurrently with a backup (and its retention) of the element on another host.
+///
+/// # Returns
+/// - `Ok(Some(S3LockGuard))` - The acquired lock, which is released after the element is pruned.
+/// - `Ok(None)` - The element has no `lock_period_minutes`, so no lock is needed.
+/// - `Err(String)` - An error message if the lock is held by another host or cannot be acquired.
+async fn acquire_prune_lock(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+) -> Result<Option<S3LockGuard>, String> {
+    let Some(period) = element.lock_period_minutes else {
+        return Ok(None);
+    };
+
+    let lock_bucket = settings
+        .resolve_bucket(bucket, element.lock_bucket_credentials())
+        .ok_or(format!("Failed to create S3 lock bucket for {}", element.element_title))?;
+    let settle_secs = settings.lock_settle_seconds.unwrap_or(DEFAULT_LOCK_SETTLE_SECS);
+
+    match acquire_s3_lock(&lock_bucket, &element.element_title, period, settle_secs).await {
+        Ok(Some(lock)) => Ok(Some(lock)),
+        Ok(None) => Err(format!(
+            "Skipping {}: the element is locked by another host",
+            element.element_title
+        )),
+        Err(e) => Err(format!(
+            "Failed to acquire lock for {}: {}",
+            element.element_title, e
+        )),
+    }
+}
+
+/// Prints (and unless `dry_run` is set, deletes) the outdated local backups of an element.
+fn prune_local_backups(settings: &Settings, element: &Elements, dry_run: bool) {
+    let path_str = format!("{}/{}", settings.backup_dir, element.element_title);
+    let path = Path::new(&path_str);
+
+    if !path.exists() {
+        return;
+    }
+
+    let policy = RetentionPolicy::local(element);
+
+    let outdated = match get_outdated_local_backups(path, &policy) {
+        Ok(outdated) => outdated,
+        Err(e) => {
+            error!(
+                "Failed to check outdated local backups of {}: {}",
+                element.element_title, e
+            );
+            return;
+        }
+    };
+
+    println!(
+        "{} (local:{}, {}):",
+        element.element_title,
+        path.display(),
+        policy.describe()
+    );
+
+    for (file_path, modified) in &outdated {
+        println!("{} {}", modified.format("%Y-%m-%d %H:%M:%S"), file_path.display());
+
+        if !dry_run {
+            match fs::remove_file(file_path) {
+                Ok(_) => info!("Deleted outdated backup: {:?}", file_path),
+                Err(e) => error!("Failed to delete outdated backup {:?}: {}", file_path, e),
+            }
+        }
+    }
+
+    report_pruned(element, &path.display().to_string(), outdated.len(), dry_run);
+}
+
+/// Prints (and unless `dry_run` is set, deletes) the outdated backups of an element in its storage targets.
+///
+/// The backups are listed with `list_for_prune`, so S3 folders are listed with the same credentials
+/// (`s3_prune_credentials`) as the outdated backups are deleted with. The outdated backups are selected by chains
+/// (see `select_outdated_chains`), which gives the same result as the per-file policy for elements without
+/// incremental backups.
+async fn prune_stored_backups(settings: &Settings, bucket: &Bucket, element: &Elements, dry_run: bool) {
+    let policy = RetentionPolicy::stored(element);
+
+    for storage in get_storages(settings, bucket, element) {
+        let outdated = storage
+            .list_for_prune(&element.s3_folder)
+            .await
+            .and_then(|backups| Ok(select_outdated_chains(backups, &policy)?));
+
+        let outdated = match outdated {
+            Ok(outdated) => outdated,
+            Err(e) => {
+                error!(
+                    "Failed to check outdated backups of {} in {}: {}",
+                    element.element_title,
+                    storage.describe(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        println!(
+            "{} ({}, {}):",
+            element.element_title,
+            storage.describe(),
+            policy.describe()
+        );
+
+        for backup in &outdated {
+            println!("{} {}", backup.time.format("%Y-%m-%d %H:%M:%S"), backup.key);
+
+            if !dry_run {
+                match storage.delete(&backup.key).await {
+                    Ok(_) => info!("Deleted outdated backup from {}: {}", storage.describe(), backup.key),
+                    Err(e) => error!(
+                        "Failed to delete outdated backup {} from {}: {}",
+                        backup.key,
+                        storage.describe(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        report_pruned(element, &storage.describe(), outdated.len(), dry_run);
+    }
+}
+
+/// Logs the number of outdated backups of an element in a location.
+fn report_pruned(element: &Elements, location: &str, count: usize, dry_run: bool) {
+    if dry_run {
+        info!(
+            "{} outdated backups of {} in {} would be deleted",
+            count, element.element_title, location
+        );
+    } else {
+        info!(
+            "Deleted {} outdated backups of {} in {}",
+            count, element.element_title, location
+        );
+    }
+}
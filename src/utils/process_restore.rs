@@ -1,7 +1,15 @@
 use crate::structures::elements::Elements;
 use crate::structures::settings::Settings;
-use crate::utils::s3_utils::get_file_from_s3;
+use crate::utils::args_utils::{get_option_value, get_positional_args};
+use crate::utils::incremental_utils::download_chain;
+use crate::utils::notify_utils::{notify_element, notify_summary, ElementReport};
+use crate::utils::pipeline_utils::decode_backup;
+use crate::utils::s3_utils::{get_s3_subfolders, sync_s3_prefix};
+use crate::utils::storage_utils::{get_storages, BackupSelector, StorageBackend};
+use crate::utils::time_utils::parse_datetime;
 use log::{error, warn};
+use std::fs;
+use std::path::Path;
 use s3::Bucket;
 
 /// Restores specified elements from an S3 bucket to the local system asynchronously.
@@ -9,12 +17,19 @@ use s3::Bucket;
 /// This function retrieves each element's backup file from the S3 bucket using the provided
 /// `restore_dir` and `s3_folder` of each element. After downloading the file, it attempts to restore
 /// the element using the `perform_restore` method. If any error occurs during downloading or restoring,
-/// it logs the error and moves to the next element.
+/// it logs the error and moves to the next element. Elements that reference named S3 credentials are
+/// downloaded with those keys instead of the default ones. S3 sync elements are restored by mirroring
+/// the element's S3 folder back into the source prefix. For elements with `all_databases: auto`, every
+/// database found in a subfolder of the element's S3 folder is restored into the database with the same name.
+/// Notifications are sent for the result of each element and with a summary at the end.
 ///
 /// # Arguments
+/// - `settings` - The configuration settings containing the named S3 credentials.
 /// - `bucket` - The S3 bucket from which the backup files will be retrieved.
 /// - `restore_dir` - The directory within the S3 bucket that contains the backup files to be restored.
 /// - `elements` - A slice of references to the elements that need to be restored.
+/// - `paths` - The paths inside folder archives to restore. Empty to restore whole backups.
+/// - `selector` - The backup of each element that is restored.
 ///
 /// # Returns
 /// This function does not return a value. It performs the restoration operation for each element,
@@ -30,22 +45,180 @@ use s3::Bucket;
 /// let bucket: Bucket = /* Obtain the S3 bucket instance */;
 /// let restore_dir = "path/to/restore".to_string();
 /// let elements: Vec<&Elements> = vec![/* elements to restore */];
-/// restore_elements(&bucket, &restore_dir, &elements).await;
+/// restore_elements(&settings, &bucket, &restore_dir, &elements, &[], &BackupSelector::Latest).await;
 /// ```
-async fn restore_elements(bucket: &Bucket, restore_dir: &String, elements: &[&Elements]) {
+async fn restore_elements(
+    settings: &Settings,
+    bucket: &Bucket,
+    restore_dir: &String,
+    elements: &[&Elements],
+    paths: &[String],
+    selector: &BackupSelector,
+) {
+    let mut reports = Vec::new();
+
     for element in elements {
-        let file_path = match get_file_from_s3(bucket, restore_dir, &element.s3_folder).await {
-            Ok(file) => file,
+        let expanded = if element.discovers_databases() {
+            expand_restored_databases(settings, bucket, element).await
+        } else {
+            Ok(vec![(*element).clone()])
+        };
+
+        let expanded = match expanded {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                error!("{}", e);
+                let report = ElementReport::failure(&element.element_title, e);
+                notify_element(settings, "restore", &report).await;
+                reports.push(report);
+                continue;
+            }
+        };
+
+        for element in &expanded {
+            let report = match restore_element(settings, bucket, restore_dir, element, paths, selector).await {
+                Ok(size) => ElementReport::success(&element.element_title, size),
+                Err(e) => {
+                    error!("{}", e.to_string());
+                    ElementReport::failure(&element.element_title, e)
+                }
+            };
+
+            notify_element(settings, "restore", &report).await;
+            reports.push(report);
+        }
+    }
+
+    notify_summary(settings, "restore", &reports).await;
+}
+
+/// Expands an element with `all_databases: auto` into one element per database found in the subfolders of its
+/// S3 folder.
+///
+/// # Returns
+/// - `Ok(Vec<Elements>)` - The elements of the backed up databases.
+/// - `Err(String)` - An error message if the S3 folder cannot be listed.
+pub async fn expand_restored_databases(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+) -> Result<Vec<Elements>, String> {
+    let element_bucket = settings
+        .resolve_bucket(bucket, element.s3_credentials.as_ref())
+        .ok_or(format!("Failed to create S3 bucket for {}", element.element_title))?;
+
+    let databases = get_s3_subfolders(&element_bucket, &element.s3_folder)
+        .await
+        .map_err(|e| format!("Failed to list the databases of {}: {}", element.element_title, e))?;
+
+    Ok(element.expand_for_databases(&databases))
+}
+
+/// Restores a single element from the backup chosen by `selector` (the latest one by default).
+///
+/// The storage targets of the element are tried in order, and the first one that has a matching backup is used.
+/// Encrypted and compressed backups are decoded with `decode_backup` before they are restored. For incremental
+/// folder elements, the full backup and all increments up to the selected archive are downloaded and restored in
+/// order. S3 sync elements are always restored from the current state of the mirror, so the selector is ignored.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the named S3 credentials.
+/// - `bucket` - The S3 bucket (created with the default keys) from which the backup will be retrieved.
+/// - `restore_dir` - The local directory where the backup file is downloaded.
+/// - `element` - The element to restore.
+/// - `paths` - The paths inside folder archives to restore. Empty to restore whole backups.
+/// - `selector` - The backup that is restored.
+///
+/// # Returns
+/// - `Ok(u64)` - The size of the restored backup files (`0` for S3 sync elements).
+/// - `Err(String)` - An error message if the backup cannot be downloaded from any storage target or restored.
+///
+/// # Example
+/// ```rust
+/// restore_element(&settings, &bucket, &restore_dir, &element, &[], &BackupSelector::Latest).await?;
+/// ```
+async fn restore_element(
+    settings: &Settings,
+    bucket: &Bucket,
+    restore_dir: &String,
+    element: &Elements,
+    paths: &[String],
+    selector: &BackupSelector,
+) -> Result<u64, String> {
+    if let Some(source) = element.get_sync_source() {
+        if !matches!(selector, BackupSelector::Latest) {
+            warn!(
+                "{} is an S3 sync element and is restored from the current mirror instead of the {}",
+                element.element_title,
+                selector.describe()
+            );
+        }
+
+        let (source_bucket, source_prefix) = source?;
+        let bucket = settings
+            .resolve_bucket(bucket, element.s3_credentials.as_ref())
+            .ok_or(format!("Failed to create S3 bucket for {}", element.element_title))?;
+
+        let temp_dir = Path::new(restore_dir);
+        fs::create_dir_all(temp_dir)
+            .map_err(|e| format!("Failed to create restore dir {}: {}", temp_dir.display(), e))?;
+
+        sync_s3_prefix(
+            &bucket,
+            &element.s3_folder,
+            &source_bucket,
+            source_prefix,
+            temp_dir,
+        )
+        .await
+        .map_err(|e| format!("Failed to restore S3 objects for {}: {}", element.element_title, e))?;
+
+        return Ok(0);
+    }
+
+    let mut errors = Vec::new();
+
+    for storage in get_storages(settings, bucket, element) {
+        let downloaded = if element.is_incremental() {
+            download_chain(&storage, &element.s3_folder, selector, Path::new(restore_dir)).await
+        } else {
+            storage
+                .download_selected(&element.s3_folder, selector, Path::new(restore_dir))
+                .await
+                .map(|file_path| vec![file_path])
+        };
+
+        let file_paths = match downloaded {
+            Ok(file_paths) => file_paths,
             Err(e) => {
-                error!("{}", e.to_string());
+                warn!(
+                    "Failed to download the {} of {} from {}: {}",
+                    selector.describe(),
+                    element.element_title,
+                    storage.describe(),
+                    e
+                );
+                errors.push(format!("{}: {}", storage.describe(), e));
                 continue;
             }
         };
 
-        if let Err(e) = element.perform_restore(&file_path).await {
-            error!("{}", e.to_string());
+        let mut size = 0;
+        for file_path in file_paths {
+            size += fs::metadata(&file_path).map(|metadata| metadata.len()).unwrap_or(0);
+            let file_path = decode_backup(settings, &file_path)?;
+            element.perform_restore(&file_path, paths).await?;
         }
+
+        return Ok(size);
     }
+
+    Err(format!(
+        "Failed to download the {} of {}: {}",
+        selector.describe(),
+        element.element_title,
+        errors.join("; ")
+    ))
 }
 
 /// Initiates the restoration process for all elements from the S3 bucket.
@@ -76,9 +249,12 @@ pub async fn restore_all_process(settings: &Settings, bucket: &Bucket) {
     let restore_dir = format!("{}/to_restore", &settings.backup_dir);
 
     restore_elements(
+        settings,
         bucket,
         &restore_dir,
         &settings.elements.iter().collect::<Vec<_>>(),
+        &[],
+        &BackupSelector::Latest,
     )
     .await;
 }
@@ -88,7 +264,14 @@ pub async fn restore_all_process(settings: &Settings, bucket: &Bucket) {
 /// This function constructs the restore directory path from the settings and filters the elements to restore
 /// based on the arguments passed to it. Only the elements whose `element_title` matches the arguments will
 /// be restored. If no matching elements are found, it logs an error. The function uses `restore_elements`
-/// to perform the restoration.
+/// to perform the restoration. With `--paths a,b`, only the listed paths are extracted from folder backups.
+///
+/// By default, the latest backup of each element is restored. An older backup can be selected with one of:
+/// - `--key <key>` - The key or file name of the backup (only for a single element).
+/// - `--before <datetime>` - The newest backup created before the given date and time.
+/// - `--at <datetime>` - The backup created at the given time, as printed by `reback list`.
+///
+/// The date and time formats are described in `parse_datetime`.
 ///
 /// # Arguments
 /// - `settings` - The configuration settings containing the elements to be restored.
@@ -100,19 +283,30 @@ pub async fn restore_all_process(settings: &Settings, bucket: &Bucket) {
 /// on the filtered arguments.
 ///
 /// # Errors
-/// This function will log an error if no matching elements are found for the provided arguments.
+/// This function will log an error if:
+/// - No matching elements are found for the provided arguments.
+/// - The backup selector is invalid, or `--key` is used with several elements.
 ///
 /// # Example
 /// ```rust
 /// let settings: Settings = /* Obtain settings from configuration */;
 /// let bucket: Bucket = /* Obtain the S3 bucket instance */;
-/// let args = vec!["restore", "element1", "element2"];
+/// let args = vec!["reback", "restore", "element1", "element2", "--before", "2025-01-10"];
 /// restore_selected_process(&settings, &bucket, &args).await;
 /// ```
 pub async fn restore_selected_process(settings: &Settings, bucket: &Bucket, args: &Vec<String>) {
     let restore_dir = format!("{}/to_restore", &settings.backup_dir);
 
-    let filtered_args: Vec<_> = args.iter().skip(2).collect();
+    let filtered_args = get_positional_args(args, &["--paths", "--key", "--before", "--at"]);
+    let paths: Vec<String> = get_option_value(args, "--paths")
+        .map(|value| {
+            value
+                .split(',')
+                .filter(|path| !path.trim().is_empty())
+                .map(|path| path.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
 
     let selected_elements: Vec<_> = settings
         .elements
@@ -128,5 +322,46 @@ pub async fn restore_selected_process(settings: &Settings, bucket: &Bucket, args
         return;
     }
 
-    restore_elements(bucket, &restore_dir, &selected_elements).await;
+    let selector = match get_backup_selector(args) {
+        Ok(selector) => selector,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    if matches!(selector, BackupSelector::Key(_)) && selected_elements.len() > 1 {
+        error!("A backup key can only be restored for a single element");
+        return;
+    }
+
+    restore_elements(
+        settings,
+        bucket,
+        &restore_dir,
+        &selected_elements,
+        &paths,
+        &selector,
+    )
+    .await;
+}
+
+/// Returns the backup selector given with `--key`, `--before` or `--at`, or `BackupSelector::Latest` if none of
+/// them is present.
+///
+/// # Returns
+/// - `Ok(BackupSelector)` - The selector.
+/// - `Err(String)` - An error message if several selectors are given or the date and time is invalid.
+fn get_backup_selector(args: &[String]) -> Result<BackupSelector, String> {
+    match (
+        get_option_value(args, "--key"),
+        get_option_value(args, "--before"),
+        get_option_value(args, "--at"),
+    ) {
+        (None, None, None) => Ok(BackupSelector::Latest),
+        (Some(key), None, None) => Ok(BackupSelector::Key(key.clone())),
+        (None, Some(before), None) => parse_datetime(before).map(BackupSelector::Before),
+        (None, None, Some(at)) => parse_datetime(at).map(BackupSelector::At),
+        _ => Err(String::from("Only one of --key, --before and --at can be used")),
+    }
 }
@@ -0,0 +1,100 @@
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::{find_element, get_option_value, get_positional_args, parse_duration};
+use crate::utils::s3_utils::resolve_s3_backup_key;
+use crate::utils::storage_utils::check_s3_storage;
+use log::{error, info};
+use s3::Bucket;
+
+/// The default lifetime of a presigned URL.
+const DEFAULT_EXPIRES: &str = "24h";
+
+/// The maximum lifetime of a presigned URL allowed by S3 (7 days).
+const MAX_EXPIRES_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Prints a presigned download URL for a backup of the selected element.
+///
+/// The command has the form `reback share <element> [key] [--expires <duration>]`. If no key is provided,
+/// the latest backup of the element is shared. The key can be either the full S3 key or the file name
+/// within the element's S3 folder. The URL lifetime defaults to 24 hours and cannot exceed 7 days.
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - The element is not specified or not found.
+/// - The element is not stored in S3 (see `check_s3_storage`).
+/// - The expiration duration is invalid.
+/// - The backup cannot be found or the URL cannot be generated.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "share", "my_pg_db", "--expires", "12h"];
+/// share_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn share_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    let positional = get_positional_args(args, &["--expires"]);
+
+    let element = match positional.first() {
+        Some(title) => match find_element(settings, title) {
+            Some(element) => element,
+            None => {
+                error!("No matching element found: {}", title);
+                return;
+            }
+        },
+        None => {
+            error!("No element provided to share.");
+            return;
+        }
+    };
+
+    let expires = get_option_value(args, "--expires")
+        .map(|value| value.as_str())
+        .unwrap_or(DEFAULT_EXPIRES);
+
+    let expires_secs = match parse_duration(expires) {
+        Ok(duration) if duration.num_seconds() > 0 && duration.num_seconds() <= MAX_EXPIRES_SECS => {
+            duration.num_seconds() as u32
+        }
+        Ok(_) => {
+            error!("Expiration must be between 1 second and 7 days: {}", expires);
+            return;
+        }
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = check_s3_storage(settings, element) {
+        error!("{}", e);
+        return;
+    }
+
+    let bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for {}", element.element_title);
+            return;
+        }
+    };
+
+    let key = match resolve_s3_backup_key(&bucket, &element.s3_folder, positional.get(1).copied()).await {
+        Ok(key) => key,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    match bucket.presign_get(&key, expires_secs, None).await {
+        Ok(url) => {
+            info!("Generated presigned URL for {} (expires in {})", key, expires);
+            println!("{}", url);
+        }
+        Err(e) => error!("Failed to generate presigned URL for {}: {}", key, e),
+    }
+}
@@ -0,0 +1,366 @@
+use crate::utils::fs_utils::get_reader_sha256;
+use log::{debug, error, info, warn};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// The GitHub API endpoint with the latest release of reback.
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/proDreams/reback/releases/latest";
+
+/// The version of the running binary.
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The timeout of the passive version check, so an unreachable GitHub does not delay backups.
+const CHECK_TIMEOUT_SECS: u64 = 10;
+
+/// The timeout of downloading a release binary.
+const DOWNLOAD_TIMEOUT_SECS: u64 = 600;
+
+/// The minimum interval between two passive version checks.
+const CHECK_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// The file in the backup directory that stores the result of the last passive version check.
+const CHECK_CACHE_FILE: &str = ".reback-version-check";
+
+/// Returns the minisign public key (the second line of `minisign.pub`) that release binaries are signed with.
+///
+/// The key is embedded at build time from the `REBACK_UPDATE_PUBLIC_KEY` environment variable. Builds without a
+/// key (or with an empty one, e.g., an unset variable of a CI workflow) cannot verify releases, so `self-update`
+/// refuses to replace their executable.
+fn get_update_public_key() -> Option<&'static str> {
+    option_env!("REBACK_UPDATE_PUBLIC_KEY").filter(|key| !key.trim().is_empty())
+}
+
+/// Represents a release returned by the GitHub API.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+/// Represents a file attached to a GitHub release.
+#[derive(Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Updates the running executable to the latest release from GitHub.
+///
+/// The command has the form `reback self-update [--check]`. The binary for the current platform
+/// (`reback_linux`, `reback_macos_intel`, `reback_macos_aarch` or `reback.exe`) is downloaded together with its
+/// `.sha256` and `.minisig` files. The running executable is only replaced if the checksum matches and the minisign
+/// signature is valid for the public key embedded at build time (see `get_update_public_key`). With `--check`, only the
+/// current and the latest versions are printed.
+///
+/// The new binary is written next to the executable first and then renamed over it, so an interrupted update does
+/// not leave a broken executable. On Windows, the running executable is renamed to `reback.exe.old` first, because
+/// it cannot be overwritten while it is running.
+///
+/// # Arguments
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error if:
+/// - The latest release cannot be fetched or has no binary for the current platform.
+/// - The binary, its checksum or its signature cannot be downloaded, the checksum does not match or the signature
+///   is invalid.
+/// - The executable was built without an update public key.
+/// - The executable cannot be replaced (e.g., due to missing write permissions).
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "self-update"];
+/// self_update_process(&args).await;
+/// ```
+pub async fn self_update_process(args: &[String]) {
+    let release = match get_latest_release(DOWNLOAD_TIMEOUT_SECS).await {
+        Ok(release) => release,
+        Err(e) => {
+            error!("Failed to fetch the latest release: {}", e);
+            return;
+        }
+    };
+
+    println!("Current version: {}", CURRENT_VERSION);
+    println!("Latest version: {}", release.tag_name.trim_start_matches('v'));
+
+    if !is_newer_version(&release.tag_name, CURRENT_VERSION) {
+        println!("reback is up to date");
+        return;
+    }
+
+    if args.iter().any(|arg| arg == "--check") {
+        println!("Run `reback self-update` to update");
+        return;
+    }
+
+    match update_executable(&release).await {
+        Ok(()) => {
+            println!("Updated reback to {}", release.tag_name);
+            info!("Updated reback from {} to {}", CURRENT_VERSION, release.tag_name);
+        }
+        Err(e) => error!("Failed to update reback to {}: {}", release.tag_name, e),
+    }
+}
+
+/// Logs a notice if a newer release of reback is available.
+///
+/// The check is best-effort: failures (e.g., no internet access) are ignored, and the request times out after
+/// `CHECK_TIMEOUT_SECS` seconds. GitHub is queried at most once per `CHECK_INTERVAL_SECS`: the latest version (or
+/// an empty line after a failed request) is stored in `.reback-version-check` in the backup directory, and the
+/// notice is logged from this file until it expires.
+///
+/// # Arguments
+/// - `cache_dir` - The directory where the result of the check is stored (the backup directory).
+///
+/// # Example
+/// ```rust
+/// if !settings.disable_version_check {
+///     check_for_update(Path::new(&settings.backup_dir)).await;
+/// }
+/// ```
+pub async fn check_for_update(cache_dir: &Path) {
+    let cache_path = cache_dir.join(CHECK_CACHE_FILE);
+
+    let latest = match get_cached_version(&cache_path) {
+        Some(latest) => latest,
+        None => {
+            let latest = get_latest_release(CHECK_TIMEOUT_SECS)
+                .await
+                .map(|release| release.tag_name)
+                .unwrap_or_default();
+
+            if let Err(e) = fs::create_dir_all(cache_dir).and_then(|_| fs::write(&cache_path, &latest)) {
+                debug!("Failed to store the version check in {}: {}", cache_path.display(), e);
+            }
+
+            latest
+        }
+    };
+
+    if is_newer_version(&latest, CURRENT_VERSION) {
+        warn!(
+            "A new version of reback is available: {} (current: {}). Run `reback self-update` to update.",
+            latest, CURRENT_VERSION
+        );
+    }
+}
+
+/// Returns the latest version stored by the previous check, or `None` if there is no check within
+/// `CHECK_INTERVAL_SECS`.
+fn get_cached_version(cache_path: &Path) -> Option<String> {
+    let modified = fs::metadata(cache_path).and_then(|metadata| metadata.modified()).ok()?;
+    let age = SystemTime::now().duration_since(modified).ok()?;
+
+    if age > Duration::from_secs(CHECK_INTERVAL_SECS) {
+        return None;
+    }
+
+    fs::read_to_string(cache_path)
+        .ok()
+        .map(|latest| latest.trim().to_string())
+}
+
+/// Fetches the latest release from the GitHub API.
+async fn get_latest_release(timeout_secs: u64) -> Result<Release, Box<dyn Error>> {
+    let release = build_client(timeout_secs)?
+        .get(LATEST_RELEASE_URL)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Release>()
+        .await?;
+
+    Ok(release)
+}
+
+/// Creates an HTTP client for GitHub. The API rejects requests without a `User-Agent`.
+fn build_client(timeout_secs: u64) -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .user_agent(format!("reback/{}", CURRENT_VERSION))
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+}
+
+/// Checks whether the version of a release tag (e.g., `v0.4.0`) is newer than the current version.
+fn is_newer_version(tag: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(['.', '-'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+
+    parse(tag) > parse(current)
+}
+
+/// Returns the name of the release binary for the current platform.
+fn get_asset_name() -> Option<&'static str> {
+    match (env::consts::OS, env::consts::ARCH) {
+        ("linux", "x86_64") => Some("reback_linux"),
+        ("macos", "aarch64") => Some("reback_macos_aarch"),
+        ("macos", "x86_64") => Some("reback_macos_intel"),
+        ("windows", "x86_64") => Some("reback.exe"),
+        _ => None,
+    }
+}
+
+/// Downloads the binary of a release, verifies its checksum and signature and replaces the running executable.
+async fn update_executable(release: &Release) -> Result<(), Box<dyn Error>> {
+    let public_key = get_update_public_key().ok_or(
+        "This build of reback has no update public key, so releases cannot be verified. Download the release manually",
+    )?;
+    let asset_name = get_asset_name().ok_or(format!(
+        "No release binary for {} {}",
+        env::consts::OS,
+        env::consts::ARCH
+    ))?;
+    let checksum_name = format!("{}.sha256", asset_name);
+    let signature_name = format!("{}.minisig", asset_name);
+
+    let find_asset = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or(format!("Release {} has no file {}", release.tag_name, name))
+    };
+    let asset = find_asset(asset_name)?;
+    let checksum_asset = find_asset(&checksum_name)?;
+    let signature_asset = find_asset(&signature_name)?;
+
+    let client = build_client(DOWNLOAD_TIMEOUT_SECS)?;
+    let download = |url: &str| {
+        let request = client.get(url).send();
+        async move { request.await?.error_for_status()?.bytes().await }
+    };
+
+    // The checksum file has the `sha256sum` format: `<checksum>  <file name>`.
+    let checksum_file = download(&checksum_asset.browser_download_url).await?;
+    let expected = String::from_utf8_lossy(&checksum_file)
+        .split_whitespace()
+        .next()
+        .map(|checksum| checksum.to_lowercase())
+        .ok_or(format!("{} is empty", checksum_name))?;
+
+    let binary = download(&asset.browser_download_url).await?;
+    let actual = get_reader_sha256(&mut binary.as_ref())?;
+
+    if actual != expected {
+        return Err(format!("Checksum mismatch: expected {}, got {}", expected, actual).into());
+    }
+
+    let signature_file = download(&signature_asset.browser_download_url).await?;
+    verify_signature(public_key, &binary, &String::from_utf8_lossy(&signature_file))
+        .map_err(|e| format!("Invalid signature of {}: {}", asset_name, e))?;
+
+    info!(
+        "Downloaded {} {} (sha256 {}, signature verified)",
+        asset_name, release.tag_name, actual
+    );
+
+    let exe_path = env::current_exe()?;
+    let new_path = exe_path.with_extension("new");
+    fs::write(&new_path, &binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    if let Err(e) = replace_file(&new_path, &exe_path) {
+        let _ = fs::remove_file(&new_path);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Verifies the minisign signature (the contents of a `.minisig` file) of a release binary.
+///
+/// Only prehashed signatures (`minisign -S`, the default since minisign 0.8) are accepted.
+fn verify_signature(public_key: &str, binary: &[u8], signature: &str) -> Result<(), Box<dyn Error>> {
+    let public_key = PublicKey::from_base64(public_key.trim())?;
+    let signature = Signature::decode(signature)?;
+
+    public_key.verify(binary, &signature, false)?;
+
+    Ok(())
+}
+
+/// Replaces the running executable with a new file.
+fn replace_file(new_path: &Path, exe_path: &Path) -> std::io::Result<()> {
+    if !cfg!(windows) {
+        return fs::rename(new_path, exe_path);
+    }
+
+    let old_path = exe_path.with_extension("exe.old");
+    let _ = fs::remove_file(&old_path);
+    fs::rename(exe_path, &old_path)?;
+
+    fs::rename(new_path, exe_path).inspect_err(|_| {
+        let _ = fs::rename(&old_path, exe_path);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors of `minisign-verify` for the message `test`.
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const PREHASHED_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+    const LEGACY_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==";
+
+    #[test]
+    fn verifies_prehashed_signatures_only() {
+        assert!(verify_signature(PUBLIC_KEY, b"test", PREHASHED_SIGNATURE).is_ok());
+        assert!(verify_signature(PUBLIC_KEY, b"Test", PREHASHED_SIGNATURE).is_err());
+        assert!(verify_signature(PUBLIC_KEY, b"test", LEGACY_SIGNATURE).is_err());
+        assert!(verify_signature(PUBLIC_KEY, b"test", "not a signature").is_err());
+    }
+
+    #[test]
+    fn compares_release_versions() {
+        assert!(is_newer_version("v0.4.0", "0.3.1"));
+        assert!(is_newer_version("v0.3.10", "0.3.9"));
+        assert!(!is_newer_version("v0.3.1", "0.3.1"));
+        assert!(!is_newer_version("", "0.3.1"));
+    }
+
+    #[test]
+    fn reads_the_cached_version_until_it_expires() {
+        let dir = std::env::temp_dir().join(format!("reback-version-check-{}", std::process::id()));
+        let cache_path = dir.join(CHECK_CACHE_FILE);
+        assert_eq!(get_cached_version(&cache_path), None);
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&cache_path, "v9.9.9\n").unwrap();
+        assert_eq!(get_cached_version(&cache_path).as_deref(), Some("v9.9.9"));
+
+        let expired = SystemTime::now() - Duration::from_secs(CHECK_INTERVAL_SECS + 60);
+        fs::File::options()
+            .write(true)
+            .open(&cache_path)
+            .unwrap()
+            .set_modified(expired)
+            .unwrap();
+        assert_eq!(get_cached_version(&cache_path), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::structures::settings::Settings;
+use crate::utils::args_utils::{find_element, get_positional_args};
+use crate::utils::fs_utils::{get_file_sha256, TempFile};
+use crate::utils::incremental_utils::{get_latest_chain, validate_chain};
+use crate::utils::s3_utils::{
+    get_backup_checksum, get_backup_time, get_s3_objects_list, resolve_s3_backup_key,
+    wait_for_glacier_restore,
+};
+use crate::utils::storage_utils::{check_s3_storage, StoredBackup};
+use log::{error, info, warn};
+use s3::Bucket;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tokio::fs::File;
+
+/// Verifies the integrity of backups of the selected element.
+///
+/// The command has the form `reback verify <element> [key] [--all]`. By default, the latest backup (or the backup
+/// with the given key) is verified. With `--all`, every backup of the element is verified. Each backup is
+/// downloaded to `<backup_dir>/to_verify`, and its SHA-256 checksum is compared with the checksum recorded at
+/// upload time. The result is printed as `OK`, `MISMATCH` or `NO CHECKSUM` for each backup.
+///
+/// The process exits with the status `1` if any backup fails the verification (a mismatch or an error), so the
+/// command can be used in scripts and monitoring.
+///
+/// For incremental folder elements, the latest backup is a single increment, so every archive of the latest chain
+/// is verified instead, after the chain itself is checked for missing links (see `validate_chain`).
+///
+/// # Arguments
+/// - `settings` - The configuration settings containing the elements.
+/// - `bucket` - The S3 bucket (created with the default keys) where the backups are stored.
+/// - `args` - The command-line arguments.
+///
+/// # Errors
+/// This function will log an error and exit with the status `1` if:
+/// - The element is not specified or not found.
+/// - The element is not stored in S3 (see `check_s3_storage`).
+/// - The backups cannot be listed, downloaded or read.
+/// - A checksum does not match.
+///
+/// # Example
+/// ```rust
+/// let args = vec!["reback", "verify", "my_pg_db", "--all"];
+/// verify_process(&settings, &bucket, &args).await;
+/// ```
+pub async fn verify_process(settings: &Settings, bucket: &Bucket, args: &[String]) {
+    if !verify_backups(settings, bucket, args).await {
+        std::process::exit(1);
+    }
+}
+
+/// Verifies the backups selected by the arguments (see `verify_process`).
+///
+/// # Returns
+/// `true` if every backup was verified or has no recorded checksum, `false` otherwise.
+async fn verify_backups(settings: &Settings, bucket: &Bucket, args: &[String]) -> bool {
+    let positional = get_positional_args(args, &[]);
+    let verify_all = args.iter().any(|arg| arg == "--all");
+
+    let element = match positional.first() {
+        Some(title) => match find_element(settings, title) {
+            Some(element) => element,
+            None => {
+                error!("No matching element found: {}", title);
+                return false;
+            }
+        },
+        None => {
+            error!("No element provided to verify.");
+            return false;
+        }
+    };
+
+    if let Err(e) = check_s3_storage(settings, element) {
+        error!("{}", e);
+        return false;
+    }
+
+    let bucket = match settings.resolve_bucket(bucket, element.s3_credentials.as_ref()) {
+        Some(b) => b,
+        None => {
+            error!("Failed to create S3 bucket for {}", element.element_title);
+            return false;
+        }
+    };
+
+    let keys = if verify_all {
+        match get_s3_objects_list(&bucket, &element.s3_folder).await {
+            Ok(results) => results
+                .into_iter()
+                .flat_map(|result| result.contents)
+                .map(|object| object.key)
+                .collect(),
+            Err(_) => return false,
+        }
+    } else if element.is_incremental() && positional.get(1).is_none() {
+        match get_latest_chain_keys(&bucket, &element.s3_folder).await {
+            Ok(keys) => keys,
+            Err(e) => {
+                error!("{}", e);
+                return false;
+            }
+        }
+    } else {
+        match resolve_s3_backup_key(&bucket, &element.s3_folder, positional.get(1).copied()).await {
+            Ok(key) => vec![key],
+            Err(e) => {
+                error!("{}", e);
+                return false;
+            }
+        }
+    };
+
+    let temp_dir = Path::new(&settings.backup_dir).join("to_verify");
+    if let Err(e) = fs::create_dir_all(&temp_dir) {
+        error!("Failed to create verification dir {}: {}", temp_dir.display(), e);
+        return false;
+    }
+
+    let mut verified = true;
+
+    for key in keys {
+        if let Err(e) = wait_for_glacier_restore(&bucket, &key, &settings.glacier_restore).await {
+            error!("{}", e);
+            verified = false;
+            continue;
+        }
+
+        match verify_backup(&bucket, &key, &temp_dir).await {
+            Ok(Some(true)) => {
+                info!("Backup {} verified successfully", key);
+                println!("OK\t{}", key);
+            }
+            Ok(Some(false)) => {
+                error!("Checksum mismatch for backup {}", key);
+                println!("MISMATCH\t{}", key);
+                verified = false;
+            }
+            Ok(None) => {
+                warn!("No checksum recorded for backup {}", key);
+                println!("NO CHECKSUM\t{}", key);
+            }
+            Err(e) => {
+                error!("Failed to verify backup {}: {}", key, e);
+                verified = false;
+            }
+        }
+    }
+
+    verified
+}
+
+/// Returns the keys of the archives of the latest incremental chain in a folder.
+///
+/// # Returns
+/// - `Ok(Vec<String>)` - The keys from the full backup to the last increment.
+/// - `Err(String)` - An error message if the folder cannot be listed, has no full backup, or the chain is broken.
+async fn get_latest_chain_keys(bucket: &Bucket, folder: &String) -> Result<Vec<String>, String> {
+    let backups = get_s3_objects_list(bucket, folder)
+        .await
+        .map_err(|e| format!("Failed to list backups in {}: {}", folder, e))?
+        .into_iter()
+        .flat_map(|result| result.contents)
+        .filter_map(|object| {
+            get_backup_time(&object.key, &object.last_modified).map(|time| StoredBackup {
+                key: object.key,
+                time,
+                size: Some(object.size),
+            })
+        })
+        .collect();
+
+    let chain = get_latest_chain(backups).ok_or(format!("No full backup found in {}", folder))?;
+    validate_chain(folder, &chain)?;
+
+    Ok(chain.into_iter().map(|backup| backup.key).collect())
+}
+
+/// Downloads a backup and compares its SHA-256 checksum with the recorded checksum.
+///
+/// # Returns
+/// - `Ok(Some(true))` if the checksums match.
+/// - `Ok(Some(false))` if the checksums differ.
+/// - `Ok(None)` if no checksum was recorded for the backup.
+/// - `Err(Box<dyn Error>)` if the backup cannot be downloaded or read.
+pub async fn verify_backup(
+    bucket: &Bucket,
+    key: &str,
+    temp_dir: &Path,
+) -> Result<Option<bool>, Box<dyn Error>> {
+    let expected = match get_backup_checksum(bucket, key).await? {
+        Some(checksum) => checksum,
+        None => return Ok(None),
+    };
+
+    let temp_file = TempFile::unique(temp_dir, "verify.tmp");
+
+    bucket
+        .get_object_to_writer(key, &mut File::create(temp_file.path()).await?)
+        .await?;
+
+    let actual = get_file_sha256(temp_file.path())?;
+
+    Ok(Some(actual == expected))
+}
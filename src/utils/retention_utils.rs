@@ -0,0 +1,262 @@
+use crate::structures::elements::Elements;
+use crate::utils::time_utils;
+use chrono::{DateTime, Datelike, Duration, FixedOffset};
+use std::cmp::Reverse;
+
+/// Returns the period of a retention tier a backup time belongs to (e.g., the year and the month).
+type GetPeriod = fn(&DateTime<FixedOffset>) -> (i32, u32);
+
+/// Describes which backups of an element are kept (grandfather-father-son retention).
+///
+/// All backups newer than `days` are kept. In addition, the newest backup of each of the last `keep_daily` days,
+/// `keep_weekly` ISO weeks, `keep_monthly` months and `keep_yearly` years that have backups is kept, so recent
+/// backups are kept densely while older ones are thinned out. Periods are counted from the newest backup and
+/// evaluated in the configured timezone. Without the `keep_*` tiers, only the `days` threshold applies.
+///
+/// # Fields
+/// - `days` - The number of days for which all backups are kept.
+/// - `keep_daily` - The number of days for which the newest backup is kept.
+/// - `keep_weekly` - The number of weeks for which the newest backup is kept.
+/// - `keep_monthly` - The number of months for which the newest backup is kept.
+/// - `keep_yearly` - The number of years for which the newest backup is kept.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub days: u64,
+    pub keep_daily: u32,
+    pub keep_weekly: u32,
+    pub keep_monthly: u32,
+    pub keep_yearly: u32,
+}
+
+impl RetentionPolicy {
+    /// Returns the policy of the local backups of an element (`backup_retention_days` and the `keep_*` tiers).
+    pub fn local(element: &Elements) -> Self {
+        Self::with_days(element, element.backup_retention_days)
+    }
+
+    /// Returns the policy of the backups of an element in its storage targets (`s3_backup_retention_days` and
+    /// the `keep_*` tiers).
+    pub fn stored(element: &Elements) -> Self {
+        Self::with_days(element, element.s3_backup_retention_days)
+    }
+
+    fn with_days(element: &Elements, days: u64) -> Self {
+        RetentionPolicy {
+            days,
+            keep_daily: element.keep_daily.unwrap_or(0),
+            keep_weekly: element.keep_weekly.unwrap_or(0),
+            keep_monthly: element.keep_monthly.unwrap_or(0),
+            keep_yearly: element.keep_yearly.unwrap_or(0),
+        }
+    }
+
+    /// Returns a human-readable description of the policy used in logs and output
+    /// (e.g., `7 days, 14 daily, 8 weekly, 12 monthly`).
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("{} days", self.days)];
+
+        for (count, name) in [
+            (self.keep_daily, "daily"),
+            (self.keep_weekly, "weekly"),
+            (self.keep_monthly, "monthly"),
+            (self.keep_yearly, "yearly"),
+        ] {
+            if count > 0 {
+                parts.push(format!("{} {}", count, name));
+            }
+        }
+
+        parts.join(", ")
+    }
+
+    /// Returns the items that are not kept by the policy.
+    ///
+    /// # Arguments
+    /// - `items` - The backups (or groups of backups, e.g., incremental chains) to check.
+    /// - `time` - Returns the backup time of an item.
+    ///
+    /// # Returns
+    /// The outdated items, sorted from the newest to the oldest.
+    ///
+    /// # Example
+    /// ```rust
+    /// for backup in policy.get_outdated(storage.list(folder).await?, |backup| backup.time) {
+    ///     storage.delete(&backup.key).await?;
+    /// }
+    /// ```
+    pub fn get_outdated<T>(&self, items: Vec<T>, time: impl Fn(&T) -> DateTime<FixedOffset>) -> Vec<T> {
+        self.get_outdated_at(time_utils::now(), items, time)
+    }
+
+    /// Returns the items that are not kept by the policy at the given time (see `get_outdated`).
+    fn get_outdated_at<T>(
+        &self,
+        now: DateTime<FixedOffset>,
+        items: Vec<T>,
+        time: impl Fn(&T) -> DateTime<FixedOffset>,
+    ) -> Vec<T> {
+        let mut items: Vec<_> = items.into_iter().map(|item| (time(&item), item)).collect();
+        items.sort_by_key(|(time, _)| Reverse(*time));
+
+        let mut keep: Vec<bool> = items
+            .iter()
+            .map(|(time, _)| now - *time <= Duration::days(self.days as i64))
+            .collect();
+
+        let tiers: [(u32, GetPeriod); 4] = [
+            (self.keep_daily, |time| (time.year(), time.ordinal())),
+            (self.keep_weekly, |time| (time.iso_week().year(), time.iso_week().week())),
+            (self.keep_monthly, |time| (time.year(), time.month())),
+            (self.keep_yearly, |time| (time.year(), 0)),
+        ];
+
+        for (count, get_period) in tiers {
+            let mut last_period = None;
+            let mut kept = 0;
+
+            for (index, (time, _)) in items.iter().enumerate() {
+                if kept >= count {
+                    break;
+                }
+
+                // Items are sorted from the newest, so the first item of a period is its newest backup.
+                let period = get_period(time);
+                if last_period != Some(period) {
+                    last_period = Some(period);
+                    keep[index] = true;
+                    kept += 1;
+                }
+            }
+        }
+
+        items
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| !keep)
+            .map(|((_, item), _)| item)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    fn policy(days: u64, keep_daily: u32, keep_weekly: u32, keep_monthly: u32, keep_yearly: u32) -> RetentionPolicy {
+        RetentionPolicy {
+            days,
+            keep_daily,
+            keep_weekly,
+            keep_monthly,
+            keep_yearly,
+        }
+    }
+
+    /// A named policy, the current time, the backup times and the expected outdated backup times.
+    type Case = (&'static str, RetentionPolicy, &'static str, &'static [&'static str], &'static [&'static str]);
+
+    /// Returns the outdated backups of the policy at `now`, sorted from the oldest to the newest.
+    fn outdated(policy: &RetentionPolicy, now: &str, backups: &[&str]) -> Vec<String> {
+        let items = backups.iter().map(|backup| time(backup)).collect();
+        let mut outdated: Vec<_> = policy
+            .get_outdated_at(time(now), items, |backup| *backup)
+            .iter()
+            .map(|backup| backup.to_rfc3339())
+            .collect();
+        outdated.reverse();
+        outdated
+    }
+
+    #[test]
+    fn applies_retention_tiers() {
+        let cases: [Case; 6] = [
+            (
+                "days threshold is inclusive",
+                policy(2, 0, 0, 0, 0),
+                "2025-01-10T12:00:00+00:00",
+                &["2025-01-08T11:59:59+00:00", "2025-01-08T12:00:00+00:00", "2025-01-09T12:00:00+00:00"],
+                &["2025-01-08T11:59:59+00:00"],
+            ),
+            (
+                "daily keeps the newest backup of each day",
+                policy(0, 2, 0, 0, 0),
+                "2025-01-11T00:00:00+00:00",
+                &[
+                    "2025-01-08T23:00:00+00:00",
+                    "2025-01-09T01:00:00+00:00",
+                    "2025-01-09T23:00:00+00:00",
+                    "2025-01-10T00:00:00+00:00",
+                    "2025-01-10T23:59:59+00:00",
+                ],
+                &["2025-01-08T23:00:00+00:00", "2025-01-09T01:00:00+00:00", "2025-01-10T00:00:00+00:00"],
+            ),
+            (
+                "weekly starts weeks on Monday",
+                policy(0, 0, 2, 0, 0),
+                "2025-01-20T00:00:00+00:00",
+                // Sunday 2025-01-05, Monday 2025-01-06, Sunday 2025-01-12, Monday 2025-01-13.
+                &[
+                    "2025-01-05T12:00:00+00:00",
+                    "2025-01-06T12:00:00+00:00",
+                    "2025-01-12T12:00:00+00:00",
+                    "2025-01-13T12:00:00+00:00",
+                ],
+                &["2025-01-05T12:00:00+00:00", "2025-01-06T12:00:00+00:00"],
+            ),
+            (
+                "weekly uses ISO weeks across the year boundary",
+                policy(0, 0, 2, 0, 0),
+                "2025-01-10T00:00:00+00:00",
+                // 2024-12-29 is in week 52 of 2024, 2024-12-30 and 2025-01-05 are in week 1 of 2025.
+                &["2024-12-22T12:00:00+00:00", "2024-12-29T12:00:00+00:00", "2024-12-30T12:00:00+00:00", "2025-01-05T12:00:00+00:00"],
+                &["2024-12-22T12:00:00+00:00", "2024-12-30T12:00:00+00:00"],
+            ),
+            (
+                "monthly keeps the newest backup of each month",
+                policy(0, 0, 0, 2, 0),
+                "2025-03-15T00:00:00+00:00",
+                &[
+                    "2024-12-31T23:59:59+00:00",
+                    "2025-01-01T00:00:00+00:00",
+                    "2025-01-31T23:59:59+00:00",
+                    "2025-02-01T00:00:00+00:00",
+                ],
+                &["2024-12-31T23:59:59+00:00", "2025-01-01T00:00:00+00:00"],
+            ),
+            (
+                "yearly counts only years with backups",
+                policy(0, 0, 0, 0, 2),
+                "2025-06-01T00:00:00+00:00",
+                &["2020-12-31T00:00:00+00:00", "2021-06-01T00:00:00+00:00", "2021-12-31T00:00:00+00:00", "2024-12-31T00:00:00+00:00"],
+                &["2020-12-31T00:00:00+00:00", "2021-06-01T00:00:00+00:00"],
+            ),
+        ];
+
+        for (name, policy, now, backups, expected) in cases {
+            let expected: Vec<String> = expected.iter().map(|value| time(value).to_rfc3339()).collect();
+            assert_eq!(outdated(&policy, now, backups), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn combines_days_and_tiers() {
+        // The last 3 days are kept completely, and the newest backup of each of the last 2 weeks with backups.
+        let policy = policy(3, 0, 2, 0, 0);
+        let backups = [
+            "2025-01-01T12:00:00+00:00",
+            "2025-01-02T12:00:00+00:00",
+            "2025-01-08T12:00:00+00:00",
+            "2025-01-09T12:00:00+00:00",
+            "2025-01-10T12:00:00+00:00",
+        ];
+
+        assert_eq!(
+            outdated(&policy, "2025-01-11T00:00:00+00:00", &backups),
+            ["2025-01-01T12:00:00+00:00"]
+        );
+    }
+}
@@ -1,9 +1,25 @@
+use crate::structures::elements::BACKUP_TIMESTAMP_FORMAT;
+use crate::structures::settings::GlacierRestore;
+use crate::utils::fs_utils::{get_file_sha256, TempFile};
+use crate::utils::incremental_utils::check_pruning;
+use crate::utils::retention_utils::RetentionPolicy;
+use crate::utils::storage_utils::StoredBackup;
 use anyhow::Result;
-use chrono::{DateTime, Duration, Local};
+use crate::utils::time_utils;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime};
 use log::{error, info, warn};
 use s3::bucket::Bucket;
 use s3::error::S3Error;
 use s3::serde_types::ListBucketResult;
+use s3::signing;
+use hmac::Mac;
+use http::{header, HeaderMap};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -15,7 +31,9 @@ use tokio::io::{BufReader};
 /// This function uploads the specified file to the given S3 bucket at the path determined by the
 /// `s3_folder` and the file's name. It uses asynchronous I/O to open and read the file from the
 /// provided local `path`, ensuring efficient resource usage without blocking operations.
-/// The file is then streamed to the specified S3 folder.
+/// The file is then streamed to the specified S3 folder. If an object with the same key already exists,
+/// a sequence suffix is added to the key (see `add_sequence_suffix`) instead of overwriting it.
+/// After the upload, the SHA-256 checksum of the file is recorded with `put_backup_checksum`.
 ///
 /// # Arguments
 /// - `bucket` - The S3 bucket where the file will be uploaded.
@@ -30,6 +48,7 @@ use tokio::io::{BufReader};
 /// This function will return an error if:
 /// - The file cannot be opened asynchronously from the provided path.
 /// - The file name cannot be extracted from the path.
+/// - It cannot be checked whether the key of the backup is free (see `get_available_s3_key`).
 /// - The upload to S3 fails.
 ///
 /// # Example
@@ -42,14 +61,12 @@ use tokio::io::{BufReader};
 pub async fn upload_file_to_s3(
     bucket: &Bucket,
     path: &Path,
-    s3_folder: &String,
+    s3_folder: &str,
 ) -> Result<(), Box<dyn Error>> {
     let file_name = path
         .file_name()
         .ok_or_else(|| format!("Failed to extract file name from {}", path.display()))?;
-    let file_name = file_name.to_string_lossy();
-
-    let s3_path = format!("/{}/{}", s3_folder, file_name);
+    let s3_path = get_available_s3_key(bucket, s3_folder, &file_name.to_string_lossy()).await?;
 
     let file = File::open(path).await?;
     let mut reader = BufReader::new(file);
@@ -60,9 +77,155 @@ pub async fn upload_file_to_s3(
         .map_err(|e| format!("Failed to upload file to S3: {}", e))?;
 
     info!("File uploaded successfully to {}", s3_path);
+
+    match get_file_sha256(path) {
+        Ok(checksum) => put_backup_checksum(bucket, &s3_path, &checksum).await,
+        Err(e) => warn!("Failed to calculate checksum of {}: {}", path.display(), e),
+    }
+
     Ok(())
 }
 
+/// Returns the S3 key (`/<s3_folder>/<file_name>`) under which a new backup can be uploaded.
+///
+/// If an object with the same key already exists, a sequence suffix is added to the file name
+/// (see `add_sequence_suffix`) until a free key is found, so existing backups are never overwritten.
+///
+/// Only a `404` response means that a key is free. A `403` response is expected for write-only upload keys, which
+/// cannot read objects, so the key is used with a warning that it could not be checked. Any other failure (e.g.,
+/// a network error or `5xx`) is returned as an error instead of overwriting a backup that may exist.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket where the backup will be uploaded.
+/// - `s3_folder` - The folder in the S3 bucket where the backup will be stored.
+/// - `file_name` - The name of the backup file.
+///
+/// # Returns
+/// - `Ok(String)` - The key of the new backup.
+/// - `Err(String)` - An error message if it cannot be checked whether a key is free.
+///
+/// # Example
+/// ```rust
+/// let s3_path = get_available_s3_key(&bucket, "my_db", "my_db-2025-01-10_02-00-00.sql").await?;
+/// ```
+pub async fn get_available_s3_key(bucket: &Bucket, s3_folder: &str, file_name: &str) -> Result<String, String> {
+    let mut s3_path = format!("/{}/{}", s3_folder, file_name);
+    let mut sequence = 0;
+
+    loop {
+        match bucket.head_object(&s3_path).await {
+            Ok((_, 200)) => {}
+            Ok((_, 404)) | Err(S3Error::HttpFailWithBody(404, _)) => break,
+            Ok((_, 403)) | Err(S3Error::HttpFailWithBody(403, _)) => {
+                warn!(
+                    "Access denied when checking whether {} already exists in S3, uploading without the check",
+                    s3_path
+                );
+                break;
+            }
+            Ok((_, status)) | Err(S3Error::HttpFailWithBody(status, _)) => {
+                return Err(format!(
+                    "Unexpected status {} when checking whether {} already exists in S3",
+                    status, s3_path
+                ))
+            }
+            Err(e) => return Err(format!("Failed to check whether {} already exists in S3: {}", s3_path, e)),
+        }
+
+        sequence += 1;
+        s3_path = format!("/{}/{}", s3_folder, add_sequence_suffix(file_name, sequence));
+    }
+
+    if sequence > 0 {
+        warn!(
+            "Backup {} already exists in S3, uploading as {}",
+            file_name, s3_path
+        );
+    }
+
+    Ok(s3_path)
+}
+
+/// The S3 prefix where the SHA-256 checksums of the backups are stored.
+pub const CHECKSUM_PREFIX: &str = ".reback/checksums";
+
+/// The format of the `x-amz-date` header of signed requests (e.g., `20250110T020000Z`).
+const AMZ_DATE_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year][month][day]T[hour][minute][second]Z");
+
+/// Returns the S3 key of the checksum of a backup (`.reback/checksums/<backup key>.sha256`).
+pub fn get_checksum_key(key: &str) -> String {
+    format!("{}/{}.sha256", CHECKSUM_PREFIX, key.trim_start_matches('/'))
+}
+
+/// Records the SHA-256 checksum of a backup in S3.
+///
+/// The checksum is stored outside of the element's S3 folder, so it does not affect listing, retention or
+/// restore of the backups. Failures are logged as warnings and do not fail the upload.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket containing the backup.
+/// - `key` - The S3 key of the backup.
+/// - `checksum` - The SHA-256 checksum as a lowercase hex string.
+pub async fn put_backup_checksum(bucket: &Bucket, key: &str, checksum: &str) {
+    if let Err(e) = bucket
+        .put_object(get_checksum_key(key), checksum.as_bytes())
+        .await
+    {
+        warn!("Failed to record checksum of {}: {}", key, e);
+    }
+}
+
+/// Reads the recorded SHA-256 checksum of a backup from S3.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket containing the backup.
+/// - `key` - The S3 key of the backup.
+///
+/// # Returns
+/// - `Ok(Some(String))` containing the checksum if it was recorded.
+/// - `Ok(None)` if no checksum was recorded for the backup.
+/// - `Err(Box<dyn Error>)` if the checksum cannot be read.
+pub async fn get_backup_checksum(bucket: &Bucket, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let checksum_key = get_checksum_key(key);
+
+    match bucket.head_object(&checksum_key).await {
+        Ok((_, 200)) => {}
+        _ => return Ok(None),
+    }
+
+    let response = bucket.get_object(&checksum_key).await?;
+
+    Ok(Some(String::from_utf8_lossy(response.bytes()).trim().to_string()))
+}
+
+/// Adds a sequence suffix to a backup file name right after its timestamp.
+///
+/// For example, `my_db-2025-01-10_02-00-00.sql` with sequence `1` becomes `my_db-2025-01-10_02-00-00-1.sql`.
+/// If the file name does not contain a timestamp, the suffix is appended to the end of the name.
+///
+/// # Arguments
+/// - `file_name` - The backup file name.
+/// - `sequence` - The sequence number.
+///
+/// # Returns
+/// The file name with the sequence suffix.
+pub fn add_sequence_suffix(file_name: &str, sequence: u32) -> String {
+    let timestamp_end = file_name
+        .rmatch_indices('-')
+        .map(|(index, _)| index + 20)
+        .find(|end| {
+            file_name
+                .get(end - 19..*end)
+                .is_some_and(|value| NaiveDateTime::parse_from_str(value, BACKUP_TIMESTAMP_FORMAT).is_ok())
+        });
+
+    match timestamp_end {
+        Some(end) => format!("{}-{}{}", &file_name[..end], sequence, &file_name[end..]),
+        None => format!("{}-{}", file_name, sequence),
+    }
+}
+
 /// Retrieves a list of objects from an S3 bucket in a specified folder asynchronously.
 ///
 /// This function constructs a prefix using the provided `folder` and attempts to list the objects
@@ -107,16 +270,57 @@ pub async fn get_s3_objects_list(
     }
 }
 
-/// Checks for outdated backups in an S3 bucket and deletes them if they exceed the specified retention period.
+/// Lists the names of the subfolders of a folder in an S3 bucket.
+///
+/// Used to find the databases that were backed up by an element with `all_databases: auto`, since each database
+/// is stored in its own subfolder of the element's S3 folder.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket to list.
+/// - `folder` - The parent folder within the S3 bucket.
+///
+/// # Returns
+/// - `Ok(Vec<String>)` - The sorted names of the subfolders (without the parent folder).
+/// - `Err(S3Error)` - If the request to list the objects fails.
+///
+/// # Example
+/// ```rust
+/// let databases = get_s3_subfolders(&bucket, "postgres").await?;
+/// ```
+pub async fn get_s3_subfolders(bucket: &Bucket, folder: &str) -> Result<Vec<String>, S3Error> {
+    let prefix = format!("{}/", folder.trim_end_matches('/'));
+    let mut subfolders = Vec::new();
+
+    for result in bucket.list(prefix.clone(), Some(String::from("/"))).await? {
+        for common_prefix in result.common_prefixes.unwrap_or_default() {
+            if let Some(name) = common_prefix.prefix.strip_prefix(&prefix) {
+                let name = name.trim_end_matches('/');
+                if !name.is_empty() {
+                    subfolders.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    subfolders.sort();
+    subfolders.dedup();
+
+    Ok(subfolders)
+}
+
+/// Checks for outdated backups in an S3 bucket and deletes the ones that are not kept by the retention policy.
 ///
-/// This function lists the objects in the specified S3 folder and checks each object's modification timestamp.
-/// If an object’s modification time is older than the specified retention period (in days), it deletes the object
-/// from the S3 bucket. The modification timestamp is retrieved from the `last_modified` property of each object.
+/// This function lists the objects in the specified S3 folder and checks each object's backup time.
+/// Objects that are not kept by the retention policy (older than its retention days and not kept by its
+/// daily/weekly/monthly/yearly tiers, see `RetentionPolicy`) are deleted from the S3 bucket together with their
+/// checksums. The backup time is determined by `get_backup_time`, so imported backups keep their original
+/// creation time. Nothing is deleted if that would break an incremental chain that is still kept (see
+/// `check_pruning`).
 ///
 /// # Arguments
 /// - `bucket` - The S3 bucket where the backups are stored.
 /// - `folder` - The folder within the S3 bucket containing the backup files to be checked.
-/// - `retention` - The retention period in days. Any file older than this period will be deleted.
+/// - `policy` - The retention policy of the element.
 ///
 /// # Returns
 /// - `Ok(())` if the outdated backups were successfully checked and deleted.
@@ -127,26 +331,24 @@ pub async fn get_s3_objects_list(
 /// - Listing the objects in the S3 bucket fails.
 /// - Parsing the `last_modified` timestamp of a file fails.
 /// - Deleting a file fails due to permissions or other issues.
+/// - Deleting the outdated backups would break an incremental chain that is still kept.
 ///
 /// # Notes
 /// - The `last_modified` property is expected to be in RFC 3339 format, which is the standard format for timestamps
 ///   in S3 metadata. If parsing fails, the file is skipped, and a warning is logged.
-/// - Files older than the specified retention period are deleted from the S3 bucket.
+/// - Files that are not kept by the retention policy are deleted from the S3 bucket.
 ///
 /// # Example
 /// ```rust
 /// let bucket: Bucket = /* Obtain the S3 bucket instance */;
 /// let folder = "backup_folder".to_string();
-/// let retention = 30; // Retention period of 30 days
-/// check_outdated_s3_backups(&bucket, &folder, &retention).await?;
+/// check_outdated_s3_backups(&bucket, &folder, &RetentionPolicy::stored(&element)).await?;
 /// ```
 pub async fn check_outdated_s3_backups(
     bucket: &Bucket,
     folder: &String,
-    retention: &u64,
+    policy: &RetentionPolicy,
 ) -> Result<(), Box<dyn Error>> {
-    let now = Local::now();
-
     let results = match get_s3_objects_list(bucket, folder).await {
         Ok(results) => results,
         Err(e) => {
@@ -154,18 +356,20 @@ pub async fn check_outdated_s3_backups(
         }
     };
 
+    let mut backups = Vec::new();
+
     for result in results {
         let contents = result.contents;
 
         for object in contents {
             let last_modified_str = &object.last_modified;
 
-            if let Ok(last_modified) = DateTime::parse_from_rfc3339(last_modified_str) {
-                let file_age = now - last_modified.with_timezone(&Local);
-                if file_age > Duration::days(*retention as i64) {
-                    bucket.delete_object(&object.key).await?;
-                    info!("Deleted outdated backup: {}", object.key);
-                }
+            if let Some(backup_time) = get_backup_time(&object.key, last_modified_str) {
+                backups.push(StoredBackup {
+                    key: object.key,
+                    time: backup_time,
+                    size: Some(object.size),
+                });
             } else {
                 warn!(
                     "Failed to parse last_modified for object {}: {}",
@@ -175,6 +379,18 @@ pub async fn check_outdated_s3_backups(
         }
     }
 
+    let outdated = policy.get_outdated(backups.clone(), |backup| backup.time);
+    check_pruning(&backups, &outdated)?;
+
+    for StoredBackup { key, .. } in outdated {
+        bucket.delete_object(&key).await?;
+        info!("Deleted outdated backup: {}", key);
+
+        if let Err(e) = bucket.delete_object(get_checksum_key(&key)).await {
+            warn!("Failed to delete checksum of {}: {}", key, e);
+        }
+    }
+
     info!("Check and delete outdated S3 backups completed");
 
     Ok(())
@@ -182,7 +398,7 @@ pub async fn check_outdated_s3_backups(
 
 /// Finds the latest backup file in an S3 bucket folder based on the modification date.
 ///
-/// This function lists all objects in the specified S3 folder and checks the `last_modified` timestamp
+/// This function lists all objects in the specified S3 folder and checks the backup time (see `get_backup_time`)
 /// of each object to determine the most recent backup file. The latest file is returned as the file key (name).
 /// If no backups are found in the folder, an error is returned.
 ///
@@ -220,7 +436,7 @@ pub async fn find_latest_s3_backup(
         }
     };
 
-    let mut latest_backup: Option<(String, DateTime<Local>)> = None;
+    let mut latest_backup: Option<(String, DateTime<FixedOffset>)> = None;
 
     for result in results {
         let contents = result.contents;
@@ -228,9 +444,7 @@ pub async fn find_latest_s3_backup(
         for object in contents {
             let last_modified_str = &object.last_modified;
 
-            if let Ok(last_modified) = DateTime::parse_from_rfc3339(last_modified_str) {
-                let last_modified_local = last_modified.with_timezone(&Local);
-
+            if let Some(last_modified_local) = get_backup_time(&object.key, last_modified_str) {
                 if latest_backup
                     .as_ref()
                     .map_or(true, |(_, latest_date)| last_modified_local > *latest_date)
@@ -259,12 +473,14 @@ pub async fn find_latest_s3_backup(
 ///
 /// This function first retrieves the latest backup file by calling `find_latest_s3_backup` and then
 /// downloads the file from the S3 bucket to the specified local path. If the local directory doesn't exist,
-/// it is created before downloading the file.
+/// it is created before downloading the file. If the backup is stored in the Glacier or Deep Archive storage
+/// class, it is retrieved with `wait_for_glacier_restore` before downloading.
 ///
 /// # Arguments
 /// - `bucket` - The S3 bucket containing the backup file to be downloaded.
 /// - `path` - The local directory where the backup file will be saved.
 /// - `file_key` - The folder in the S3 bucket where the backup files are stored (used to find the latest backup).
+/// - `glacier` - The parameters used to retrieve archived backups.
 ///
 /// # Returns
 /// - `Ok(PathBuf)` containing the path to the downloaded file if successful.
@@ -274,6 +490,7 @@ pub async fn find_latest_s3_backup(
 /// This function will return an error if:
 /// - The latest backup cannot be found in the specified folder.
 /// - The directory cannot be created.
+/// - The archived backup cannot be retrieved.
 /// - The file download fails due to S3 or network issues.
 ///
 /// # Example
@@ -281,7 +498,7 @@ pub async fn find_latest_s3_backup(
 /// let bucket: Bucket = /* Obtain the S3 bucket instance */;
 /// let path = "local_backup_dir".to_string();
 /// let folder = "backup_folder".to_string();
-/// match get_file_from_s3(&bucket, &path, &folder).await {
+/// match get_file_from_s3(&bucket, &path, &folder, &settings.glacier_restore).await {
 ///     Ok(file_path) => println!("Backup downloaded to: {}", file_path.display()),
 ///     Err(e) => eprintln!("Error: {}", e),
 /// }
@@ -290,9 +507,12 @@ pub async fn get_file_from_s3(
     bucket: &Bucket,
     path: &String,
     file_key: &String,
+    glacier: &GlacierRestore,
 ) -> Result<PathBuf, Box<dyn Error>> {
     let file_key = find_latest_s3_backup(&bucket, &file_key).await?;
 
+    wait_for_glacier_restore(bucket, &file_key, glacier).await?;
+
     let file_path = format!("{}/{}", &path, file_key);
     let path = Path::new(&file_path);
 
@@ -320,3 +540,593 @@ pub async fn get_file_from_s3(
 
     Ok(PathBuf::from(path))
 }
+
+/// Downloads an S3 object into a local directory.
+///
+/// The file keeps the name of the object (the last component of the key).
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket where the object is stored.
+/// - `key` - The key of the object.
+/// - `dir` - The local directory where the file is written. It is created if it does not exist.
+///
+/// # Returns
+/// - `Ok(PathBuf)` - The path of the downloaded file.
+/// - `Err(Box<dyn Error>)` - If the directory cannot be created or the object cannot be downloaded.
+///
+/// # Example
+/// ```rust
+/// let file_path = download_s3_object(&bucket, &key, Path::new("/tmp/backups/to_diff")).await?;
+/// ```
+pub async fn download_s3_object(
+    bucket: &Bucket,
+    key: &str,
+    dir: &Path,
+) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+
+    let file_name = key.rsplit('/').next().unwrap_or(key);
+    let file_path = dir.join(file_name);
+
+    let mut output_file = File::create(&file_path).await?;
+    bucket.get_object_to_writer(key, &mut output_file).await?;
+
+    info!("File downloaded successfully: {}", key);
+
+    Ok(file_path)
+}
+
+/// Determines the time a backup was created.
+///
+/// The timestamp embedded in the backup file name (interpreted in the configured timezone) is preferred, since the `last_modified` property of an
+/// object reflects the time of the upload (or copy) rather than the time of the backup. If the file name
+/// does not contain a timestamp, the `last_modified` property is used.
+///
+/// # Arguments
+/// - `key` - The S3 key of the backup.
+/// - `last_modified` - The `last_modified` property of the object in RFC 3339 format.
+///
+/// # Returns
+/// - `Some(DateTime<FixedOffset>)` containing the backup time in the configured timezone.
+/// - `None` if neither the file name nor the `last_modified` property can be parsed.
+///
+/// # Example
+/// ```rust
+/// let backup_time = get_backup_time(&object.key, &object.last_modified);
+/// ```
+pub fn get_backup_time(key: &str, last_modified: &str) -> Option<DateTime<FixedOffset>> {
+    let file_name = key.rsplit('/').next().unwrap_or(key);
+
+    let from_name = file_name
+        .rmatch_indices('-')
+        .filter_map(|(index, _)| file_name.get(index + 1..index + 20))
+        .filter_map(|value| NaiveDateTime::parse_from_str(value, BACKUP_TIMESTAMP_FORMAT).ok())
+        .find_map(|value| time_utils::from_naive(&value));
+
+    from_name.or_else(|| {
+        DateTime::parse_from_rfc3339(last_modified)
+            .ok()
+            .map(|value| time_utils::to_configured(&value))
+    })
+}
+
+/// Resolves the S3 key of a backup within a folder.
+///
+/// If `key` is `None`, the latest backup in the folder is found with `find_latest_s3_backup`. Otherwise, the key
+/// is used as is when it already starts with the folder, or treated as a file name within the folder.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket containing the backups.
+/// - `folder` - The folder within the S3 bucket containing the backups.
+/// - `key` - The optional key or file name of the backup.
+///
+/// # Returns
+/// - `Ok(String)` containing the full S3 key of the backup.
+/// - `Err(Box<dyn Error>)` if the latest backup cannot be found.
+///
+/// # Example
+/// ```rust
+/// let key = resolve_s3_backup_key(&bucket, &folder, Some(&file_name)).await?;
+/// ```
+pub async fn resolve_s3_backup_key(
+    bucket: &Bucket,
+    folder: &String,
+    key: Option<&String>,
+) -> Result<String, Box<dyn Error>> {
+    match key {
+        None => find_latest_s3_backup(bucket, folder).await,
+        Some(key) => {
+            let key = key.trim_start_matches('/');
+            if key.starts_with(&folder_prefix(folder)) {
+                Ok(key.to_string())
+            } else {
+                Ok(format!("{}{}", folder_prefix(folder), key))
+            }
+        }
+    }
+}
+
+/// Makes sure an object in the Glacier or Deep Archive storage class can be downloaded.
+///
+/// This function checks the storage class of the object. Objects in other storage classes are returned
+/// immediately. For archived objects, a restore request is initiated (if none is in progress) with the
+/// configured tier, and the object is polled until the restored copy becomes available.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket containing the object.
+/// - `key` - The key of the object.
+/// - `glacier` - The parameters used for the restore request and polling.
+///
+/// # Returns
+/// - `Ok(())` if the object can be downloaded.
+/// - `Err(Box<dyn Error>)` if the restore request fails or the object is not restored within `max_wait_hours`.
+///
+/// # Example
+/// ```rust
+/// wait_for_glacier_restore(&bucket, &key, &settings.glacier_restore).await?;
+/// ```
+pub async fn wait_for_glacier_restore(
+    bucket: &Bucket,
+    key: &str,
+    glacier: &GlacierRestore,
+) -> Result<(), Box<dyn Error>> {
+    let started = time_utils::now();
+    let mut requested = false;
+
+    loop {
+        let (head, _) = bucket.head_object(key).await?;
+
+        match get_glacier_state(head.storage_class.as_deref(), head.restore.as_deref()) {
+            GlacierState::Available => return Ok(()),
+            GlacierState::Restored => {
+                info!("Archived object {} is restored and ready to download", key);
+                return Ok(());
+            }
+            GlacierState::InProgress => info!("Restore of archived object {} is in progress", key),
+            GlacierState::NotRequested if !requested => {
+                request_glacier_restore(bucket, key, glacier).await?;
+                requested = true;
+            }
+            GlacierState::NotRequested => {}
+        }
+
+        if time_utils::now() - started > Duration::hours(glacier.max_wait_hours as i64) {
+            return Err(format!(
+                "Archived object {} was not restored within {} hours",
+                key, glacier.max_wait_hours
+            )
+            .into());
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(glacier.poll_interval_secs)).await;
+    }
+}
+
+/// The retrieval state of an object, derived from its storage class and `x-amz-restore` header.
+///
+/// # Variants
+/// - `Available` - The object is not archived and can be downloaded.
+/// - `Restored` - The object is archived, but a restored copy is available.
+/// - `InProgress` - A restore of the archived object is in progress.
+/// - `NotRequested` - The object is archived and no restore has been requested.
+#[derive(Debug, PartialEq)]
+enum GlacierState {
+    Available,
+    Restored,
+    InProgress,
+    NotRequested,
+}
+
+/// Returns the retrieval state of an object from its storage class and the value of its `x-amz-restore` header
+/// (e.g., `ongoing-request="false", expiry-date="Fri, 10 Jan 2025 00:00:00 GMT"`).
+fn get_glacier_state(storage_class: Option<&str>, restore: Option<&str>) -> GlacierState {
+    match storage_class {
+        Some("GLACIER") | Some("DEEP_ARCHIVE") => {}
+        _ => return GlacierState::Available,
+    }
+
+    match restore {
+        Some(restore) if restore.contains("ongoing-request=\"false\"") => GlacierState::Restored,
+        Some(_) => GlacierState::InProgress,
+        None => GlacierState::NotRequested,
+    }
+}
+
+/// Initiates a restore request for an object in the Glacier or Deep Archive storage class.
+///
+/// The `POST ?restore` request is signed with AWS Signature Version 4 (see `s3::signing`) using the credentials,
+/// region and endpoint of the provided bucket, including the session token of temporary credentials.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket containing the object.
+/// - `key` - The key of the object.
+/// - `glacier` - The parameters of the restore request.
+///
+/// # Returns
+/// - `Ok(())` if the restore request was accepted.
+/// - `Err(Box<dyn Error>)` if the request cannot be signed or sent, or is rejected.
+async fn request_glacier_restore(
+    bucket: &Bucket,
+    key: &str,
+    glacier: &GlacierRestore,
+) -> Result<(), Box<dyn Error>> {
+    info!(
+        "Requesting restore of archived object {}: tier={}, days={}",
+        key,
+        glacier.tier.as_str(),
+        glacier.days
+    );
+
+    let body = get_restore_request_body(glacier);
+    let url = Url::parse(&format!(
+        "{}/{}?restore",
+        bucket.url(),
+        signing::uri_encode(key.trim_start_matches('/'), false)
+    ))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(format!("Invalid S3 endpoint {}", url).into()),
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let content_sha256 = format!("{:x}", Sha256::digest(body.as_bytes()));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::HOST, host.parse()?);
+    headers.insert(header::CONTENT_TYPE, "application/xml".parse()?);
+    headers.insert("x-amz-content-sha256", content_sha256.parse()?);
+    headers.insert("x-amz-date", now.format(AMZ_DATE_FORMAT)?.parse()?);
+
+    let credentials = bucket.credentials().await?;
+    if let Some(token) = credentials.session_token.or(credentials.security_token) {
+        headers.insert("x-amz-security-token", token.parse()?);
+    }
+
+    if let (Some(access_key), Some(secret_key)) = (&credentials.access_key, &credentials.secret_key) {
+        let region = bucket.region();
+        let canonical_request = signing::canonical_request("POST", &url, &headers, &content_sha256)?;
+        let string_to_sign = signing::string_to_sign(&now, &region, &canonical_request)?;
+        let mut hmac = signing::HmacSha256::new_from_slice(&signing::signing_key(&now, secret_key, &region, "s3")?)?;
+        hmac.update(string_to_sign.as_bytes());
+        let signature = format!("{:x}", hmac.finalize().into_bytes());
+        let authorization = signing::authorization_header(
+            access_key,
+            &now,
+            &region,
+            &signing::signed_header_string(&headers),
+            &signature,
+        )?;
+        headers.insert(header::AUTHORIZATION, authorization.parse()?);
+    }
+
+    let mut request = reqwest::Client::new().post(url).body(body);
+    for (name, value) in &headers {
+        request = request.header(name.as_str(), value.as_bytes());
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to request restore of archived object {}: {} {}",
+            key,
+            status,
+            response.text().await.unwrap_or_default()
+        )
+        .into())
+    }
+}
+
+/// Returns the XML body of a `RestoreObject` request.
+fn get_restore_request_body(glacier: &GlacierRestore) -> String {
+    format!(
+        "<RestoreRequest><Days>{}</Days><GlacierJobParameters><Tier>{}</Tier></GlacierJobParameters></RestoreRequest>",
+        glacier.days,
+        glacier.tier.as_str()
+    )
+}
+
+/// Builds the listing prefix for an S3 folder, allowing an empty folder to address the whole bucket.
+fn folder_prefix(folder: &str) -> String {
+    let folder = folder.trim_matches('/');
+    if folder.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", folder)
+    }
+}
+
+/// Mirrors all objects under a prefix of one S3 bucket into a folder of another S3 bucket.
+///
+/// Objects that are missing in the target folder, differ in size, or were modified in the source after
+/// the target copy was made are copied. When the source and the target are the same bucket, the copy is
+/// performed server-side. Otherwise, each object is downloaded to `temp_dir` and uploaded to the target.
+///
+/// # Arguments
+/// - `source` - The S3 bucket to copy objects from.
+/// - `source_prefix` - The prefix within the source bucket to mirror. An empty prefix mirrors the whole bucket.
+/// - `target` - The S3 bucket to copy objects to.
+/// - `target_folder` - The folder within the target bucket where the objects will be stored.
+/// - `temp_dir` - The local directory used to stage objects that cannot be copied server-side.
+///
+/// # Returns
+/// - `Ok(HashSet<String>)` containing the keys (relative to `source_prefix`) of all objects present in the source.
+/// - `Err(Box<dyn Error>)` if listing, downloading, or uploading an object fails.
+///
+/// # Example
+/// ```rust
+/// let source_keys = sync_s3_prefix(&source, &prefix, &bucket, &s3_folder, &temp_dir).await?;
+/// ```
+pub async fn sync_s3_prefix(
+    source: &Bucket,
+    source_prefix: &str,
+    target: &Bucket,
+    target_folder: &str,
+    temp_dir: &Path,
+) -> Result<HashSet<String>, Box<dyn Error>> {
+    let source_prefix = folder_prefix(source_prefix);
+    let target_prefix = folder_prefix(target_folder);
+    let server_side = source.name() == target.name() && source.region() == target.region();
+
+    let mut existing: HashMap<String, (u64, Option<DateTime<FixedOffset>>)> = HashMap::new();
+    for result in target.list(target_prefix.clone(), None).await? {
+        for object in result.contents {
+            let relative_key = object.key[target_prefix.len()..].to_string();
+            let last_modified = DateTime::parse_from_rfc3339(&object.last_modified)
+                .ok()
+                .map(|date| time_utils::to_configured(&date));
+            existing.insert(relative_key, (object.size, last_modified));
+        }
+    }
+
+    let mut source_keys = HashSet::new();
+    let mut copied = 0;
+
+    for result in source.list(source_prefix.clone(), None).await? {
+        for object in result.contents {
+            let relative_key = object.key[source_prefix.len()..].to_string();
+            if relative_key.is_empty() || relative_key.ends_with('/') {
+                continue;
+            }
+            source_keys.insert(relative_key.clone());
+
+            let source_modified = DateTime::parse_from_rfc3339(&object.last_modified)
+                .ok()
+                .map(|date| time_utils::to_configured(&date));
+
+            let up_to_date = match existing.get(&relative_key) {
+                Some((size, target_modified)) => {
+                    *size == object.size
+                        && matches!((source_modified, target_modified), (Some(s), Some(t)) if s <= *t)
+                }
+                None => false,
+            };
+
+            if up_to_date {
+                continue;
+            }
+
+            let target_key = format!("{}{}", target_prefix, relative_key);
+
+            if server_side {
+                target.copy_object_internal(&object.key, &target_key).await?;
+            } else {
+                let temp_file = TempFile::unique(temp_dir, "s3_sync.tmp");
+
+                source
+                    .get_object_to_writer(&object.key, &mut File::create(temp_file.path()).await?)
+                    .await?;
+
+                let mut reader = BufReader::new(File::open(temp_file.path()).await?);
+                target.put_object_stream(&mut reader, &target_key).await?;
+            }
+
+            copied += 1;
+            info!("Synced object {} to {}", object.key, target_key);
+        }
+    }
+
+    info!(
+        "Sync of {} objects completed, {} objects copied",
+        source_keys.len(),
+        copied
+    );
+
+    Ok(source_keys)
+}
+
+/// Deletes mirrored objects that no longer exist in the sync source and exceed the retention period.
+///
+/// Objects that are still present in the source are never deleted, regardless of their age.
+///
+/// # Arguments
+/// - `bucket` - The S3 bucket containing the mirror.
+/// - `folder` - The folder within the S3 bucket containing the mirrored objects.
+/// - `source_keys` - The keys (relative to the folder) of all objects present in the sync source.
+/// - `retention` - The retention period in days for objects removed from the source.
+///
+/// # Returns
+/// - `Ok(())` if the outdated objects were successfully checked and deleted.
+/// - `Err(Box<dyn Error>)` if listing or deleting objects fails.
+///
+/// # Example
+/// ```rust
+/// check_outdated_s3_mirror(&bucket, &folder, &source_keys, &retention).await?;
+/// ```
+pub async fn check_outdated_s3_mirror(
+    bucket: &Bucket,
+    folder: &str,
+    source_keys: &HashSet<String>,
+    retention: &u64,
+) -> Result<(), Box<dyn Error>> {
+    let now = time_utils::now();
+    let prefix = folder_prefix(folder);
+
+    for result in bucket.list(prefix.clone(), None).await? {
+        for object in result.contents {
+            if source_keys.contains(&object.key[prefix.len()..]) {
+                continue;
+            }
+
+            if let Ok(last_modified) = DateTime::parse_from_rfc3339(&object.last_modified) {
+                let file_age = now - time_utils::to_configured(&last_modified);
+                if file_age > Duration::days(*retention as i64) {
+                    bucket.delete_object(&object.key).await?;
+                    info!("Deleted outdated mirrored object: {}", object.key);
+                }
+            } else {
+                warn!(
+                    "Failed to parse last_modified for object {}: {}",
+                    object.key, object.last_modified
+                );
+            }
+        }
+    }
+
+    info!("Check and delete outdated S3 mirror objects completed");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::settings::Settings;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn glacier_state_of_objects_in_other_storage_classes_is_available() {
+        assert_eq!(get_glacier_state(None, None), GlacierState::Available);
+        assert_eq!(get_glacier_state(Some("STANDARD"), None), GlacierState::Available);
+        assert_eq!(get_glacier_state(Some("GLACIER_IR"), None), GlacierState::Available);
+    }
+
+    #[test]
+    fn glacier_state_follows_the_restore_header() {
+        for storage_class in ["GLACIER", "DEEP_ARCHIVE"] {
+            assert_eq!(get_glacier_state(Some(storage_class), None), GlacierState::NotRequested);
+            assert_eq!(
+                get_glacier_state(Some(storage_class), Some("ongoing-request=\"true\"")),
+                GlacierState::InProgress
+            );
+            assert_eq!(
+                get_glacier_state(
+                    Some(storage_class),
+                    Some("ongoing-request=\"false\", expiry-date=\"Fri, 10 Jan 2025 00:00:00 GMT\"")
+                ),
+                GlacierState::Restored
+            );
+        }
+    }
+
+    #[test]
+    fn restore_request_body_contains_days_and_tier() {
+        let glacier: GlacierRestore = serde_json::from_str(r#"{"tier": "bulk", "days": 3}"#).unwrap();
+
+        assert_eq!(
+            get_restore_request_body(&glacier),
+            "<RestoreRequest><Days>3</Days><GlacierJobParameters><Tier>Bulk</Tier></GlacierJobParameters></RestoreRequest>"
+        );
+    }
+
+    #[test]
+    fn adds_sequence_suffix_after_the_timestamp() {
+        let cases = [
+            ("my_db-2025-01-10_02-00-00.sql", 1, "my_db-2025-01-10_02-00-00-1.sql"),
+            ("files-2025-01-10_02-00-00.inc2.tar.gz", 2, "files-2025-01-10_02-00-00-2.inc2.tar.gz"),
+            ("my-app-2025-01-10_02-00-00.sql.zst.age", 1, "my-app-2025-01-10_02-00-00-1.sql.zst.age"),
+            ("my_db-2025-01-10_02-00-00", 3, "my_db-2025-01-10_02-00-00-3"),
+            ("dump.sql", 1, "dump.sql-1"),
+            ("my_db-2025-13-10_02-00-00.sql", 1, "my_db-2025-13-10_02-00-00.sql-1"),
+        ];
+
+        for (file_name, sequence, expected) in cases {
+            assert_eq!(add_sequence_suffix(file_name, sequence), expected, "{}", file_name);
+        }
+    }
+
+    /// Answers S3 requests with the given statuses, one request per connection, and returns the request lines.
+    fn serve_statuses(statuses: &[u16]) -> (Bucket, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings: Settings = serde_json::from_str(&format!(
+            r#"{{
+                "s3_endpoint": "http://{}",
+                "s3_region": "us-east-1",
+                "s3_bucket": "backups",
+                "s3_access": "access",
+                "s3_secret": "secret",
+                "s3_path_style": "path",
+                "backup_dir": "/tmp/reback",
+                "elements": []
+            }}"#,
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let statuses = statuses.to_vec();
+
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+
+                let response = format!("HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).unwrap();
+
+                let request = String::from_utf8_lossy(&request).to_string();
+                requests.push(request.lines().next().unwrap_or_default().to_string());
+            }
+
+            requests
+        });
+
+        (settings.get_bucket().unwrap(), server)
+    }
+
+    #[tokio::test]
+    async fn available_s3_key_skips_existing_backups() {
+        let (bucket, server) = serve_statuses(&[200, 404]);
+
+        let key = get_available_s3_key(&bucket, "db", "db-2025-01-10_02-00-00.sql").await;
+        let requests = server.join().unwrap();
+
+        assert_eq!(key.unwrap(), "/db/db-2025-01-10_02-00-00-1.sql");
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn available_s3_key_is_used_unchecked_if_access_is_denied() {
+        let (bucket, server) = serve_statuses(&[403]);
+
+        let key = get_available_s3_key(&bucket, "db", "db-2025-01-10_02-00-00.sql").await;
+        server.join().unwrap();
+
+        assert_eq!(key.unwrap(), "/db/db-2025-01-10_02-00-00.sql");
+    }
+
+    #[tokio::test]
+    async fn available_s3_key_fails_on_other_errors() {
+        let (bucket, server) = serve_statuses(&[503]);
+
+        let key = get_available_s3_key(&bucket, "db", "db-2025-01-10_02-00-00.sql").await;
+        server.join().unwrap();
+
+        assert!(key.unwrap_err().contains("503"));
+    }
+}
@@ -0,0 +1,1001 @@
+use crate::structures::elements::Elements;
+use crate::structures::settings::{Settings, StorageTarget};
+use crate::utils::incremental_utils::check_pruning;
+use crate::utils::retention_utils::RetentionPolicy;
+use crate::utils::s3_utils::{
+    add_sequence_suffix, check_outdated_s3_backups, download_s3_object, get_backup_checksum,
+    get_backup_time, get_checksum_key, get_file_from_s3, get_s3_objects_list, upload_file_to_s3,
+    wait_for_glacier_restore,
+};
+use chrono::{DateTime, FixedOffset, Utc};
+use log::{info, warn};
+use s3::Bucket;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The default port of SFTP servers.
+const SFTP_DEFAULT_PORT: u16 = 22;
+
+/// Represents a backup file stored in a storage target.
+///
+/// # Fields
+/// - `key` - The path of the backup relative to the root of the target (`<s3_folder>/<file name>`).
+/// - `time` - The backup time in the configured timezone (see `get_backup_time`).
+/// - `size` - The size of the file in bytes, if the target reports it.
+#[derive(Debug, Clone)]
+pub struct StoredBackup {
+    pub key: String,
+    pub time: DateTime<FixedOffset>,
+    pub size: Option<u64>,
+}
+
+/// Selects the backup of a folder that is restored.
+///
+/// # Variants
+/// - `Latest` - The newest backup.
+/// - `Key` - The backup with the given key. A file name is interpreted as a file in the folder.
+/// - `Before` - The newest backup created before the given time.
+/// - `At` - The backup created at the given time (to the second), e.g., a time printed by `reback list`.
+#[derive(Debug, Clone)]
+pub enum BackupSelector {
+    Latest,
+    Key(String),
+    Before(DateTime<FixedOffset>),
+    At(DateTime<FixedOffset>),
+}
+
+impl BackupSelector {
+    /// Returns a human-readable description of the selected backup used in logs and errors
+    /// (e.g., `latest backup before 2025-01-10 00:00:00`).
+    pub fn describe(&self) -> String {
+        match self {
+            BackupSelector::Latest => String::from("latest backup"),
+            BackupSelector::Key(key) => format!("backup {}", key),
+            BackupSelector::Before(time) => format!("latest backup before {}", time.format("%Y-%m-%d %H:%M:%S")),
+            BackupSelector::At(time) => format!("backup at {}", time.format("%Y-%m-%d %H:%M:%S")),
+        }
+    }
+
+    /// Selects a backup from the backups of a folder.
+    ///
+    /// # Arguments
+    /// - `folder` - The folder the backups were listed from.
+    /// - `backups` - The backups of the folder.
+    ///
+    /// # Returns
+    /// - `Some(&StoredBackup)` - The selected backup. If several backups match, the newest one is returned.
+    /// - `None` - If no backup matches.
+    ///
+    /// # Example
+    /// ```rust
+    /// let backup = selector.select(&element.s3_folder, &storage.list(&element.s3_folder).await?);
+    /// ```
+    pub fn select<'a>(&self, folder: &str, backups: &'a [StoredBackup]) -> Option<&'a StoredBackup> {
+        let folder = format!("{}/", folder.trim_matches('/'));
+
+        backups
+            .iter()
+            .filter(|backup| match self {
+                BackupSelector::Latest => true,
+                BackupSelector::Key(key) => {
+                    let key = key.trim_start_matches('/');
+                    backup.key == key || backup.key.strip_prefix(&folder) == Some(key)
+                }
+                BackupSelector::Before(time) => backup.time < *time,
+                BackupSelector::At(time) => backup.time.timestamp() == time.timestamp(),
+            })
+            .max_by_key(|backup| backup.time)
+    }
+}
+
+/// A destination where backup files are uploaded, listed, downloaded and deleted.
+///
+/// Backups are addressed by keys relative to the root of the target (`<s3_folder>/<file name>`), so the same
+/// element has the same layout in every target. `download_latest` and `delete_outdated` are built on top of the
+/// other methods, but can be overridden when a target has a more specific implementation (e.g., S3 checksums and
+/// Glacier retrieval).
+pub trait StorageBackend {
+    /// Returns a human-readable name of the target used in logs and errors (e.g., `S3`, `local:/mnt/backups`).
+    fn describe(&self) -> String;
+
+    /// Uploads a backup file into a folder. If a file with the same name already exists, a sequence suffix is added
+    /// (see `add_sequence_suffix`), so existing backups are never overwritten.
+    async fn upload(&self, file_path: &Path, folder: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Lists the backups in a folder. Files without a recognizable backup time are skipped with a warning.
+    async fn list(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>>;
+
+    /// Lists the backups in a folder before outdated backups are deleted from it. Targets with separate prune
+    /// credentials list with them, so the folder is read with the same access as the backups are deleted with.
+    async fn list_for_prune(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        self.list(folder).await
+    }
+
+    /// Downloads a backup into `dir/<key>` and returns the path of the downloaded file.
+    async fn download(&self, key: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>>;
+
+    /// Deletes a backup.
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Returns the recorded SHA-256 checksum of a backup, or `None` if the target does not record checksums or no
+    /// checksum was recorded for the backup.
+    async fn get_checksum(&self, _key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    /// Downloads the latest backup of a folder into `dir/<key>`.
+    ///
+    /// # Returns
+    /// - `Ok(PathBuf)` - The path of the downloaded file.
+    /// - `Err(Box<dyn Error>)` - If the folder cannot be listed, contains no backups or the download fails.
+    async fn download_latest(&self, folder: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let latest = self
+            .list(folder)
+            .await?
+            .into_iter()
+            .max_by_key(|backup| backup.time)
+            .ok_or(format!("No backups found in {} of {}", folder, self.describe()))?;
+
+        info!("Latest backup found in {}: {}", self.describe(), latest.key);
+
+        self.download(&latest.key, dir).await
+    }
+
+    /// Downloads the backup of a folder chosen by a selector into `dir/<key>`.
+    ///
+    /// The latest backup is downloaded with `download_latest`, so targets that override it keep their behavior.
+    ///
+    /// # Returns
+    /// - `Ok(PathBuf)` - The path of the downloaded file.
+    /// - `Err(Box<dyn Error>)` - If the folder cannot be listed, no backup matches or the download fails.
+    async fn download_selected(
+        &self,
+        folder: &str,
+        selector: &BackupSelector,
+        dir: &Path,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        if let BackupSelector::Latest = selector {
+            return self.download_latest(folder, dir).await;
+        }
+
+        let backups = self.list(folder).await?;
+        let backup = selector.select(folder, &backups).ok_or(format!(
+            "No {} found in {} of {}",
+            selector.describe(),
+            folder,
+            self.describe()
+        ))?;
+
+        info!("Selected backup in {}: {}", self.describe(), backup.key);
+
+        self.download(&backup.key, dir).await
+    }
+
+    /// Returns the backups of a folder that are not kept by the retention policy, newest first.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<StoredBackup>)` - The outdated backups.
+    /// - `Err(Box<dyn Error>)` - If the folder cannot be listed, or deleting the outdated backups would break an
+    ///   incremental chain that is still kept (see `check_pruning`).
+    async fn get_outdated(&self, folder: &str, policy: &RetentionPolicy) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        let backups = self.list_for_prune(folder).await?;
+        let outdated = policy.get_outdated(backups.clone(), |backup| backup.time);
+
+        check_pruning(&backups, &outdated)?;
+
+        Ok(outdated)
+    }
+
+    /// Deletes the backups of a folder that are not kept by the retention policy.
+    ///
+    /// # Returns
+    /// - `Ok(())` - If the outdated backups are deleted.
+    /// - `Err(Box<dyn Error>)` - If the folder cannot be listed or a backup cannot be deleted.
+    async fn delete_outdated(&self, folder: &str, policy: &RetentionPolicy) -> Result<(), Box<dyn Error>> {
+        for backup in self.get_outdated(folder, policy).await? {
+            self.delete(&backup.key).await?;
+            info!("Deleted outdated backup from {}: {}", self.describe(), backup.key);
+        }
+
+        info!("Check and delete outdated backups in {} completed", self.describe());
+
+        Ok(())
+    }
+}
+
+/// Represents a configured storage target of an element.
+///
+/// The enum dispatches to the backend of each target, so elements can have a list of targets of different types.
+pub enum Storage<'a> {
+    S3(S3Storage<'a>),
+    Local(LocalStorage),
+    Sftp(SftpStorage),
+}
+
+/// Returns the storage targets of an element.
+///
+/// The `storage` of the element takes precedence over `Settings::storage`. If neither is set, the element is
+/// stored in the S3 bucket.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the global storage targets and named S3 credentials.
+/// - `bucket` - The S3 bucket (created with the default keys).
+/// - `element` - The element whose targets are returned.
+///
+/// # Returns
+/// The storage targets in the configured order. The first target is used for restores.
+///
+/// # Example
+/// ```rust
+/// for storage in get_storages(&settings, &bucket, &element) {
+///     storage.upload(&file_path, &element.s3_folder).await?;
+/// }
+/// ```
+pub fn get_storages<'a>(settings: &'a Settings, bucket: &'a Bucket, element: &'a Elements) -> Vec<Storage<'a>> {
+    let targets = element.storage.as_ref().unwrap_or(&settings.storage);
+    let s3 = || {
+        Storage::S3(S3Storage {
+            settings,
+            bucket,
+            element,
+        })
+    };
+
+    if targets.is_empty() {
+        return vec![s3()];
+    }
+
+    targets
+        .iter()
+        .map(|target| match target {
+            StorageTarget::S3 => s3(),
+            StorageTarget::Local { path } => Storage::Local(LocalStorage {
+                root: PathBuf::from(path),
+            }),
+            StorageTarget::Sftp {
+                host,
+                port,
+                user,
+                identity_file,
+                path,
+            } => Storage::Sftp(SftpStorage {
+                host: host.clone(),
+                port: port.unwrap_or(SFTP_DEFAULT_PORT),
+                user: user.clone(),
+                identity_file: identity_file.clone(),
+                root: path.trim_end_matches('/').to_string(),
+            }),
+        })
+        .collect()
+}
+
+/// Checks that an element stores its backups in the S3 bucket.
+///
+/// Commands that work on single S3 objects (`cat`, `verify`, `share`, `inspect`, `diff`, `import` and `migrate`)
+/// use this check, so an element that is only stored in local or SFTP targets is rejected with a clear error
+/// instead of reporting that its S3 folder has no backups.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the global storage targets.
+/// - `element` - The element to check.
+///
+/// # Returns
+/// - `Ok(())` if one of the storage targets of the element is S3.
+/// - `Err(String)` - An error message naming the storage targets of the element otherwise.
+///
+/// # Example
+/// ```rust
+/// check_s3_storage(&settings, element)?;
+/// ```
+pub fn check_s3_storage(settings: &Settings, element: &Elements) -> Result<(), String> {
+    let targets = element.storage.as_ref().unwrap_or(&settings.storage);
+
+    if targets.is_empty() || targets.iter().any(|target| matches!(target, StorageTarget::S3)) {
+        return Ok(());
+    }
+
+    let targets: Vec<String> = targets
+        .iter()
+        .map(|target| match target {
+            StorageTarget::S3 => String::from("s3"),
+            StorageTarget::Local { path } => format!("local:{}", path),
+            StorageTarget::Sftp { host, path, .. } => format!("sftp:{}:{}", host, path),
+        })
+        .collect();
+
+    Err(format!(
+        "Element '{}' is not stored in S3 ({}). This command only supports backups in the S3 bucket",
+        element.element_title,
+        targets.join(", ")
+    ))
+}
+
+impl StorageBackend for Storage<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Storage::S3(storage) => storage.describe(),
+            Storage::Local(storage) => storage.describe(),
+            Storage::Sftp(storage) => storage.describe(),
+        }
+    }
+
+    async fn upload(&self, file_path: &Path, folder: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.upload(file_path, folder).await,
+            Storage::Local(storage) => storage.upload(file_path, folder).await,
+            Storage::Sftp(storage) => storage.upload(file_path, folder).await,
+        }
+    }
+
+    async fn list(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.list(folder).await,
+            Storage::Local(storage) => storage.list(folder).await,
+            Storage::Sftp(storage) => storage.list(folder).await,
+        }
+    }
+
+    async fn list_for_prune(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.list_for_prune(folder).await,
+            Storage::Local(storage) => storage.list_for_prune(folder).await,
+            Storage::Sftp(storage) => storage.list_for_prune(folder).await,
+        }
+    }
+
+    async fn download(&self, key: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.download(key, dir).await,
+            Storage::Local(storage) => storage.download(key, dir).await,
+            Storage::Sftp(storage) => storage.download(key, dir).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.delete(key).await,
+            Storage::Local(storage) => storage.delete(key).await,
+            Storage::Sftp(storage) => storage.delete(key).await,
+        }
+    }
+
+    async fn get_checksum(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.get_checksum(key).await,
+            Storage::Local(storage) => storage.get_checksum(key).await,
+            Storage::Sftp(storage) => storage.get_checksum(key).await,
+        }
+    }
+
+    async fn download_latest(&self, folder: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.download_latest(folder, dir).await,
+            Storage::Local(storage) => storage.download_latest(folder, dir).await,
+            Storage::Sftp(storage) => storage.download_latest(folder, dir).await,
+        }
+    }
+
+    async fn delete_outdated(&self, folder: &str, policy: &RetentionPolicy) -> Result<(), Box<dyn Error>> {
+        match self {
+            Storage::S3(storage) => storage.delete_outdated(folder, policy).await,
+            Storage::Local(storage) => storage.delete_outdated(folder, policy).await,
+            Storage::Sftp(storage) => storage.delete_outdated(folder, policy).await,
+        }
+    }
+}
+
+/// Stores backups in the S3 bucket from the settings.
+///
+/// Uploads and downloads use the `s3_credentials` of the element. The prune credentials (`s3_prune_credentials`)
+/// are only loaded right before outdated backups are listed and deleted.
+pub struct S3Storage<'a> {
+    settings: &'a Settings,
+    bucket: &'a Bucket,
+    element: &'a Elements,
+}
+
+impl S3Storage<'_> {
+    /// Returns the bucket created with the given named credentials of the element.
+    fn resolve_bucket(&self, credentials: Option<&String>, purpose: &str) -> Result<Bucket, Box<dyn Error>> {
+        self.settings
+            .resolve_bucket(self.bucket, credentials)
+            .ok_or_else(|| format!("Failed to create S3 bucket for {}{}", purpose, self.element.element_title).into())
+    }
+
+    /// Returns the bucket used for uploads and downloads.
+    fn element_bucket(&self) -> Result<Bucket, Box<dyn Error>> {
+        self.resolve_bucket(self.element.s3_credentials.as_ref(), "")
+    }
+
+    /// Returns the bucket used to delete backups.
+    fn prune_bucket(&self) -> Result<Bucket, Box<dyn Error>> {
+        let credentials = self
+            .element
+            .s3_prune_credentials
+            .as_ref()
+            .or(self.element.s3_credentials.as_ref());
+
+        self.resolve_bucket(credentials, "pruning ")
+    }
+
+    /// Lists the backups in a folder of the given bucket.
+    async fn list_in(&self, bucket: &Bucket, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        let results = get_s3_objects_list(bucket, &folder.to_string()).await?;
+        let mut backups = Vec::new();
+
+        for object in results.into_iter().flat_map(|result| result.contents) {
+            match get_backup_time(&object.key, &object.last_modified) {
+                Some(time) => backups.push(StoredBackup {
+                    key: object.key,
+                    time,
+                    size: Some(object.size),
+                }),
+                None => warn!("Failed to parse the backup time of {}", object.key),
+            }
+        }
+
+        Ok(backups)
+    }
+}
+
+impl StorageBackend for S3Storage<'_> {
+    fn describe(&self) -> String {
+        "S3".to_string()
+    }
+
+    async fn upload(&self, file_path: &Path, folder: &str) -> Result<(), Box<dyn Error>> {
+        upload_file_to_s3(&self.element_bucket()?, file_path, folder).await
+    }
+
+    async fn list(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        self.list_in(&self.element_bucket()?, folder).await
+    }
+
+    async fn list_for_prune(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        self.list_in(&self.prune_bucket()?, folder).await
+    }
+
+    async fn download(&self, key: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let target_dir = match Path::new(key).parent() {
+            Some(parent) => dir.join(parent),
+            None => dir.to_path_buf(),
+        };
+
+        let bucket = self.element_bucket()?;
+        wait_for_glacier_restore(&bucket, key, &self.settings.glacier_restore).await?;
+
+        download_s3_object(&bucket, key, &target_dir).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let bucket = self.prune_bucket()?;
+        bucket.delete_object(key).await?;
+
+        if let Err(e) = bucket.delete_object(get_checksum_key(key)).await {
+            warn!("Failed to delete checksum of {}: {}", key, e);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the checksum recorded by `upload_file_to_s3` (see `get_backup_checksum`).
+    async fn get_checksum(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        get_backup_checksum(&self.element_bucket()?, key).await
+    }
+
+    /// Downloads the latest backup with `get_file_from_s3`, so archived backups are retrieved from Glacier first.
+    async fn download_latest(&self, folder: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        get_file_from_s3(
+            &self.element_bucket()?,
+            &dir.to_string_lossy().to_string(),
+            &folder.to_string(),
+            &self.settings.glacier_restore,
+        )
+        .await
+    }
+
+    /// Deletes outdated backups and their checksums with `check_outdated_s3_backups`.
+    async fn delete_outdated(&self, folder: &str, policy: &RetentionPolicy) -> Result<(), Box<dyn Error>> {
+        check_outdated_s3_backups(&self.prune_bucket()?, &folder.to_string(), policy).await
+    }
+}
+
+/// Stores backups in a local or mounted directory (e.g., an NFS or SMB share).
+///
+/// Files are first copied with a temporary name and renamed afterwards, so an interrupted copy never looks like
+/// a complete backup. Copies, listings and deletions run on a blocking thread (see `run_blocking`).
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Returns the path of a folder or key below the root.
+    ///
+    /// A leading `/` is ignored like in S3 keys. Keys with `..` or other special components are refused, so a
+    /// folder or key can never address (and `delete` never remove) files outside the root.
+    fn path(&self, relative: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let relative = Path::new(relative.trim_start_matches('/'));
+
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!("Invalid path {} in {}", relative.display(), self.describe()).into());
+        }
+
+        Ok(self.root.join(relative))
+    }
+}
+
+impl StorageBackend for LocalStorage {
+    fn describe(&self) -> String {
+        format!("local:{}", self.root.display())
+    }
+
+    async fn upload(&self, file_path: &Path, folder: &str) -> Result<(), Box<dyn Error>> {
+        let file_name = file_path
+            .file_name()
+            .ok_or_else(|| format!("Failed to extract file name from {}", file_path.display()))?
+            .to_string_lossy()
+            .to_string();
+
+        let dir = self.path(folder)?;
+        let file_path = file_path.to_path_buf();
+
+        let target_name = run_blocking(move || {
+            fs::create_dir_all(&dir)?;
+
+            let mut target_name = file_name.clone();
+            let mut sequence = 0;
+            while dir.join(&target_name).exists() {
+                sequence += 1;
+                target_name = add_sequence_suffix(&file_name, sequence);
+            }
+
+            let temp_path = dir.join(format!(".{}.part", target_name));
+            fs::copy(&file_path, &temp_path)?;
+            fs::rename(&temp_path, dir.join(&target_name))?;
+
+            Ok(target_name)
+        })
+        .await?;
+
+        info!("File copied to {}: {}/{}", self.describe(), folder, target_name);
+
+        Ok(())
+    }
+
+    async fn list(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        let dir = self.path(folder)?;
+        let folder = folder.to_string();
+
+        run_blocking(move || {
+            let mut backups = Vec::new();
+
+            if !dir.exists() {
+                return Ok(backups);
+            }
+
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                let metadata = entry.metadata()?;
+
+                if !metadata.is_file() || name.starts_with('.') {
+                    continue;
+                }
+
+                let modified = metadata
+                    .modified()
+                    .map(|time| DateTime::<Utc>::from(time).to_rfc3339())
+                    .unwrap_or_default();
+
+                match get_backup_time(&name, &modified) {
+                    Some(time) => backups.push(StoredBackup {
+                        key: format!("{}/{}", folder, name),
+                        time,
+                        size: Some(metadata.len()),
+                    }),
+                    None => warn!("Failed to parse the backup time of {}", entry.path().display()),
+                }
+            }
+
+            Ok(backups)
+        })
+        .await
+    }
+
+    async fn download(&self, key: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let source = self.path(key)?;
+        let file_path = dir.join(key.trim_start_matches('/'));
+
+        let target = file_path.clone();
+        run_blocking(move || {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::copy(&source, &target)?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("File copied from {}: {}", self.describe(), key);
+
+        Ok(file_path)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let file_path = self.path(key)?;
+
+        run_blocking(move || fs::remove_file(file_path)).await
+    }
+}
+
+/// Stores backups on an SFTP server.
+///
+/// The `sftp` client is run in batch mode, so authentication must work without a password (a key from
+/// `identity_file`, the default keys or an SSH agent). Files are uploaded with a temporary name and renamed
+/// afterwards, so an interrupted upload never looks like a complete backup.
+#[derive(Clone)]
+pub struct SftpStorage {
+    host: String,
+    port: u16,
+    user: String,
+    identity_file: Option<String>,
+    root: String,
+}
+
+impl SftpStorage {
+    /// Runs `sftp` with a batch of commands on a blocking thread and returns its standard output.
+    ///
+    /// Commands prefixed with `-` may fail without aborting the batch (e.g., `-mkdir` of an existing directory).
+    async fn run_batch(&self, commands: String) -> Result<String, Box<dyn Error>> {
+        let storage = self.clone();
+
+        run_blocking(move || storage.run_batch_blocking(&commands)).await
+    }
+
+    /// Runs `sftp` with a batch of commands and waits for it to finish (see `run_batch`).
+    fn run_batch_blocking(&self, commands: &str) -> io::Result<String> {
+        let mut command = Command::new("sftp");
+        command.args(["-b", "-", "-o", "BatchMode=yes", "-P", &self.port.to_string()]);
+
+        if let Some(identity_file) = &self.identity_file {
+            command.args(["-i", identity_file]);
+        }
+
+        let mut child = command
+            .arg(format!("{}@{}", self.user, self.host))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to execute sftp: {}", e)))?;
+
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other("Failed to open the input of sftp"))?
+            .write_all(commands.as_bytes())?;
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "sftp failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Returns the remote path of a key.
+    fn remote_path(&self, key: &str) -> String {
+        format!("{}/{}", self.root, key)
+    }
+
+    /// Lists the names of the backup files in a folder. A missing folder has no files.
+    async fn list_names(&self, folder: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let output = self
+            .run_batch(format!("-ls -1 {}\n", quote(&self.remote_path(folder))))
+            .await?;
+
+        // In batch mode, every command is echoed with the `sftp>` prompt.
+        Ok(output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("sftp>"))
+            .filter_map(|line| line.rsplit('/').next())
+            .filter(|name| !name.starts_with('.'))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+impl StorageBackend for SftpStorage {
+    fn describe(&self) -> String {
+        format!("sftp:{}@{}:{}", self.user, self.host, self.root)
+    }
+
+    async fn upload(&self, file_path: &Path, folder: &str) -> Result<(), Box<dyn Error>> {
+        let file_name = file_path
+            .file_name()
+            .ok_or_else(|| format!("Failed to extract file name from {}", file_path.display()))?
+            .to_string_lossy()
+            .to_string();
+
+        let existing = self.list_names(folder).await?;
+        let mut target_name = file_name.clone();
+        let mut sequence = 0;
+        while existing.contains(&target_name) {
+            sequence += 1;
+            target_name = add_sequence_suffix(&file_name, sequence);
+        }
+
+        let dir = self.remote_path(folder);
+        let mut commands = String::new();
+        let mut current = String::new();
+
+        // `mkdir` is not recursive, so every parent directory is created separately.
+        for component in dir.split('/').filter(|component| !component.is_empty()) {
+            if !current.is_empty() || dir.starts_with('/') {
+                current.push('/');
+            }
+            current.push_str(component);
+            commands.push_str(&format!("-mkdir {}\n", quote(&current)));
+        }
+
+        let temp_path = format!("{}/.{}.part", dir, target_name);
+        commands.push_str(&format!(
+            "put {} {}\nrename {} {}\n",
+            quote(&file_path.to_string_lossy()),
+            quote(&temp_path),
+            quote(&temp_path),
+            quote(&format!("{}/{}", dir, target_name)),
+        ));
+
+        self.run_batch(commands).await?;
+        info!("File uploaded to {}: {}/{}", self.describe(), folder, target_name);
+
+        Ok(())
+    }
+
+    async fn list(&self, folder: &str) -> Result<Vec<StoredBackup>, Box<dyn Error>> {
+        let mut backups = Vec::new();
+
+        for name in self.list_names(folder).await? {
+            match get_backup_time(&name, "") {
+                Some(time) => backups.push(StoredBackup {
+                    key: format!("{}/{}", folder, name),
+                    time,
+                    size: None,
+                }),
+                None => warn!("Failed to parse the backup time of {}/{}", folder, name),
+            }
+        }
+
+        Ok(backups)
+    }
+
+    async fn download(&self, key: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let file_path = dir.join(key);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        self.run_batch(format!(
+            "get {} {}\n",
+            quote(&self.remote_path(key)),
+            quote(&file_path.to_string_lossy())
+        ))
+        .await?;
+        info!("File downloaded from {}: {}", self.describe(), key);
+
+        Ok(file_path)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.run_batch(format!("rm {}\n", quote(&self.remote_path(key))))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Runs blocking file or process I/O on a blocking thread, so it does not stall the workers of the runtime (e.g.,
+/// other elements backed up in parallel).
+async fn run_blocking<T: Send + 'static>(
+    task: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> Result<T, Box<dyn Error>> {
+    Ok(tokio::task::spawn_blocking(task).await??)
+}
+
+/// Quotes a path for an `sftp` batch command.
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn time(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    fn backups() -> Vec<StoredBackup> {
+        [
+            ("db/db-2025-01-10_02-00-00.sql", "2025-01-10T02:00:00+03:00"),
+            ("db/db-2025-01-11_02-00-00.sql", "2025-01-11T02:00:00+03:00"),
+            ("db/db-2025-01-12_02-00-00.sql", "2025-01-12T02:00:00+03:00"),
+        ]
+        .iter()
+        .map(|(key, value)| StoredBackup {
+            key: key.to_string(),
+            time: time(value),
+            size: None,
+        })
+        .collect()
+    }
+
+    fn select(selector: BackupSelector) -> Option<String> {
+        selector.select("/db/", &backups()).map(|backup| backup.key.clone())
+    }
+
+    #[test]
+    fn selects_latest_backup() {
+        assert_eq!(select(BackupSelector::Latest).as_deref(), Some("db/db-2025-01-12_02-00-00.sql"));
+        assert!(BackupSelector::Latest.select("db", &[]).is_none());
+    }
+
+    #[test]
+    fn selects_backup_by_key_or_file_name() {
+        let expected = Some("db/db-2025-01-11_02-00-00.sql");
+
+        for key in [
+            "db/db-2025-01-11_02-00-00.sql",
+            "/db/db-2025-01-11_02-00-00.sql",
+            "db-2025-01-11_02-00-00.sql",
+        ] {
+            assert_eq!(select(BackupSelector::Key(key.to_string())).as_deref(), expected, "{}", key);
+        }
+
+        assert_eq!(select(BackupSelector::Key(String::from("db-2025-01-13_02-00-00.sql"))), None);
+        assert_eq!(select(BackupSelector::Key(String::from("other/db-2025-01-11_02-00-00.sql"))), None);
+    }
+
+    #[test]
+    fn selects_latest_backup_strictly_before_time() {
+        let before = |value| select(BackupSelector::Before(time(value)));
+
+        assert_eq!(before("2025-01-12T00:00:00+03:00").as_deref(), Some("db/db-2025-01-11_02-00-00.sql"));
+        assert_eq!(before("2025-01-11T02:00:00+03:00").as_deref(), Some("db/db-2025-01-10_02-00-00.sql"));
+        assert_eq!(before("2025-01-11T02:00:01+03:00").as_deref(), Some("db/db-2025-01-11_02-00-00.sql"));
+        assert_eq!(before("2025-01-10T02:00:00+03:00"), None);
+    }
+
+    #[test]
+    fn selects_backup_at_the_same_second_in_any_offset() {
+        let at = |value| select(BackupSelector::At(time(value)));
+
+        assert_eq!(at("2025-01-11T02:00:00+03:00").as_deref(), Some("db/db-2025-01-11_02-00-00.sql"));
+        assert_eq!(at("2025-01-10T23:00:00.500Z").as_deref(), Some("db/db-2025-01-11_02-00-00.sql"));
+        assert_eq!(at("2025-01-11T02:00:01+03:00"), None);
+    }
+
+    #[test]
+    fn local_storage_keeps_paths_below_the_root() {
+        let storage = LocalStorage {
+            root: PathBuf::from("/mnt/backups"),
+        };
+
+        assert_eq!(storage.path("db/dump.sql").unwrap(), Path::new("/mnt/backups/db/dump.sql"));
+        assert_eq!(storage.path("/db/dump.sql").unwrap(), Path::new("/mnt/backups/db/dump.sql"));
+        assert!(storage.path("db/../../etc/passwd").is_err());
+        assert!(storage.path("../db").is_err());
+    }
+
+    #[tokio::test]
+    async fn local_storage_uploads_lists_and_deletes_backups() {
+        let root = std::env::temp_dir().join(format!("reback-local-storage-{}", std::process::id()));
+        let source = root.join("db-2025-01-10_02-00-00.sql");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&source, "dump").unwrap();
+        let storage = LocalStorage {
+            root: root.join("target"),
+        };
+
+        storage.upload(&source, "db").await.unwrap();
+        let listed = storage.list("db").await.unwrap();
+        storage.delete(&listed[0].key).await.unwrap();
+        let remaining = storage.list("db").await.unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].key, "db/db-2025-01-10_02-00-00.sql");
+        assert_eq!(listed[0].size, Some(4));
+        assert!(remaining.is_empty());
+    }
+
+    /// Answers one S3 request with an empty listing and returns the head of the request.
+    fn serve_empty_listing(listener: TcpListener) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..read]);
+            }
+
+            let body = "<ListBucketResult><Name>backups</Name><IsTruncated>false</IsTruncated></ListBucketResult>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+
+            String::from_utf8_lossy(&request).to_string()
+        })
+    }
+
+    #[tokio::test]
+    async fn s3_storage_lists_for_pruning_with_prune_credentials() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings: Settings = serde_json::from_str(&format!(
+            r#"{{
+                "s3_endpoint": "http://{}",
+                "s3_region": "us-east-1",
+                "s3_bucket": "backups",
+                "s3_access": "default-access",
+                "s3_secret": "default-secret",
+                "s3_path_style": "path",
+                "s3_credentials": {{
+                    "upload": {{"s3_access": "upload-access", "s3_secret": "upload-secret"}},
+                    "prune": {{"s3_access": "prune-access", "s3_secret": "prune-secret"}}
+                }},
+                "backup_dir": "/tmp/reback",
+                "elements": []
+            }}"#,
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let element: Elements = serde_json::from_str(
+            r#"{
+                "element_title": "db",
+                "s3_folder": "db",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30,
+                "s3_credentials": "upload",
+                "s3_prune_credentials": "prune"
+            }"#,
+        )
+        .unwrap();
+        let bucket = settings.get_bucket().unwrap();
+        let storage = S3Storage {
+            settings: &settings,
+            bucket: &bucket,
+            element: &element,
+        };
+
+        let server = serve_empty_listing(listener);
+        let backups = storage.list_for_prune("db").await.unwrap();
+        let request = server.join().unwrap();
+
+        assert!(backups.is_empty());
+        assert!(request.contains("Credential=prune-access/"), "{}", request);
+    }
+}
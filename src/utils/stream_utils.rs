@@ -0,0 +1,335 @@
+use crate::structures::elements::{DumpCommand, Elements, BACKUP_TIMESTAMP_FORMAT};
+use crate::structures::settings::Settings;
+use crate::utils::pipeline_utils::StreamEncoder;
+use crate::utils::s3_utils::{get_available_s3_key, put_backup_checksum};
+use crate::utils::time_utils;
+use log::info;
+use s3::Bucket;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWriteExt, DuplexStream, ReadBuf};
+use tokio::runtime::Handle;
+
+/// The size of the in-memory buffer between the dump command and the S3 upload.
+const STREAM_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// Streams the dump of a database element directly into the S3 bucket, without an intermediate file.
+///
+/// The output of the dump command is compressed and encrypted the same way as by `encode_backup`
+/// (see `StreamEncoder`) while it is uploaded with a multipart upload. The backup is named like a regular
+/// backup (`element-title-YYYY-MM-DD_HH-MM-SS.extension`), so it is listed, restored and deleted by retention
+/// like any other backup. After the upload, the SHA-256 checksum of the uploaded data is recorded with
+/// `put_backup_checksum`.
+///
+/// The upload is only completed if the dump command has succeeded: the end of the stream is reported to
+/// `put_object_stream` as a read error instead if the command fails (see `DumpReader`). A failed dump therefore never
+/// becomes an S3 object (a started multipart upload is left incomplete), so no delete permission is needed to clean
+/// it up.
+///
+/// # Arguments
+/// - `settings` - The configuration containing the compression and encryption settings.
+/// - `bucket` - The S3 bucket (created with the credentials of the element) where the backup will be uploaded.
+/// - `element` - The database element to back up.
+/// - `dump` - The dump command of the element (see `Elements::get_dump_command`).
+///
+/// # Returns
+/// - `Ok(u64)` - The size of the uploaded backup.
+/// - `Err(String)` - An error message if any step fails.
+///
+/// # Example
+/// ```rust
+/// let dump = element.get_dump_command()?;
+/// let size = stream_backup_to_s3(&settings, &upload_bucket, &element, &dump).await?;
+/// ```
+pub async fn stream_backup_to_s3(
+    settings: &Settings,
+    bucket: &Bucket,
+    element: &Elements,
+    dump: &DumpCommand,
+) -> Result<u64, String> {
+    let now = time_utils::now().format(BACKUP_TIMESTAMP_FORMAT).to_string();
+    let file_name = format!("{}-{}.{}", element.element_title, now, dump.extension);
+    let encoder = StreamEncoder::new(settings, element, &file_name)?;
+    let s3_path = get_available_s3_key(bucket, &element.s3_folder, &encoder.file_name).await?;
+
+    info!("Streaming backup of {} to {}", element.element_title, s3_path);
+
+    let (writer, reader) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+    let succeeded = Arc::new(AtomicBool::new(false));
+    let mut reader = DumpReader {
+        reader,
+        succeeded: succeeded.clone(),
+    };
+    let runtime = Handle::current();
+    let dump = dump.clone();
+
+    // The dump command is run and waited for on a blocking thread, so it never blocks a runtime worker.
+    let dumping = tokio::task::spawn_blocking(move || {
+        let mut writer = UploadWriter { writer, runtime };
+        let result = run_dump(&dump, encoder, &mut writer);
+
+        // The result is recorded before the stream is closed, so `DumpReader` knows it when it reaches the end.
+        succeeded.store(result.is_ok(), Ordering::SeqCst);
+        drop(writer);
+
+        result
+    });
+
+    let upload = bucket.put_object_stream(&mut reader, s3_path.clone()).await;
+
+    // Closing the reader unblocks the encoder if the upload stopped before the end of the stream.
+    drop(reader);
+
+    let dumped = dumping
+        .await
+        .map_err(|e| format!("Failed to run the backup command: {}", e))
+        .and_then(|result| result);
+
+    let (size, checksum) = match (upload, dumped) {
+        (Ok(_), Ok(dumped)) => dumped,
+        (Err(e), Ok(_)) => return Err(format!("Failed to upload file to S3: {}", e)),
+        (Ok(_), Err(e)) => return Err(e),
+        (Err(upload_error), Err(e)) => return Err(format!("{} (S3 upload: {})", e, upload_error)),
+    };
+
+    info!("File uploaded successfully to {}", s3_path);
+    put_backup_checksum(bucket, &s3_path, &checksum).await;
+
+    Ok(size)
+}
+
+/// Runs a dump command and writes its encoded output into `writer`.
+///
+/// This function blocks until the command has exited, so it must run on a blocking thread.
+///
+/// # Arguments
+/// - `dump` - The dump command.
+/// - `encoder` - The compression and encryption of the output.
+/// - `writer` - The destination of the encoded output.
+///
+/// # Returns
+/// - `Ok((u64, String))` - The size and the SHA-256 checksum of the encoded output.
+/// - `Err(String)` - An error message if the command cannot be started, the output cannot be encoded or written,
+///   or the command fails.
+fn run_dump(dump: &DumpCommand, encoder: StreamEncoder, writer: impl Write) -> Result<(u64, String), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&dump.command)
+        .envs(dump.env.iter().cloned())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute backup command '{}': {}", dump.command, e))?;
+
+    let (Some(stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(String::from("Failed to capture the output of the backup command"));
+    };
+
+    // The error output is collected on a separate thread, so a verbose command cannot fill the pipe and stall.
+    let stderr_reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+
+    let mut writer = HashingWriter {
+        writer,
+        hasher: Sha256::new(),
+        size: 0,
+    };
+
+    // The output is closed when `encode` returns, so a command that is still writing exits with a broken pipe.
+    let encoded = encoder.encode(stdout, &mut writer);
+    let status = child.wait();
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    match (encoded, status) {
+        (Err(e), _) => Err(format!("Failed to encode the backup stream: {}", e)),
+        (_, Err(e)) => Err(format!("Failed to wait for the backup command: {}", e)),
+        (_, Ok(status)) if !status.success() => Err(format!("Backup failed! Error: {}", stderr.trim())),
+        (Ok(_), Ok(_)) => Ok((writer.size, format!("{:x}", writer.hasher.finalize()))),
+    }
+}
+
+/// Calculates the size and SHA-256 checksum of the data written into `writer`.
+struct HashingWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes the encoded backup stream into the S3 upload from a blocking thread.
+///
+/// # Fields
+/// - `writer` - The half of the in-memory pipe read by `put_object_stream`.
+/// - `runtime` - The handle of the runtime used to write into the pipe from a blocking thread.
+struct UploadWriter {
+    writer: DuplexStream,
+    runtime: Handle,
+}
+
+impl Write for UploadWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.runtime.block_on(self.writer.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.runtime.block_on(self.writer.flush())
+    }
+}
+
+/// Reads the encoded backup stream for the S3 upload.
+///
+/// The end of the stream is only passed on if the dump command has succeeded. Otherwise, it is turned into a read
+/// error, so `put_object_stream` stops before the object is completed.
+///
+/// # Fields
+/// - `reader` - The half of the in-memory pipe written by `UploadWriter`.
+/// - `succeeded` - Whether the dump command has succeeded, set before the pipe is closed.
+struct DumpReader {
+    reader: DuplexStream,
+    succeeded: Arc<AtomicBool>,
+}
+
+impl AsyncRead for DumpReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.reader).poll_read(cx, buf))?;
+
+        let at_end = buf.filled().len() == filled && buf.remaining() > 0;
+        if at_end && !self.succeeded.load(Ordering::SeqCst) {
+            return Poll::Ready(Err(io::Error::other("the backup command failed")));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder() -> StreamEncoder {
+        let settings: Settings = serde_json::from_str(
+            r#"{
+                "s3_endpoint": "http://localhost:9000",
+                "s3_region": "us-east-1",
+                "s3_bucket": "backups",
+                "s3_access": "access",
+                "s3_secret": "secret",
+                "s3_path_style": "path",
+                "backup_dir": "/tmp/reback",
+                "elements": []
+            }"#,
+        )
+        .unwrap();
+        let element: Elements = serde_json::from_str(
+            r#"{
+                "element_title": "db",
+                "s3_folder": "db",
+                "backup_retention_days": 7,
+                "s3_backup_retention_days": 30
+            }"#,
+        )
+        .unwrap();
+
+        StreamEncoder::new(&settings, &element, "db-2025-01-10_02-00-00.sql").unwrap()
+    }
+
+    fn dump(command: &str) -> DumpCommand {
+        DumpCommand {
+            command: command.to_string(),
+            env: vec![("REBACK_TEST_VALUE", String::from("dump"))],
+            extension: "sql",
+        }
+    }
+
+    #[test]
+    fn run_dump_writes_the_output_with_its_size_and_checksum() {
+        let mut output = Vec::new();
+
+        let (size, checksum) = run_dump(&dump("printf \"$REBACK_TEST_VALUE\""), encoder(), &mut output).unwrap();
+
+        assert_eq!(output, b"dump");
+        assert_eq!(size, 4);
+        assert_eq!(checksum, format!("{:x}", Sha256::digest(b"dump")));
+    }
+
+    #[test]
+    fn run_dump_fails_if_the_command_fails_after_writing_output() {
+        let mut output = Vec::new();
+
+        let result = run_dump(&dump("printf partial; echo 'connection lost' >&2; exit 3"), encoder(), &mut output);
+
+        assert_eq!(output, b"partial");
+        assert_eq!(result.unwrap_err(), "Backup failed! Error: connection lost");
+    }
+
+    #[test]
+    fn run_dump_fails_if_the_output_cannot_be_written() {
+        struct ClosedWriter;
+
+        impl Write for ClosedWriter {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "upload stopped"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let result = run_dump(&dump("yes"), encoder(), ClosedWriter);
+
+        assert!(result.unwrap_err().starts_with("Failed to encode the backup stream"));
+    }
+
+    async fn read_dump(succeeded: bool) -> io::Result<Vec<u8>> {
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let mut reader = DumpReader {
+            reader,
+            succeeded: Arc::new(AtomicBool::new(succeeded)),
+        };
+
+        writer.write_all(b"dump").await?;
+        drop(writer);
+
+        let mut output = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut output).await?;
+
+        Ok(output)
+    }
+
+    #[tokio::test]
+    async fn dump_reader_ends_the_stream_after_a_successful_dump() {
+        assert_eq!(read_dump(true).await.unwrap(), b"dump");
+    }
+
+    #[tokio::test]
+    async fn dump_reader_fails_the_stream_after_a_failed_dump() {
+        assert!(read_dump(false).await.is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::structures::elements::BACKUP_TIMESTAMP_FORMAT;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use std::sync::OnceLock;
+
+/// The timezone configured in the settings. If it is not set, the system timezone is used.
+static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Sets the timezone used for backup file names, schedules and retention.
+///
+/// # Arguments
+/// - `name` - The IANA name of the timezone (e.g., `Europe/Moscow`).
+///
+/// # Returns
+/// - `Ok(())` if the timezone is valid.
+/// - `Err(String)` if the timezone is unknown or has already been set.
+///
+/// # Example
+/// ```rust
+/// set_timezone("Europe/Moscow")?;
+/// ```
+pub fn set_timezone(name: &str) -> Result<(), String> {
+    let timezone: Tz = name
+        .parse()
+        .map_err(|_| format!("Unknown timezone: {}", name))?;
+
+    TIMEZONE
+        .set(timezone)
+        .map_err(|_| "Timezone is already set".to_string())
+}
+
+/// Returns the configured timezone, or `None` if the system timezone is used.
+pub fn get_timezone() -> Option<Tz> {
+    TIMEZONE.get().copied()
+}
+
+/// Returns the current time in the configured timezone.
+///
+/// # Example
+/// ```rust
+/// let timestamp = now().format(BACKUP_TIMESTAMP_FORMAT).to_string();
+/// ```
+pub fn now() -> DateTime<FixedOffset> {
+    to_configured(&Utc::now())
+}
+
+/// Converts a point in time to the configured timezone.
+///
+/// # Arguments
+/// - `date` - The point in time in any timezone.
+///
+/// # Returns
+/// The same point in time in the configured timezone.
+pub fn to_configured<T: TimeZone>(date: &DateTime<T>) -> DateTime<FixedOffset> {
+    match get_timezone() {
+        Some(timezone) => date.with_timezone(&timezone).fixed_offset(),
+        None => date.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+/// Interprets a date and time without timezone (e.g., from a backup file name) in the configured timezone.
+///
+/// # Arguments
+/// - `date` - The date and time without timezone.
+///
+/// # Returns
+/// - `Some(DateTime<FixedOffset>)` if the date and time exist in the configured timezone.
+/// - `None` if they are ambiguous or do not exist (e.g., during a DST transition).
+pub fn from_naive(date: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    match get_timezone() {
+        Some(timezone) => timezone
+            .from_local_datetime(date)
+            .single()
+            .map(|date| date.fixed_offset()),
+        None => Local
+            .from_local_datetime(date)
+            .single()
+            .map(|date| date.fixed_offset()),
+    }
+}
+
+/// Parses a date and time given on the command line.
+///
+/// RFC 3339 values (`2025-01-10T02:00:00+03:00`) keep their offset. Other values are interpreted in the configured
+/// timezone: `YYYY-MM-DD HH:MM:SS`, `YYYY-MM-DDTHH:MM:SS`, `YYYY-MM-DD HH:MM`, the timestamp of backup file names
+/// (`YYYY-MM-DD_HH-MM-SS`) and a date (`YYYY-MM-DD`, which means midnight).
+///
+/// # Arguments
+/// - `value` - The date and time string.
+///
+/// # Returns
+/// - `Ok(DateTime<FixedOffset>)` with the point in time in the configured timezone.
+/// - `Err(String)` if the value has none of the supported formats or does not exist in the configured timezone.
+///
+/// # Example
+/// ```rust
+/// let before = parse_datetime("2025-01-10 02:00")?;
+/// ```
+pub fn parse_datetime(value: &str) -> Result<DateTime<FixedOffset>, String> {
+    let value = value.trim();
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(to_configured(&date));
+    }
+
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", BACKUP_TIMESTAMP_FORMAT]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or(format!("Invalid date and time: {}", value))?;
+
+    from_naive(&naive).ok_or(format!("Date and time does not exist in the configured timezone: {}", value))
+}
+
+/// Parses a cron expression of an element schedule.
+///
+/// Standard five-field expressions (`minute hour day month weekday`) are supported, as well as expressions with
+/// a leading seconds field. Weekdays use the crontab numbering (`0` and `7` are Sunday) or names (`MON-FRI`).
+///
+/// # Arguments
+/// - `expression` - The cron expression (e.g., `0 3 * * *`).
+///
+/// # Returns
+/// - `Ok(Cron)` if the expression is valid.
+/// - `Err(String)` if the expression cannot be parsed.
+///
+/// # Example
+/// ```rust
+/// let schedule = parse_schedule("*/15 * * * *")?;
+/// ```
+pub fn parse_schedule(expression: &str) -> Result<Cron, String> {
+    Cron::new(expression.trim())
+        .with_seconds_optional()
+        .parse()
+        .map_err(|e| format!("Invalid cron expression '{}': {}", expression, e))
+}
+
+/// Returns the next run of a schedule after the current time in the configured timezone.
+///
+/// # Arguments
+/// - `schedule` - The parsed schedule.
+///
+/// # Returns
+/// - `Some(DateTime<FixedOffset>)` with the next run.
+/// - `None` if no next run can be found.
+pub fn get_next_run(schedule: &Cron) -> Option<DateTime<FixedOffset>> {
+    match get_timezone() {
+        Some(timezone) => schedule
+            .find_next_occurrence(&Utc::now().with_timezone(&timezone), false)
+            .ok()
+            .map(|date| date.fixed_offset()),
+        None => schedule
+            .find_next_occurrence(&Local::now(), false)
+            .ok()
+            .map(|date| date.fixed_offset()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn matches(expression: &str, value: &str) -> bool {
+        parse_schedule(expression).unwrap().is_time_matching(&at(value)).unwrap()
+    }
+
+    #[test]
+    fn parses_five_field_expressions() {
+        assert!(matches("0 3 * * *", "2024-12-08T03:00:00Z"));
+        assert!(!matches("0 3 * * *", "2024-12-08T03:01:00Z"));
+        assert!(matches(" */15 * * * * ", "2024-12-08T10:45:00Z"));
+
+        let next = parse_schedule("30 2 * * *")
+            .unwrap()
+            .find_next_occurrence(&at("2024-12-08T03:00:00Z"), false)
+            .unwrap();
+        assert_eq!(next, at("2024-12-09T02:30:00Z"));
+    }
+
+    #[test]
+    fn parses_optional_seconds_field() {
+        assert!(matches("30 0 3 * * *", "2024-12-08T03:00:30Z"));
+        assert!(!matches("30 0 3 * * *", "2024-12-08T03:00:00Z"));
+    }
+
+    #[test]
+    fn parses_crontab_weekdays() {
+        // 2024-12-08 is a Sunday, 2024-12-09 a Monday.
+        assert!(matches("0 0 * * 0", "2024-12-08T00:00:00Z"));
+        assert!(matches("0 0 * * 7", "2024-12-08T00:00:00Z"));
+        assert!(!matches("0 0 * * MON-FRI", "2024-12-08T00:00:00Z"));
+        assert!(matches("0 0 * * MON-FRI", "2024-12-09T00:00:00Z"));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in ["", "* * *", "61 * * * *", "0 25 * * *", "every day"] {
+            let error = parse_schedule(expression).unwrap_err();
+            assert!(error.starts_with("Invalid cron expression"), "{}", error);
+        }
+    }
+}